@@ -0,0 +1,53 @@
+use std::sync::{Arc, Condvar, Mutex};
+
+#[derive(Debug, Default)]
+struct ForkCounts {
+    live: usize,
+    peak: usize,
+}
+
+// Accounts the live RAM forks of a chain. Each fork mirrors the store, so once
+// `max_forks` of them are alive, new forks wait for one to be dropped. Forks of a
+// fork are not counted, they live as long as their parent anyway.
+#[derive(Debug)]
+pub struct ForkLimiter {
+    max_forks: usize,
+    counts: Mutex<ForkCounts>,
+    released: Condvar,
+}
+
+impl ForkLimiter {
+    pub fn new(max_forks: usize) -> Self {
+        Self {
+            max_forks: std::cmp::max(max_forks, 1),
+            counts: Mutex::new(ForkCounts::default()),
+            released: Condvar::new(),
+        }
+    }
+    pub fn acquire(self: &Arc<Self>) -> ForkGuard {
+        let mut counts = self.counts.lock().unwrap();
+        while counts.live >= self.max_forks {
+            counts = self.released.wait(counts).unwrap();
+        }
+        counts.live += 1;
+        counts.peak = std::cmp::max(counts.peak, counts.live);
+        ForkGuard(Arc::clone(self))
+    }
+    pub fn live(&self) -> usize {
+        self.counts.lock().unwrap().live
+    }
+    // Most forks ever alive at the same time
+    pub fn peak(&self) -> usize {
+        self.counts.lock().unwrap().peak
+    }
+}
+
+#[derive(Debug)]
+pub struct ForkGuard(Arc<ForkLimiter>);
+
+impl Drop for ForkGuard {
+    fn drop(&mut self) {
+        self.0.counts.lock().unwrap().live -= 1;
+        self.0.released.notify_one();
+    }
+}
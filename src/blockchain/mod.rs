@@ -1,10 +1,10 @@
 use thiserror::Error;
 
-use crate::config::blockchain::MPN_CONTRACT_ID;
+use crate::config::blockchain::{get_blockchain_config, MPN_CONTRACT_ID};
 use crate::core::{
     hash::Hash, Account, Address, Block, ContractAccount, ContractId, ContractPayment,
     ContractUpdate, Hasher, Header, Money, PaymentDirection, ProofOfWork, Signature, Transaction,
-    TransactionAndDelta, TransactionData, ZkHasher,
+    TransactionAndDelta, TransactionData, ZkHasher, HEADER_VERSION, MIN_HEADER_VERSION,
 };
 use crate::db::{KvStore, KvStoreError, RamMirrorKvStore, StringKey, WriteOp};
 use crate::utils;
@@ -15,24 +15,86 @@ use rayon::prelude::*;
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
+
+mod strategy;
+pub use strategy::*;
+
+mod fork_limiter;
+pub use fork_limiter::*;
 
 #[derive(Clone)]
 pub struct BlockchainConfig {
     pub genesis: BlockAndPatch,
+    // Peers of different networks (E.g mainnet/testnet) refuse to talk to each other
+    pub network_magic: u32,
     pub total_supply: u64,
     pub reward_ratio: u64,
+    // Bytes of the transactions of a block (Besides the miner reward)
+    pub max_block_size: usize,
+    // Growth of the contract states in a block
     pub max_delta_size: usize,
+    pub max_state_patch_size: usize,
+    pub max_contracts_per_block: usize,
+    pub max_proof_verifications_per_block: usize,
+    // Including the miner reward
+    pub max_txs_per_block: usize,
+    pub enforce_nonce_order: bool,
     pub block_time: usize,
     pub difficulty_calc_interval: u64,
     pub pow_base_key: &'static [u8],
     pub pow_key_change_delay: u64,
     pub pow_key_change_interval: u64,
     pub median_timestamp_count: u64,
+    // A block's timestamp may be at most this many seconds below its parent's
+    pub max_timestamp_regression: Option<u32>,
+    // Soft cap on the RAM forks of the chain alive at the same time (E.g. drafts
+    // and validations), the ones beyond it wait for the others to finish
+    pub max_ram_forks: usize,
+    // Blocks are not required to meet their difficulty target (Dev networks only),
+    // a chain started with PoW can't be reopened with it disabled
+    pub pow_enabled: bool,
     pub mpn_num_function_calls: usize,
     pub mpn_num_deposit_withdraws: usize,
+    // Headers up to a trusted checkpoint are not checked against their PoW target,
+    // which speeds up the initial sync
+    pub checkpoint: Option<Checkpoint>,
+    pub fee_split: FeeSplit,
+    // Don't draft reward-only blocks when there is nothing to include
+    pub allow_empty_blocks: bool,
+}
+
+// Shares of the fees collected in a block (In basis points). The miner's share is
+// paid through the reward transaction, the Treasury's share is credited back to it
+// and the rest is burnt.
+#[derive(Clone, Debug)]
+pub struct FeeSplit {
+    pub miner_bps: u64,
+    pub treasury_bps: u64,
+    pub burn_bps: u64,
 }
 
-#[derive(Debug, Clone)]
+impl FeeSplit {
+    pub fn is_valid(&self) -> bool {
+        self.miner_bps + self.treasury_bps + self.burn_bps == 10000
+    }
+    // Miner, Treasury and burnt shares of the fees. Rounding leftovers are burnt.
+    pub fn split(&self, fees: Money) -> (Money, Money, Money) {
+        let miner = (fees as u128 * self.miner_bps as u128 / 10000) as Money;
+        let treasury = (fees as u128 * self.treasury_bps as u128 / 10000) as Money;
+        (miner, treasury, fees - miner - treasury)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    pub height: u64,
+    pub hash: <Hasher as Hash>::Output,
+    // Power of the chain up to and including the checkpoint block
+    pub power: u128,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionStats {
     pub first_seen: u32,
 }
@@ -59,6 +121,10 @@ pub enum BlockchainError {
     InvalidBlockNumber,
     #[error("parent hash invalid")]
     InvalidParentHash,
+    #[error("block does not match the trusted checkpoint")]
+    CheckpointMismatch,
+    #[error("cannot fork the chain below the trusted checkpoint")]
+    ForkBelowCheckpoint,
     #[error("merkle root invalid")]
     InvalidMerkleRoot,
     #[error("transaction nonce invalid")]
@@ -75,6 +141,8 @@ pub enum BlockchainError {
     IllegalTreasuryAccess,
     #[error("miner reward transaction is invalid")]
     InvalidMinerReward,
+    #[error("block has more than one transaction from the treasury")]
+    MultipleCoinbase,
     #[error("contract not found")]
     ContractNotFound,
     #[error("update function not found in the given contract")]
@@ -91,6 +159,14 @@ pub enum BlockchainError {
     StatesUnavailable,
     #[error("block too big")]
     BlockTooBig,
+    #[error("block grows the contract states too much")]
+    DeltaTooBig,
+    #[error("block has too many transactions")]
+    TooManyTransactions,
+    #[error("no transactions to include in the block")]
+    NothingToMine,
+    #[error("fee split shares don't add up to 10000 basis points")]
+    InvalidFeeSplit,
     #[error("compressed-state at specified height not found")]
     CompressedStateNotFound,
     #[error("full-state has invalid deltas")]
@@ -105,6 +181,30 @@ pub enum BlockchainError {
     InvalidDepositWithdrawSignature,
     #[error("insufficient mpn updates")]
     InsufficientMpnUpdates,
+    #[error("only the contract owner can update it")]
+    Unauthorized,
+    #[error("contract is paused")]
+    ContractPaused,
+    #[error("too many contracts created in a block")]
+    TooManyContracts,
+    #[error("proof verification budget of the block exceeded")]
+    ProofBudgetExceeded,
+    #[error("state patch too big")]
+    StatePatchTooBig,
+    #[error("transactions of a sender are not in nonce order")]
+    UnorderedNonces,
+    #[error("declared initial state doesn't match the provided state")]
+    InitialStateMismatch,
+    #[error("extending the chain didn't increase its power")]
+    PowerNotIncreased,
+    #[error("cannot disable PoW on a chain started with PoW")]
+    PowCannotBeDisabled,
+    #[error("genesis block can only be applied on an empty chain")]
+    UnexpectedGenesis,
+    #[error("database was created by an incompatible version (db version {0:?})")]
+    IncompatibleDatabase(Option<u32>),
+    #[error("header version {0} is not supported")]
+    UnsupportedHeaderVersion(u32),
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -112,7 +212,7 @@ pub struct ZkBlockchainPatch {
     pub patches: HashMap<ContractId, zk::ZkStatePatch>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ZkCompressedStateChange {
     prev_state: zk::ZkCompressedState,
     state: zk::ZkCompressedState,
@@ -125,6 +225,7 @@ pub struct BlockAndPatch {
     pub patch: ZkBlockchainPatch,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum TxSideEffect {
     StateChange {
         contract_id: ContractId,
@@ -133,7 +234,13 @@ pub enum TxSideEffect {
     Nothing,
 }
 
+// Owns a snapshot of the raw accounts it decodes, so that it can outlive the
+// lock on the chain
+pub type AccountIterator =
+    Box<dyn Iterator<Item = Result<(Address, Account), BlockchainError>> + Send>;
+
 pub trait Blockchain {
+    fn config(&self) -> &BlockchainConfig;
     fn cleanup_mempool(
         &self,
         mempool: &mut HashMap<TransactionAndDelta, TransactionStats>,
@@ -147,6 +254,14 @@ pub trait Blockchain {
     fn validate_transaction(&self, tx_delta: &TransactionAndDelta)
         -> Result<bool, BlockchainError>;
     fn get_account(&self, addr: Address) -> Result<Account, BlockchainError>;
+    fn account_exists(&self, addr: Address) -> Result<bool, BlockchainError>;
+    // A snapshot of every stored account, sorted by the address. The raw pairs
+    // are all loaded (and sorted) up front, only the decoding is done one
+    // account at a time, as the iterator is consumed
+    fn iter_accounts(&self) -> Result<AccountIterator, BlockchainError>;
+    fn all_accounts(&self) -> Result<Vec<(Address, Account)>, BlockchainError> {
+        self.iter_accounts()?.collect()
+    }
     fn get_contract_account(
         &self,
         contract_id: ContractId,
@@ -167,14 +282,61 @@ pub trait Blockchain {
         wallet: &Wallet,
         check: bool,
     ) -> Result<Option<BlockAndPatch>, BlockchainError>;
+    fn draft_block_with_strategy(
+        &self,
+        timestamp: u32,
+        mempool: &HashMap<TransactionAndDelta, TransactionStats>,
+        wallet: &Wallet,
+        check: bool,
+        strategy: &dyn MempoolStrategy,
+    ) -> Result<Option<BlockAndPatch>, BlockchainError>;
     fn get_height(&self) -> Result<u64, BlockchainError>;
     fn get_tip(&self) -> Result<Header, BlockchainError>;
     fn get_headers(&self, since: u64, until: Option<u64>) -> Result<Vec<Header>, BlockchainError>;
+    // Unlike `get_headers`, a bad header doesn't discard the ones before it. The
+    // headers up to the first failure are returned along with the failure.
+    fn get_headers_lenient(
+        &self,
+        since: u64,
+        until: Option<u64>,
+    ) -> Result<(Vec<Header>, Option<BlockchainError>), BlockchainError>;
     fn get_blocks(&self, since: u64, until: Option<u64>) -> Result<Vec<Block>, BlockchainError>;
+    // Blocks are read one at a time, as the iterator is consumed
+    fn stream_blocks(
+        &self,
+        since: u64,
+        until: Option<u64>,
+    ) -> Result<Box<dyn Iterator<Item = Result<Block, BlockchainError>> + '_>, BlockchainError>;
     fn get_power(&self) -> Result<u128, BlockchainError>;
+    // Height of the block a transaction is confirmed in, along with the transaction
+    fn get_transaction(
+        &self,
+        hash: <Hasher as Hash>::Output,
+    ) -> Result<Option<(u64, Transaction)>, BlockchainError>;
+    // Fraction of the last `window` blocks with a header version of at least `min_version`
+    fn version_bits_support(&self, min_version: u32, window: u64) -> Result<f64, BlockchainError>;
+    // Average time between the last `window` blocks, relative to the target block time.
+    // None when there are not enough blocks yet.
+    fn block_time_ratio(&self, window: u64) -> Result<Option<f64>, BlockchainError>;
     fn pow_key(&self, index: u64) -> Result<Vec<u8>, BlockchainError>;
 
     fn get_contract(&self, contract_id: ContractId) -> Result<zk::ZkContract, BlockchainError>;
+    // Contracts created without an owner are open to everyone
+    fn get_contract_owner(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<Option<Address>, BlockchainError>;
+    // Paused contracts don't accept updates until their owner resumes them
+    fn is_contract_paused(&self, contract_id: ContractId) -> Result<bool, BlockchainError>;
+    // Value of a single cell in the current state of a contract
+    fn get_contract_value(
+        &self,
+        contract_id: ContractId,
+        locator: &zk::ZkDataLocator,
+    ) -> Result<zk::ZkScalar, BlockchainError>;
+    // Bytes occupied by a contract in the database (Keys and values), including
+    // its full state, rollback data and the history of its compressed-states
+    fn contract_storage_size(&self, contract_id: ContractId) -> Result<usize, BlockchainError>;
 
     fn get_outdated_contracts(&self) -> Result<Vec<ContractId>, BlockchainError>;
 
@@ -185,30 +347,209 @@ pub trait Blockchain {
         to: <Hasher as Hash>::Output,
     ) -> Result<ZkBlockchainPatch, BlockchainError>;
     fn update_states(&mut self, patch: &ZkBlockchainPatch) -> Result<(), BlockchainError>;
+    // Blocks don't carry the initial states of the contracts they create, those
+    // arrive in the patch of the block and are checked against the declared ones
+    fn check_initial_states(
+        &self,
+        block: &Block,
+        patch: &ZkBlockchainPatch,
+    ) -> Result<(), BlockchainError>;
+    // Unlike `update_states`, outdated contracts missing from the patch are left
+    // outdated instead of failing the update.
+    fn update_states_partial(&mut self, patch: &ZkBlockchainPatch) -> Result<(), BlockchainError>;
+    // Database writes (and side effect) of a transaction, without committing them
+    fn trace_tx(&self, tx: &Transaction) -> Result<(Vec<WriteOp>, TxSideEffect), BlockchainError>;
+    // Database writes which undo the block at the given height
+    fn get_rollback_ops(&self, height: u64) -> Result<Vec<WriteOp>, BlockchainError>;
+}
+
+// Bumped whenever the layout of the database changes. Databases without the
+// marker have decimal height-indexed keys and can't be read anymore. Version 1
+// databases don't keep the running total of the burnt fees, version 2 databases
+// store headers without the version field.
+const DB_VERSION: u32 = 3;
+
+// Height-indexed keys are fixed-width hex, so that their lexicographic order
+// matches the numeric order over the whole u64 range.
+fn height_key(prefix: &str, height: u64) -> StringKey {
+    format!("{}_{:016x}", prefix, height).into()
+}
+
+fn tx_key(hash: &<Hasher as Hash>::Output) -> StringKey {
+    format!("tx_{}", hex::encode(hash)).into()
+}
+
+// Deposits/withdrawals neither create nor destroy money, the contract balance changes
+// exactly by the net amount paid by its initiators
+fn check_dw_conservation(
+    deposit_withdraws: &[ContractPayment],
+    contract_delta: i128,
+    initiators_delta: i128,
+) -> Result<(), BlockchainError> {
+    let net = deposit_withdraws
+        .iter()
+        .map(|dw| match dw.direction {
+            PaymentDirection::Deposit(_) => dw.amount as i128,
+            PaymentDirection::Withdraw(_) => -(dw.amount as i128),
+        })
+        .sum::<i128>();
+    if contract_delta != net || initiators_delta != -net {
+        return Err(BlockchainError::Inconsistency);
+    }
+    Ok(())
+}
+
+// Number of zero-knowledge proofs verified when applying the transaction
+fn num_proof_verifications(tx: &Transaction) -> usize {
+    match &tx.data {
+        TransactionData::UpdateContract { updates, .. } => updates.len(),
+        _ => 0,
+    }
 }
 
 pub struct KvStoreChain<K: KvStore> {
     config: BlockchainConfig,
     database: K,
+    forks: Arc<ForkLimiter>,
+    // Shared by a fork and the forks made of it
+    fork_guard: Option<Arc<ForkGuard>>,
 }
 
 impl<K: KvStore> KvStoreChain<K> {
     pub fn new(database: K, config: BlockchainConfig) -> Result<KvStoreChain<K>, BlockchainError> {
+        if !config.fee_split.is_valid() {
+            return Err(BlockchainError::InvalidFeeSplit);
+        }
+        // The mainnet is secured by PoW, even when started from scratch
+        if !config.pow_enabled
+            && config.genesis.block.header.hash()
+                == get_blockchain_config().genesis.block.header.hash()
+        {
+            return Err(BlockchainError::PowCannotBeDisabled);
+        }
         let mut chain = KvStoreChain::<K> {
             database,
             config: config.clone(),
+            forks: Arc::new(ForkLimiter::new(config.max_ram_forks)),
+            fork_guard: None,
         };
         if chain.get_height()? == 0 {
+            Self::verify_genesis(&config.genesis.block)?;
             chain.apply_block(&config.genesis.block, true)?;
             chain.update_states(&config.genesis.patch)?;
+        } else {
+            let db_version: Option<u32> = match chain.database.get("db_version".into())? {
+                Some(v) => Some(v.try_into()?),
+                None => None,
+            };
+            if db_version != Some(DB_VERSION) {
+                return Err(BlockchainError::IncompatibleDatabase(db_version));
+            }
+            // Chains started before the flag existed had PoW enabled
+            let started_with_pow: bool = match chain.database.get("pow_enabled".into())? {
+                Some(v) => v.try_into()?,
+                None => true,
+            };
+            if started_with_pow && !config.pow_enabled {
+                return Err(BlockchainError::PowCannotBeDisabled);
+            }
+            chain.recover_outdated_contracts()?;
         }
         Ok(chain)
     }
 
-    fn fork_on_ram(&self) -> KvStoreChain<RamMirrorKvStore<'_, K>> {
+    // Genesis is exempt from PoW and the header checks of `apply_block`, so a
+    // hand-constructed genesis is verified here instead. The mainnet genesis was
+    // created without a merkle root, an unset root is accepted for that reason.
+    fn verify_genesis(genesis: &Block) -> Result<(), BlockchainError> {
+        if genesis.header.number != 0 {
+            return Err(BlockchainError::InvalidBlockNumber);
+        }
+        if genesis.header.parent_hash != <Hasher as Hash>::Output::default() {
+            return Err(BlockchainError::InvalidParentHash);
+        }
+        if genesis.header.block_root != <Hasher as Hash>::Output::default()
+            && genesis.merkle_tree().root() != genesis.header.block_root
+        {
+            return Err(BlockchainError::InvalidMerkleRoot);
+        }
+        Ok(())
+    }
+
+    // A missing "outdated" key might be the result of a corrupted database. Compare
+    // the compressed-states of the contract accounts with the states we actually have
+    // and reconstruct the list, so that nothing is built on top of unavailable states.
+    fn recover_outdated_contracts(&mut self) -> Result<(), BlockchainError> {
+        if self.database.get("outdated".into())?.is_some() {
+            return Ok(());
+        }
+        let mut outdated_contracts = Vec::new();
+        for (k, v) in self.database.pairs("contract_account_".into())? {
+            let cid: ContractId = k.0["contract_account_".len()..]
+                .parse()
+                .map_err(|_| BlockchainError::Inconsistency)?;
+            let contract_account: ContractAccount = v.try_into()?;
+            if zk::KvStoreStateManager::<ZkHasher>::root(&self.database, cid)?
+                != contract_account.compressed_state
+            {
+                outdated_contracts.push(cid);
+            }
+        }
+        if !outdated_contracts.is_empty() {
+            self.database
+                .update(&[WriteOp::Put("outdated".into(), outdated_contracts.into())])?;
+        }
+        Ok(())
+    }
+
+    // Money held by accounts and contracts. The burnt share of the transaction fees
+    // is gone, so the circulating supply plus the burnt fees should always match
+    // what has been taken out of the Treasury.
+    pub fn total_circulating(&self) -> Result<Money, BlockchainError> {
+        let mut circulating: Money = 0;
+        let mut treasury = self.config.total_supply;
+        for entry in self.iter_accounts()? {
+            let (addr, acc) = entry?;
+            if addr == Address::Treasury {
+                treasury = acc.balance;
+            } else {
+                circulating += acc.balance;
+            }
+        }
+        for (_, v) in self.database.pairs("contract_account_".into())? {
+            let contract_account: ContractAccount = v.try_into()?;
+            circulating += contract_account.balance;
+        }
+        let burnt = self.get_burnt()?;
+        if circulating + burnt + treasury != self.config.total_supply {
+            return Err(BlockchainError::Inconsistency);
+        }
+        Ok(circulating)
+    }
+
+    // Running total of the burnt fees, kept up to date by `apply_block` (And
+    // undone by the rollback ops of each block)
+    fn get_burnt(&self) -> Result<Money, BlockchainError> {
+        Ok(match self.database.get("burnt".into())? {
+            Some(b) => b.try_into()?,
+            None => 0,
+        })
+    }
+
+    pub fn forks(&self) -> &ForkLimiter {
+        &self.forks
+    }
+
+    pub(crate) fn fork_on_ram(&self) -> KvStoreChain<RamMirrorKvStore<'_, K>> {
+        let fork_guard = match &self.fork_guard {
+            Some(guard) => Arc::clone(guard),
+            None => Arc::new(self.forks.acquire()),
+        };
         KvStoreChain {
             database: self.database.mirror(),
             config: self.config.clone(),
+            forks: Arc::clone(&self.forks),
+            fork_guard: Some(fork_guard),
         }
     }
 
@@ -221,6 +562,26 @@ impl<K: KvStore> KvStoreChain<K> {
         Ok((mirror.database.to_ops(), result))
     }
 
+    // Once the checkpoint block is applied, the chain before it is final
+    fn check_checkpoint_fork(&self, from: u64) -> Result<(), BlockchainError> {
+        if let Some(checkpoint) = &self.config.checkpoint {
+            if from <= checkpoint.height && self.get_height()? > checkpoint.height {
+                return Err(BlockchainError::ForkBelowCheckpoint);
+            }
+        }
+        Ok(())
+    }
+
+    // Headers up to the checkpoint are only trusted when the batch reaches the
+    // checkpoint, so that they get pinned down by its hash (Through the linkage)
+    fn trusted_until(&self, last: Option<&Header>) -> Option<u64> {
+        self.config
+            .checkpoint
+            .as_ref()
+            .filter(|c| last.map(|h| h.number >= c.height).unwrap_or(false))
+            .map(|c| c.height)
+    }
+
     fn median_timestamp(&self, index: u64) -> Result<u32, BlockchainError> {
         Ok(utils::median(
             &(0..std::cmp::min(index + 1, self.config.median_timestamp_count))
@@ -248,11 +609,11 @@ impl<K: KvStore> KvStoreChain<K> {
         }
     }
 
-    fn get_block(&self, index: u64) -> Result<Block, BlockchainError> {
+    pub(crate) fn get_block(&self, index: u64) -> Result<Block, BlockchainError> {
         if index >= self.get_height()? {
             return Err(BlockchainError::BlockNotFound);
         }
-        let block_key: StringKey = format!("block_{:010}", index).into();
+        let block_key = height_key("block", index);
         Ok(match self.database.get(block_key)? {
             Some(b) => b.try_into()?,
             None => {
@@ -283,11 +644,11 @@ impl<K: KvStore> KvStoreChain<K> {
         })
     }
 
-    fn get_header(&self, index: u64) -> Result<Header, BlockchainError> {
+    pub(crate) fn get_header(&self, index: u64) -> Result<Header, BlockchainError> {
         if index >= self.get_height()? {
             return Err(BlockchainError::BlockNotFound);
         }
-        let header_key: StringKey = format!("header_{:010}", index).into();
+        let header_key = height_key("header", index);
         Ok(match self.database.get(header_key)? {
             Some(b) => b.try_into()?,
             None => {
@@ -342,6 +703,12 @@ impl<K: KvStore> KvStoreChain<K> {
             acc_src.balance -= tx.fee;
             acc_src.nonce += 1;
 
+            // Deposits/withdrawals of the transaction, checked against the balances
+            // once all of its writes are done
+            let mut dw_payments = Vec::new();
+            let mut dw_contract_delta: i128 = 0;
+            let mut dw_initiators: HashMap<Address, Money> = HashMap::new();
+
             match &tx.data {
                 TransactionData::RegularSend { dst, amount } => {
                     if acc_src.balance < *amount {
@@ -360,18 +727,26 @@ impl<K: KvStore> KvStoreChain<K> {
                         )])?;
                     }
                 }
-                TransactionData::CreateContract { contract } => {
+                TransactionData::CreateContract { contract }
+                | TransactionData::CreateOwnedContract { contract, .. } => {
                     let contract_id = ContractId::new(tx);
                     chain.database.update(&[WriteOp::Put(
                         format!("contract_{}", contract_id).into(),
-                        contract.clone().into(),
+                        (**contract).clone().into(),
                     )])?;
+                    if let TransactionData::CreateOwnedContract { owner, .. } = &tx.data {
+                        chain.database.update(&[WriteOp::Put(
+                            format!("contract_owner_{}", contract_id).into(),
+                            owner.clone().into(),
+                        )])?;
+                    }
                     let compressed_empty =
                         zk::ZkCompressedState::empty::<ZkHasher>(contract.state_model.clone());
                     chain.database.update(&[WriteOp::Put(
                         format!("contract_account_{}", contract_id).into(),
                         ContractAccount {
                             compressed_state: contract.initial_state,
+                            byte_size: contract.initial_state.byte_size(),
                             balance: 0,
                             height: 1,
                             nonce: 0,
@@ -391,12 +766,35 @@ impl<K: KvStore> KvStoreChain<K> {
                         },
                     };
                 }
+                TransactionData::PauseContract {
+                    contract_id,
+                    paused,
+                } => {
+                    // Contracts without an owner can't be paused
+                    if chain.get_contract_owner(*contract_id)?.as_ref() != Some(&tx.src) {
+                        return Err(BlockchainError::Unauthorized);
+                    }
+                    chain.database.update(&[WriteOp::Put(
+                        format!("contract_paused_{}", contract_id).into(),
+                        (*paused).into(),
+                    )])?;
+                }
                 TransactionData::UpdateContract {
                     contract_id,
                     updates,
                 } => {
                     let contract = chain.get_contract(*contract_id)?;
 
+                    if let Some(owner) = chain.get_contract_owner(*contract_id)? {
+                        if tx.src != owner {
+                            return Err(BlockchainError::Unauthorized);
+                        }
+                    }
+
+                    if chain.is_contract_paused(*contract_id)? {
+                        return Err(BlockchainError::ContractPaused);
+                    }
+
                     for update in updates {
                         let prev_account = chain.get_contract_account(*contract_id)?;
                         let mut new_account = prev_account.clone();
@@ -427,8 +825,20 @@ impl<K: KvStore> KvStoreChain<K> {
                                         zk::ZkScalar::from(dw.amount),
                                     )?;
 
-                                    let mut addr_account = chain
-                                        .get_account(Address::PublicKey(dw.address.clone()))?;
+                                    // The account of the sender is written once the whole
+                                    // transaction is applied, its own payments (And its
+                                    // balance, after the fee) are taken from `acc_src`
+                                    let initiator = Address::PublicKey(dw.address.clone());
+                                    let is_src = initiator == tx.src;
+                                    let mut addr_account = if is_src {
+                                        acc_src.clone()
+                                    } else {
+                                        chain.get_account(initiator.clone())?
+                                    };
+                                    // Balances of the initiators, before the payments
+                                    dw_initiators
+                                        .entry(initiator.clone())
+                                        .or_insert(addr_account.balance);
                                     match &dw.direction {
                                         PaymentDirection::Deposit(_) => {
                                             if addr_account.nonce != dw.nonce {
@@ -462,14 +872,14 @@ impl<K: KvStore> KvStoreChain<K> {
                                         }
                                     }
 
-                                    chain.database.update(&[WriteOp::Put(
-                                        format!(
-                                            "account_{}",
-                                            Address::PublicKey(dw.address.clone())
-                                        )
-                                        .into(),
-                                        addr_account.into(),
-                                    )])?;
+                                    if is_src {
+                                        acc_src = addr_account;
+                                    } else {
+                                        chain.database.update(&[WriteOp::Put(
+                                            format!("account_{}", initiator).into(),
+                                            addr_account.into(),
+                                        )])?;
+                                    }
 
                                     if !dw.verify_signature() {
                                         return Err(
@@ -477,6 +887,9 @@ impl<K: KvStore> KvStoreChain<K> {
                                         );
                                     }
                                 }
+                                dw_payments.extend(deposit_withdraws.iter().cloned());
+                                dw_contract_delta +=
+                                    new_account.balance as i128 - prev_account.balance as i128;
                                 let _aux_data = state_builder.compress()?;
                                 let aux_data = zk::ZkCompressedState::default();
                                 (circuit, aux_data, next_state, proof)
@@ -490,7 +903,7 @@ impl<K: KvStore> KvStoreChain<K> {
                                     .functions
                                     .get(*function_id as usize)
                                     .ok_or(BlockchainError::ContractFunctionNotFound)?;
-                                let aux_data = zk::ZkCompressedState::default();
+                                let aux_data = zk::function_call_aux_data(*function_id);
                                 (circuit, aux_data, next_state, proof)
                             }
                         };
@@ -506,6 +919,7 @@ impl<K: KvStore> KvStoreChain<K> {
                         }
 
                         new_account.compressed_state = *next_state;
+                        new_account.byte_size = next_state.byte_size();
 
                         chain.database.update(&[WriteOp::Put(
                             format!("contract_account_{}", contract_id).into(),
@@ -536,6 +950,12 @@ impl<K: KvStore> KvStoreChain<K> {
                 acc_src.into(),
             )])?;
 
+            let mut initiators_delta: i128 = 0;
+            for (addr, before) in dw_initiators {
+                initiators_delta += chain.get_account(addr)?.balance as i128 - before as i128;
+            }
+            check_dw_conservation(&dw_payments, dw_contract_delta, initiators_delta)?;
+
             Ok(side_effect)
         })?;
 
@@ -547,7 +967,7 @@ impl<K: KvStore> KvStoreChain<K> {
         &self,
         index: u64,
     ) -> Result<HashMap<ContractId, ZkCompressedStateChange>, BlockchainError> {
-        let k = format!("contract_updates_{:010}", index).into();
+        let k = height_key("contract_updates", index);
         Ok(self
             .database
             .get(k)?
@@ -555,32 +975,77 @@ impl<K: KvStore> KvStoreChain<K> {
             .ok_or(BlockchainError::Inconsistency)??)
     }
 
+    // `apply_tx` only sees the compressed initial state of a new contract, so its
+    // full initial state is checked against it wherever the delta is available.
+    fn check_initial_state(tx_delta: &TransactionAndDelta) -> Result<(), BlockchainError> {
+        if let TransactionData::CreateContract { contract }
+        | TransactionData::CreateOwnedContract { contract, .. } = &tx_delta.tx.data
+        {
+            let mut builder = zk::ZkStateBuilder::<ZkHasher>::new(contract.state_model.clone());
+            builder.batch_set(&tx_delta.state_delta.clone().unwrap_or_default())?;
+            if builder.compress()? != contract.initial_state {
+                return Err(BlockchainError::InitialStateMismatch);
+            }
+        }
+        Ok(())
+    }
+
     fn select_transactions(
         &self,
         txs: &HashMap<TransactionAndDelta, TransactionStats>,
         check: bool,
+        strategy: &dyn MempoolStrategy,
     ) -> Result<Vec<TransactionAndDelta>, BlockchainError> {
-        let mut sorted = txs.keys().cloned().collect::<Vec<_>>();
-        sorted.sort_by_key(|tx| {
-            let is_mpn = if let TransactionData::UpdateContract { contract_id, .. } = &tx.tx.data {
-                *contract_id == *MPN_CONTRACT_ID
-            } else {
-                false
-            };
-            (is_mpn, tx.tx.nonce)
-        });
+        let sorted = strategy.order(txs);
+        // Leave enough of the proof budget for the mandatory MPN updates
+        let mpn_reserved_proofs =
+            self.config.mpn_num_function_calls + self.config.mpn_num_deposit_withdraws;
         let (_, result) = self.isolated(|chain| {
             let mut result = Vec::new();
-            let mut sz = 0isize;
+            let mut body_sz = 0usize;
+            let mut delta_sz = 0isize;
+            let mut num_contracts = 0;
+            let mut num_proofs = 0;
             for tx in sorted.into_iter() {
-                let delta =
-                    tx.tx.size() as isize + tx.state_delta.clone().unwrap_or_default().size();
+                let tx_sz = tx.tx.size();
+                let tx_delta_sz = tx.state_delta.clone().unwrap_or_default().size();
+                let creates_contract = matches!(
+                    tx.tx.data,
+                    TransactionData::CreateContract { .. }
+                        | TransactionData::CreateOwnedContract { .. }
+                );
+                let tx_proofs = num_proof_verifications(&tx.tx);
+                let proof_budget = match &tx.tx.data {
+                    TransactionData::UpdateContract { contract_id, .. }
+                        if *contract_id == *MPN_CONTRACT_ID =>
+                    {
+                        chain.config.max_proof_verifications_per_block
+                    }
+                    _ => chain
+                        .config
+                        .max_proof_verifications_per_block
+                        .saturating_sub(mpn_reserved_proofs),
+                };
+                // The miner reward takes one of the slots
+                if check && result.len() + 1 >= chain.config.max_txs_per_block {
+                    break;
+                }
                 if !check
-                    || (sz + delta <= chain.config.max_delta_size as isize
+                    || (body_sz + tx_sz <= chain.config.max_block_size
+                        && delta_sz + tx_delta_sz <= chain.config.max_delta_size as isize
+                        && (!creates_contract
+                            || num_contracts < chain.config.max_contracts_per_block)
+                        && num_proofs + tx_proofs <= proof_budget
                         && tx.tx.verify_signature()
+                        && Self::check_initial_state(&tx).is_ok()
                         && chain.apply_tx(&tx.tx, false).is_ok())
                 {
-                    sz += delta;
+                    body_sz += tx_sz;
+                    delta_sz += tx_delta_sz;
+                    if creates_contract {
+                        num_contracts += 1;
+                    }
+                    num_proofs += tx_proofs;
                     result.push(tx);
                 }
             }
@@ -589,17 +1054,141 @@ impl<K: KvStore> KvStoreChain<K> {
         Ok(result)
     }
 
-    fn apply_block(&mut self, block: &Block, check_pow: bool) -> Result<(), BlockchainError> {
+    pub(crate) fn apply_block(
+        &mut self,
+        block: &Block,
+        check_pow: bool,
+    ) -> Result<(), BlockchainError> {
+        self.apply_block_checked(block, check_pow, true)
+    }
+
+    // Outdated contracts are processed in id order, the ones missing from the patch
+    // fail the update unless `allow_missing`
+    fn apply_state_patch(
+        &mut self,
+        patch: &ZkBlockchainPatch,
+        allow_missing: bool,
+    ) -> Result<(), BlockchainError> {
+        // Reject huge patches before writing anything
+        if patch.patches.values().map(|p| p.size()).sum::<usize>()
+            > self.config.max_state_patch_size
+        {
+            return Err(BlockchainError::StatePatchTooBig);
+        }
+
+        let (ops, _) = self.isolated(|chain| {
+            let mut outdated_contracts = chain.get_outdated_contracts()?;
+
+            // Process the contracts in a fixed order, so that failures are reproducible
+            let mut to_update = outdated_contracts.clone();
+            to_update.sort_by_key(|cid| cid.to_string());
+
+            for cid in to_update {
+                let contract_account = chain.get_contract_account(cid)?;
+                let patch = match patch.patches.get(&cid) {
+                    Some(patch) => patch,
+                    None if allow_missing => {
+                        continue;
+                    }
+                    None => {
+                        return Err(BlockchainError::FullStateNotFound);
+                    }
+                };
+                let reassembled;
+                let full = match &patch {
+                    zk::ZkStatePatch::Full(full) => Some(full),
+                    zk::ZkStatePatch::Chunks(chunks) => {
+                        reassembled = zk::ZkState::from_chunks(chunks)
+                            .ok_or(BlockchainError::FullStateNotValid)?;
+                        Some(&reassembled)
+                    }
+                    zk::ZkStatePatch::Delta(delta) => {
+                        zk::KvStoreStateManager::<ZkHasher>::update_contract(
+                            &mut chain.database,
+                            cid,
+                            delta,
+                        )?;
+                        None
+                    }
+                };
+                if let Some(full) = full {
+                    let (_, rollback_results) =
+                        zk::KvStoreStateManager::<ZkHasher>::reset_contract(
+                            &mut chain.database,
+                            cid,
+                            contract_account.height,
+                            full,
+                        )?;
+                    for (i, rollback_result) in rollback_results.into_iter().enumerate() {
+                        if rollback_result
+                            != self.get_compressed_state_at(
+                                cid,
+                                contract_account.height - 1 - i as u64,
+                            )?
+                        {
+                            return Err(BlockchainError::DeltasInvalid);
+                        }
+                    }
+                }
+
+                if zk::KvStoreStateManager::<ZkHasher>::root(&chain.database, cid)?
+                    != contract_account.compressed_state
+                {
+                    return Err(BlockchainError::FullStateNotValid);
+                }
+                outdated_contracts.retain(|&x| x != cid);
+            }
+
+            chain.database.update(&[if outdated_contracts.is_empty() {
+                WriteOp::Remove("outdated".into())
+            } else {
+                WriteOp::Put("outdated".into(), outdated_contracts.clone().into())
+            }])?;
+
+            Ok(())
+        })?;
+        self.database.update(&ops)?;
+        Ok(())
+    }
+
+    // Skipping signature verification is only allowed for blocks which are
+    // already known to be valid (E.g. blocks below a trusted checkpoint).
+    fn apply_block_checked(
+        &mut self,
+        block: &Block,
+        check_pow: bool,
+        verify_sigs: bool,
+    ) -> Result<(), BlockchainError> {
         let (ops, _) = self.isolated(|chain| {
             let curr_height = chain.get_height()?;
-            let is_genesis = block.header.number == 0;
             let next_reward = chain.next_reward()?;
+            let (miner_fees, treasury_fees, burnt_fees) = chain
+                .config
+                .fee_split
+                .split(block.body.iter().skip(1).map(|tx| tx.fee).sum());
+
+            // Genesis is the only block applied on an empty chain. It is exempt from
+            // PoW (And the other header checks), has no miner reward and its
+            // transactions are allowed to draw from the Treasury.
+            let is_genesis = curr_height == 0;
+            if is_genesis && block.header.number != 0 {
+                return Err(BlockchainError::InvalidBlockNumber);
+            }
+            if !is_genesis && block.header.number == 0 {
+                return Err(BlockchainError::UnexpectedGenesis);
+            }
 
-            if curr_height > 0 {
-                if block.merkle_tree().root() != block.header.block_root {
-                    return Err(BlockchainError::InvalidMerkleRoot);
-                }
+            // Cheap to check, rejects blocks with absurdly long bodies before any
+            // per-transaction work (Hashing, signature verification, ...)
+            if !is_genesis && block.body.len() > self.config.max_txs_per_block {
+                return Err(BlockchainError::TooManyTransactions);
+            }
+
+            if !is_genesis && block.merkle_tree().root() != block.header.block_root {
+                return Err(BlockchainError::InvalidMerkleRoot);
+            }
 
+            if !is_genesis {
                 chain.will_extend(curr_height, &[block.header.clone()], check_pow)?;
             }
 
@@ -618,7 +1207,7 @@ impl<K: KvStore> KvStoreChain<K> {
                 }
                 match reward_tx.data {
                     TransactionData::RegularSend { dst: _, amount } => {
-                        if amount != next_reward {
+                        if amount != next_reward + miner_fees {
                             return Err(BlockchainError::InvalidMinerReward);
                         }
                     }
@@ -627,6 +1216,11 @@ impl<K: KvStore> KvStoreChain<K> {
                     }
                 }
 
+                // The reward is the only transaction sourced from the Treasury
+                if block.body[1..].iter().any(|tx| tx.src == Address::Treasury) {
+                    return Err(BlockchainError::MultipleCoinbase);
+                }
+
                 // Reward tx allowed to get money from Treasury
                 chain.apply_tx(reward_tx, true)?;
                 &block.body[1..]
@@ -634,68 +1228,155 @@ impl<K: KvStore> KvStoreChain<K> {
                 &block.body[..]
             };
 
-            let mut body_size = 0usize;
-            let mut state_size_delta = 0isize;
             let mut state_updates: HashMap<ContractId, ZkCompressedStateChange> = HashMap::new();
             let mut outdated_contracts = self.get_outdated_contracts()?;
 
-            if !txs.par_iter().all(|tx| tx.verify_signature()) {
-                return Err(BlockchainError::SignatureError);
-            }
+            // Fast path for reward-only blocks, common on an idle network
+            if txs.is_empty() {
+                if !is_genesis
+                    && (self.config.mpn_num_function_calls > 0
+                        || self.config.mpn_num_deposit_withdraws > 0)
+                {
+                    return Err(BlockchainError::InsufficientMpnUpdates);
+                }
+            } else {
+                let mut body_size = 0usize;
+                let mut state_size_delta = 0isize;
 
-            let mut num_mpn_function_calls = 0;
-            let mut num_mpn_deposit_withdraws = 0;
+                if verify_sigs && !txs.par_iter().all(|tx| tx.verify_signature()) {
+                    return Err(BlockchainError::SignatureError);
+                }
 
-            for tx in txs.iter() {
-                // Count MPN updates
-                if let TransactionData::UpdateContract {
-                    contract_id,
-                    updates,
-                } = &tx.data
+                // Genesis block is allowed to create as many contracts as it wants
+                if !is_genesis
+                    && txs
+                        .iter()
+                        .filter(|tx| {
+                            matches!(
+                                tx.data,
+                                TransactionData::CreateContract { .. }
+                                    | TransactionData::CreateOwnedContract { .. }
+                            )
+                        })
+                        .count()
+                        > self.config.max_contracts_per_block
                 {
-                    if *contract_id == *MPN_CONTRACT_ID {
-                        for update in updates.iter() {
-                            match update {
-                                ContractUpdate::DepositWithdraw { .. } => {
-                                    num_mpn_deposit_withdraws += 1;
-                                }
-                                ContractUpdate::FunctionCall { .. } => {
-                                    num_mpn_function_calls += 1;
+                    return Err(BlockchainError::TooManyContracts);
+                }
+
+                if txs.iter().map(num_proof_verifications).sum::<usize>()
+                    > self.config.max_proof_verifications_per_block
+                {
+                    return Err(BlockchainError::ProofBudgetExceeded);
+                }
+
+                // Transactions of each sender should appear in strictly increasing nonce order
+                if !is_genesis && self.config.enforce_nonce_order {
+                    let mut last_nonces: HashMap<&Address, u32> = HashMap::new();
+                    for tx in txs.iter() {
+                        if let Some(last_nonce) = last_nonces.insert(&tx.src, tx.nonce) {
+                            if tx.nonce <= last_nonce {
+                                return Err(BlockchainError::UnorderedNonces);
+                            }
+                        }
+                    }
+                }
+
+                let mut num_mpn_function_calls = 0;
+                let mut num_mpn_deposit_withdraws = 0;
+
+                for tx in txs.iter() {
+                    // Count MPN updates
+                    if let TransactionData::UpdateContract {
+                        contract_id,
+                        updates,
+                    } = &tx.data
+                    {
+                        if *contract_id == *MPN_CONTRACT_ID {
+                            for update in updates.iter() {
+                                match update {
+                                    ContractUpdate::DepositWithdraw { .. } => {
+                                        num_mpn_deposit_withdraws += 1;
+                                    }
+                                    ContractUpdate::FunctionCall { .. } => {
+                                        num_mpn_function_calls += 1;
+                                    }
                                 }
                             }
                         }
                     }
+
+                    body_size += tx.size();
+                    // All genesis block txs are allowed to get from Treasury
+                    if let TxSideEffect::StateChange {
+                        contract_id,
+                        state_change,
+                    } = chain.apply_tx(tx, is_genesis)?
+                    {
+                        state_size_delta += state_change.state.size() as isize
+                            - state_change.prev_state.size() as isize;
+                        state_updates.insert(contract_id, state_change.clone());
+                        outdated_contracts.push(contract_id);
+                    }
                 }
 
-                body_size += tx.size();
-                // All genesis block txs are allowed to get from Treasury
-                if let TxSideEffect::StateChange {
-                    contract_id,
-                    state_change,
-                } = chain.apply_tx(tx, is_genesis)?
+                if !is_genesis
+                    && (num_mpn_function_calls < self.config.mpn_num_function_calls
+                        || num_mpn_deposit_withdraws < self.config.mpn_num_deposit_withdraws)
                 {
-                    state_size_delta += state_change.state.size() as isize
-                        - state_change.prev_state.size() as isize;
-                    state_updates.insert(contract_id, state_change.clone());
-                    outdated_contracts.push(contract_id);
+                    return Err(BlockchainError::InsufficientMpnUpdates);
+                }
+
+                if body_size > self.config.max_block_size {
+                    return Err(BlockchainError::BlockTooBig);
+                }
+                if state_size_delta > self.config.max_delta_size as isize {
+                    return Err(BlockchainError::DeltaTooBig);
                 }
             }
 
-            if !is_genesis
-                && (num_mpn_function_calls < self.config.mpn_num_function_calls
-                    || num_mpn_deposit_withdraws < self.config.mpn_num_deposit_withdraws)
-            {
-                return Err(BlockchainError::InsufficientMpnUpdates);
+            // The Treasury has already paid the miner's share of the fees, through the
+            // reward transaction
+            if !is_genesis && miner_fees + treasury_fees > 0 {
+                let mut treasury = chain.get_account(Address::Treasury)?;
+                treasury.balance += miner_fees + treasury_fees;
+                chain.database.update(&[WriteOp::Put(
+                    format!("account_{}", Address::Treasury).into(),
+                    treasury.into(),
+                )])?;
             }
 
-            if (body_size as isize + state_size_delta) as usize > self.config.max_delta_size {
-                return Err(BlockchainError::BlockTooBig);
+            // Confirmed transactions can be looked up by their hash
+            chain.database.update(
+                &block
+                    .body
+                    .iter()
+                    .enumerate()
+                    .map(|(i, tx)| {
+                        WriteOp::Put(tx_key(&tx.hash()), (block.header.number, i as u32).into())
+                    })
+                    .collect::<Vec<_>>(),
+            )?;
+
+            if is_genesis {
+                chain.database.update(&[
+                    WriteOp::Put("pow_enabled".into(), self.config.pow_enabled.into()),
+                    WriteOp::Put("db_version".into(), DB_VERSION.into()),
+                ])?;
             }
 
+            // Fees of the genesis block are not distributed
+            let burnt_fees = if is_genesis {
+                block.body.iter().map(|tx| tx.fee).sum::<Money>()
+            } else {
+                burnt_fees
+            };
+
             chain.database.update(&[
                 WriteOp::Put("height".into(), (curr_height + 1).into()),
+                WriteOp::Put("burnt".into(), (chain.get_burnt()? + burnt_fees).into()),
                 WriteOp::Put(
-                    format!("power_{:010}", block.header.number).into(),
+                    height_key("power", block.header.number),
                     (block.header.power() + self.get_power()?).into(),
                 ),
             ])?;
@@ -703,24 +1384,18 @@ impl<K: KvStore> KvStoreChain<K> {
             let rollback = chain.database.rollback()?;
 
             chain.database.update(&[
+                WriteOp::Put(height_key("rollback", block.header.number), rollback.into()),
                 WriteOp::Put(
-                    format!("rollback_{:010}", block.header.number).into(),
-                    rollback.into(),
-                ),
-                WriteOp::Put(
-                    format!("header_{:010}", block.header.number).into(),
+                    height_key("header", block.header.number),
                     block.header.clone().into(),
                 ),
+                WriteOp::Put(height_key("block", block.header.number), block.into()),
                 WriteOp::Put(
-                    format!("block_{:010}", block.header.number).into(),
-                    block.into(),
-                ),
-                WriteOp::Put(
-                    format!("merkle_{:010}", block.header.number).into(),
+                    height_key("merkle", block.header.number),
                     block.merkle_tree().into(),
                 ),
                 WriteOp::Put(
-                    format!("contract_updates_{:010}", block.header.number).into(),
+                    height_key("contract_updates", block.header.number),
                     state_updates.into(),
                 ),
                 if outdated_contracts.is_empty() {
@@ -739,6 +1414,23 @@ impl<K: KvStore> KvStoreChain<K> {
 }
 
 impl<K: KvStore> Blockchain for KvStoreChain<K> {
+    fn config(&self) -> &BlockchainConfig {
+        &self.config
+    }
+    fn trace_tx(&self, tx: &Transaction) -> Result<(Vec<WriteOp>, TxSideEffect), BlockchainError> {
+        self.isolated(|chain| chain.apply_tx(tx, false))
+    }
+    fn get_rollback_ops(&self, height: u64) -> Result<Vec<WriteOp>, BlockchainError> {
+        if height >= self.get_height()? {
+            return Err(BlockchainError::BlockNotFound);
+        }
+        Ok(match self.database.get(height_key("rollback", height))? {
+            Some(b) => b.try_into()?,
+            None => {
+                return Err(BlockchainError::Inconsistency);
+            }
+        })
+    }
     fn rollback(&mut self) -> Result<(), BlockchainError> {
         let (ops, _) = self.isolated(|chain| {
             let height = chain.get_height()?;
@@ -747,13 +1439,8 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
                 return Err(BlockchainError::NoBlocksToRollback);
             }
 
-            let rollback_key: StringKey = format!("rollback_{:010}", height - 1).into();
-            let rollback: Vec<WriteOp> = match chain.database.get(rollback_key.clone())? {
-                Some(b) => b.try_into()?,
-                None => {
-                    return Err(BlockchainError::Inconsistency);
-                }
-            };
+            let rollback_key = height_key("rollback", height - 1);
+            let rollback = chain.get_rollback_ops(height - 1)?;
 
             let mut outdated = chain.get_outdated_contracts()?;
             let changed_states = chain.get_changed_states(height - 1)?;
@@ -794,10 +1481,10 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
                 } else {
                     WriteOp::Put("outdated".into(), outdated.clone().into())
                 },
-                WriteOp::Remove(format!("header_{:010}", height - 1).into()),
-                WriteOp::Remove(format!("block_{:010}", height - 1).into()),
-                WriteOp::Remove(format!("merkle_{:010}", height - 1).into()),
-                WriteOp::Remove(format!("contract_updates_{:010}", height - 1).into()),
+                WriteOp::Remove(height_key("header", height - 1)),
+                WriteOp::Remove(height_key("block", height - 1)),
+                WriteOp::Remove(height_key("merkle", height - 1)),
+                WriteOp::Remove(height_key("contract_updates", height - 1)),
                 WriteOp::Remove(rollback_key),
             ])?;
 
@@ -839,6 +1526,64 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
             .map(|b| b.try_into())
             .ok_or(BlockchainError::ContractNotFound)??)
     }
+    fn get_contract_owner(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<Option<Address>, BlockchainError> {
+        // Makes sure the contract exists
+        self.get_contract(contract_id)?;
+        let k = format!("contract_owner_{}", contract_id).into();
+        Ok(match self.database.get(k)? {
+            Some(b) => Some(b.try_into()?),
+            None => None,
+        })
+    }
+    fn is_contract_paused(&self, contract_id: ContractId) -> Result<bool, BlockchainError> {
+        // Makes sure the contract exists
+        self.get_contract(contract_id)?;
+        let k = format!("contract_paused_{}", contract_id).into();
+        Ok(match self.database.get(k)? {
+            Some(b) => b.try_into()?,
+            None => false,
+        })
+    }
+    fn get_contract_value(
+        &self,
+        contract_id: ContractId,
+        locator: &zk::ZkDataLocator,
+    ) -> Result<zk::ZkScalar, BlockchainError> {
+        // Makes sure the contract exists
+        self.get_contract(contract_id)?;
+        if self.get_outdated_contracts()?.contains(&contract_id) {
+            return Err(BlockchainError::StatesUnavailable);
+        }
+        // Locators not fitting the state-model of the contract are rejected here
+        Ok(zk::KvStoreStateManager::<ZkHasher>::get_data(
+            &self.database,
+            contract_id,
+            locator,
+        )?)
+    }
+    fn contract_storage_size(&self, contract_id: ContractId) -> Result<usize, BlockchainError> {
+        // Makes sure the contract exists
+        self.get_contract(contract_id)?;
+        let prefixes = [
+            format!("contract_{}", contract_id),
+            format!("contract_account_{}", contract_id),
+            format!("contract_owner_{}", contract_id),
+            format!("contract_paused_{}", contract_id),
+            format!("contract_compressed_state_{}_", contract_id),
+            // Full state and rollbacks, kept by the state manager
+            format!("{}_", contract_id),
+        ];
+        let mut size = 0;
+        for prefix in prefixes {
+            for (k, v) in self.database.pairs(prefix.into())? {
+                size += k.0.len() + v.len();
+            }
+        }
+        Ok(size)
+    }
     fn get_contract_account(
         &self,
         contract_id: ContractId,
@@ -851,6 +1596,25 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
             .ok_or(BlockchainError::ContractNotFound)??)
     }
 
+    fn iter_accounts(&self) -> Result<AccountIterator, BlockchainError> {
+        let mut pairs = self
+            .database
+            .pairs("account_".into())?
+            .into_iter()
+            .collect::<Vec<_>>();
+        pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(Box::new(pairs.into_iter().map(|(k, v)| {
+            let addr = match &k.0["account_".len()..] {
+                "Treasury" => Address::Treasury,
+                s => s.parse().map_err(|_| BlockchainError::Inconsistency)?,
+            };
+            Ok((addr, v.try_into()?))
+        })))
+    }
+    fn account_exists(&self, addr: Address) -> Result<bool, BlockchainError> {
+        let k = format!("account_{}", addr).into();
+        Ok(self.database.get(k)?.is_some())
+    }
     fn get_account(&self, addr: Address) -> Result<Account, BlockchainError> {
         let k = format!("account_{}", addr).into();
         Ok(match self.database.get(k)? {
@@ -879,10 +1643,12 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
         } else if from > self.get_height()? {
             return Err(BlockchainError::ExtendFromFuture);
         }
+        self.check_checkpoint_fork(from)?;
+        let trusted_until = self.trusted_until(headers.last());
 
         let mut new_power: u128 = self
             .database
-            .get(format!("power_{:010}", from - 1).into())?
+            .get(height_key("power", from - 1))?
             .ok_or(BlockchainError::Inconsistency)?
             .try_into()?;
 
@@ -894,6 +1660,10 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
             .proof_of_work;
 
         for h in headers.iter() {
+            if h.version < MIN_HEADER_VERSION {
+                return Err(BlockchainError::UnsupportedHeaderVersion(h.version));
+            }
+
             if h.number % self.config.difficulty_calc_interval == 0 {
                 if h.proof_of_work.target
                     != utils::calc_pow_difficulty(
@@ -908,17 +1678,29 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
                 last_pow = h.proof_of_work;
             }
 
-            let pow_key = self.pow_key(h.number)?;
-
             if h.proof_of_work.timestamp < self.median_timestamp(from - 1)? {
                 return Err(BlockchainError::InvalidTimestamp);
             }
+            if let Some(max_regression) = self.config.max_timestamp_regression {
+                if h.proof_of_work.timestamp.saturating_add(max_regression)
+                    < last_header.proof_of_work.timestamp
+                {
+                    return Err(BlockchainError::InvalidTimestamp);
+                }
+            }
 
             if last_pow.target != h.proof_of_work.target {
                 return Err(BlockchainError::DifficultyTargetWrong);
             }
 
-            if check_pow && !h.meets_target(&pow_key) {
+            // Linkage is still verified below the checkpoint, so the headers are
+            // pinned down by the checkpoint hash.
+            let trusted = trusted_until.map(|c| h.number <= c).unwrap_or(false);
+            if check_pow
+                && self.config.pow_enabled
+                && !trusted
+                && !h.meets_target(&self.pow_key(h.number)?)
+            {
                 return Err(BlockchainError::DifficultyTargetUnmet);
             }
 
@@ -932,6 +1714,14 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
 
             last_header = h.clone();
             new_power += h.power();
+
+            if let Some(checkpoint) = &self.config.checkpoint {
+                if h.number == checkpoint.height
+                    && (h.hash() != checkpoint.hash || new_power != checkpoint.power)
+                {
+                    return Err(BlockchainError::CheckpointMismatch);
+                }
+            }
         }
 
         Ok(new_power > current_power)
@@ -939,19 +1729,54 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
     fn extend(&mut self, from: u64, blocks: &[Block]) -> Result<(), BlockchainError> {
         let (ops, _) = self.isolated(|chain| {
             let curr_height = chain.get_height()?;
+            let curr_power = chain.get_power()?;
 
             if from == 0 {
                 return Err(BlockchainError::ExtendFromGenesis);
             } else if from > curr_height {
                 return Err(BlockchainError::ExtendFromFuture);
             }
+            chain.check_checkpoint_fork(from)?;
+            let trusted_until = chain.trusted_until(blocks.last().map(|b| &b.header));
+
+            // Blocks already on the chain (E.g. the common part of the forks) should
+            // reproduce the same contract updates when re-applied, otherwise the state
+            // transition is nondeterministic
+            let mut prior_updates = HashMap::new();
+            for index in from..curr_height {
+                prior_updates.insert(
+                    index,
+                    (
+                        chain.get_header(index)?.hash(),
+                        chain.get_changed_states(index)?,
+                    ),
+                );
+            }
 
             while chain.get_height()? > from {
                 chain.rollback()?;
             }
 
             for block in blocks.iter() {
-                chain.apply_block(block, true)?;
+                let trusted = trusted_until
+                    .map(|c| block.header.number <= c)
+                    .unwrap_or(false);
+                // Blocks pinned down by the checkpoint are known to be valid, their
+                // PoW and signatures are not checked again
+                chain.apply_block_checked(block, !trusted, !trusted)?;
+                if let Some((hash, updates)) = prior_updates.get(&block.header.number) {
+                    if *hash == block.header.hash()
+                        && *updates != chain.get_changed_states(block.header.number)?
+                    {
+                        return Err(BlockchainError::Inconsistency);
+                    }
+                }
+            }
+
+            // The chain might have changed since `will_extend` was checked, or the
+            // peer might have lied about its power
+            if chain.get_power()? <= curr_power {
+                return Err(BlockchainError::PowerNotIncreased);
             }
 
             Ok(())
@@ -966,6 +1791,31 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
             None => 0,
         })
     }
+    fn version_bits_support(&self, min_version: u32, window: u64) -> Result<f64, BlockchainError> {
+        let height = self.get_height()?;
+        let headers = self.get_headers(height.saturating_sub(window), None)?;
+        if headers.is_empty() {
+            return Ok(0.0);
+        }
+        let signaling = headers.iter().filter(|h| h.version >= min_version).count();
+        Ok(signaling as f64 / headers.len() as f64)
+    }
+    fn block_time_ratio(&self, window: u64) -> Result<Option<f64>, BlockchainError> {
+        let height = self.get_height()?;
+        // Timestamp of the genesis block is arbitrary
+        let headers = self.get_headers(height.saturating_sub(window).max(1), None)?;
+        if let (Some(first), Some(last)) = (headers.first(), headers.last()) {
+            if headers.len() >= 2 {
+                let elapsed = last
+                    .proof_of_work
+                    .timestamp
+                    .saturating_sub(first.proof_of_work.timestamp);
+                let avg = elapsed as f64 / (headers.len() - 1) as f64;
+                return Ok(Some(avg / self.config.block_time as f64));
+            }
+        }
+        Ok(None)
+    }
     fn get_headers(&self, since: u64, until: Option<u64>) -> Result<Vec<Header>, BlockchainError> {
         let mut blks: Vec<Header> = Vec::new();
         let height = self.get_height()?;
@@ -977,16 +1827,35 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
         }
         Ok(blks)
     }
-    fn get_blocks(&self, since: u64, until: Option<u64>) -> Result<Vec<Block>, BlockchainError> {
-        let mut blks: Vec<Block> = Vec::new();
+    fn get_headers_lenient(
+        &self,
+        since: u64,
+        until: Option<u64>,
+    ) -> Result<(Vec<Header>, Option<BlockchainError>), BlockchainError> {
+        let mut headers: Vec<Header> = Vec::new();
         let height = self.get_height()?;
-        for i in since..until.unwrap_or(height) {
-            if i >= height {
-                break;
+        for i in since..until.unwrap_or(height).min(height) {
+            match self.get_header(i) {
+                Ok(header) => headers.push(header),
+                Err(e) => {
+                    return Ok((headers, Some(e)));
+                }
             }
-            blks.push(self.get_block(i)?);
         }
-        Ok(blks)
+        Ok((headers, None))
+    }
+    fn get_blocks(&self, since: u64, until: Option<u64>) -> Result<Vec<Block>, BlockchainError> {
+        self.stream_blocks(since, until)?.collect()
+    }
+    fn stream_blocks(
+        &self,
+        since: u64,
+        until: Option<u64>,
+    ) -> Result<Box<dyn Iterator<Item = Result<Block, BlockchainError>> + '_>, BlockchainError>
+    {
+        let height = self.get_height()?;
+        let until = std::cmp::min(until.unwrap_or(height), height);
+        Ok(Box::new((since..until).map(|i| self.get_block(i))))
     }
     fn next_reward(&self) -> Result<Money, BlockchainError> {
         let supply = self.get_account(Address::Treasury)?.balance;
@@ -998,33 +1867,61 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
         mempool: &HashMap<TransactionAndDelta, TransactionStats>,
         wallet: &Wallet,
         check: bool,
+    ) -> Result<Option<BlockAndPatch>, BlockchainError> {
+        self.draft_block_with_strategy(timestamp, mempool, wallet, check, &FifoStrategy)
+    }
+    fn draft_block_with_strategy(
+        &self,
+        timestamp: u32,
+        mempool: &HashMap<TransactionAndDelta, TransactionStats>,
+        wallet: &Wallet,
+        check: bool,
+        strategy: &dyn MempoolStrategy,
     ) -> Result<Option<BlockAndPatch>, BlockchainError> {
         let height = self.get_height()?;
         let outdated_contracts = self.get_outdated_contracts()?;
 
-        if !outdated_contracts.is_empty() {
-            return Err(BlockchainError::StatesOutdated);
-        }
-
         let last_header = self.get_header(height - 1)?;
         let treasury_nonce = self.get_account(Address::Treasury)?.nonce;
 
-        let mut txs = vec![Transaction {
-            src: Address::Treasury,
-            data: TransactionData::RegularSend {
-                dst: wallet.get_address(),
-                amount: self.next_reward()?,
-            },
-            nonce: treasury_nonce + 1,
-            fee: 0,
-            sig: Signature::Unsigned,
-        }];
+        // Drafting is possible while some states are outdated, the transactions
+        // needing those states are just left in the mempool
+        let needs_outdated_state = |tx_delta: &TransactionAndDelta| {
+            matches!(&tx_delta.tx.data, TransactionData::UpdateContract { contract_id, .. }
+                if outdated_contracts.contains(contract_id))
+        };
+        let usable_mempool: HashMap<TransactionAndDelta, TransactionStats>;
+        let mempool = if mempool.keys().any(needs_outdated_state) {
+            usable_mempool = mempool
+                .iter()
+                .filter(|(tx_delta, _)| !needs_outdated_state(tx_delta))
+                .map(|(tx_delta, stats)| (tx_delta.clone(), stats.clone()))
+                .collect();
+            &usable_mempool
+        } else {
+            mempool
+        };
+
+        // Nothing to select from on an idle network, a reward-only block is drafted
+        let tx_and_deltas = if mempool.is_empty() {
+            if self.config.mpn_num_function_calls > 0 || self.config.mpn_num_deposit_withdraws > 0 {
+                return Ok(None);
+            }
+            Vec::new()
+        } else {
+            self.select_transactions(mempool, check, strategy)?
+        };
+        if tx_and_deltas.is_empty() && !self.config.allow_empty_blocks {
+            return Err(BlockchainError::NothingToMine);
+        }
 
-        let tx_and_deltas = self.select_transactions(mempool, check)?;
         let mut block_delta: HashMap<ContractId, zk::ZkStatePatch> = HashMap::new();
         for tx_delta in tx_and_deltas.iter() {
             if let Some(contract_id) = match &tx_delta.tx.data {
-                TransactionData::CreateContract { .. } => Some(ContractId::new(&tx_delta.tx)),
+                TransactionData::CreateContract { .. }
+                | TransactionData::CreateOwnedContract { .. } => {
+                    Some(ContractId::new(&tx_delta.tx))
+                }
                 TransactionData::UpdateContract { contract_id, .. } => Some(*contract_id),
                 _ => None,
             } {
@@ -1043,10 +1940,25 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
             patches: block_delta,
         };
 
+        let (miner_fees, _, _) = self
+            .config
+            .fee_split
+            .split(tx_and_deltas.iter().map(|tp| tp.tx.fee).sum());
+        let mut txs = vec![Transaction {
+            src: Address::Treasury,
+            data: TransactionData::RegularSend {
+                dst: wallet.get_address(),
+                amount: self.next_reward()? + miner_fees,
+            },
+            nonce: treasury_nonce + 1,
+            fee: 0,
+            sig: Signature::Unsigned,
+        }];
         txs.extend(tx_and_deltas.iter().map(|tp| tp.tx.clone()));
 
         let mut blk = Block {
             header: Header {
+                version: HEADER_VERSION,
                 parent_hash: last_header.hash(),
                 number: height as u64,
                 block_root: Default::default(),
@@ -1062,7 +1974,7 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
 
         match self.isolated(|chain| {
             chain.apply_block(&blk, false)?; // Check if everything is ok
-            chain.update_states(&block_delta)?;
+            chain.update_states_partial(&block_delta)?;
 
             Ok(())
         }) {
@@ -1082,12 +1994,31 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
         } else {
             Ok(self
                 .database
-                .get(format!("power_{:010}", height - 1).into())?
+                .get(height_key("power", height - 1))?
                 .ok_or(BlockchainError::Inconsistency)?
                 .try_into()?)
         }
     }
 
+    fn get_transaction(
+        &self,
+        hash: <Hasher as Hash>::Output,
+    ) -> Result<Option<(u64, Transaction)>, BlockchainError> {
+        let (number, index): (u64, u32) = match self.database.get(tx_key(&hash))? {
+            Some(b) => b.try_into()?,
+            None => {
+                return Ok(None);
+            }
+        };
+        let tx = self
+            .get_block(number)?
+            .body
+            .get(index as usize)
+            .cloned()
+            .ok_or(BlockchainError::Inconsistency)?;
+        Ok(Some((number, tx)))
+    }
+
     fn pow_key(&self, index: u64) -> Result<Vec<u8>, BlockchainError> {
         Ok(if index < self.config.pow_key_change_delay {
             self.config.pow_base_key.to_vec()
@@ -1100,64 +2031,54 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
     }
 
     fn update_states(&mut self, patch: &ZkBlockchainPatch) -> Result<(), BlockchainError> {
-        let (ops, _) = self.isolated(|chain| {
-            let mut outdated_contracts = chain.get_outdated_contracts()?;
-
-            for cid in outdated_contracts.clone() {
-                let contract_account = chain.get_contract_account(cid)?;
-                let patch = patch
-                    .patches
-                    .get(&cid)
-                    .ok_or(BlockchainError::FullStateNotFound)?;
-                match &patch {
-                    zk::ZkStatePatch::Full(full) => {
-                        let (_, rollback_results) =
-                            zk::KvStoreStateManager::<ZkHasher>::reset_contract(
-                                &mut chain.database,
-                                cid,
-                                contract_account.height,
-                                full,
-                            )?;
-                        for (i, rollback_result) in rollback_results.into_iter().enumerate() {
-                            if rollback_result
-                                != self.get_compressed_state_at(
-                                    cid,
-                                    contract_account.height - 1 - i as u64,
-                                )?
-                            {
-                                return Err(BlockchainError::DeltasInvalid);
-                            }
-                        }
-                    }
-                    zk::ZkStatePatch::Delta(delta) => {
-                        zk::KvStoreStateManager::<ZkHasher>::update_contract(
-                            &mut chain.database,
-                            cid,
-                            delta,
-                        )?;
-                    }
-                };
+        self.apply_state_patch(patch, false)
+    }
 
-                if zk::KvStoreStateManager::<ZkHasher>::root(&chain.database, cid)?
-                    != contract_account.compressed_state
-                {
-                    return Err(BlockchainError::FullStateNotValid);
-                }
-                outdated_contracts.retain(|&x| x != cid);
+    fn check_initial_states(
+        &self,
+        block: &Block,
+        patch: &ZkBlockchainPatch,
+    ) -> Result<(), BlockchainError> {
+        for tx in block.body.iter() {
+            if !matches!(
+                tx.data,
+                TransactionData::CreateContract { .. }
+                    | TransactionData::CreateOwnedContract { .. }
+            ) {
+                continue;
             }
-
-            chain.database.update(&[if outdated_contracts.is_empty() {
-                WriteOp::Remove("outdated".into())
-            } else {
-                WriteOp::Put("outdated".into(), outdated_contracts.clone().into())
-            }])?;
-
-            Ok(())
-        })?;
-        self.database.update(&ops)?;
+            let cid = ContractId::new(tx);
+            // The patch has the state after the whole block, which is not the
+            // initial state anymore when the block also updates the contract
+            if block.body.iter().any(|t| {
+                matches!(&t.data, TransactionData::UpdateContract { contract_id, .. }
+                    if *contract_id == cid)
+            }) {
+                continue;
+            }
+            let state_delta = match patch.patches.get(&cid) {
+                Some(zk::ZkStatePatch::Delta(delta)) => delta.clone(),
+                Some(zk::ZkStatePatch::Full(full)) => full.data.as_delta(),
+                Some(zk::ZkStatePatch::Chunks(chunks)) => zk::ZkState::from_chunks(chunks)
+                    .ok_or(BlockchainError::FullStateNotValid)?
+                    .data
+                    .as_delta(),
+                None => {
+                    return Err(BlockchainError::FullStateNotFound);
+                }
+            };
+            Self::check_initial_state(&TransactionAndDelta {
+                tx: tx.clone(),
+                state_delta: Some(state_delta),
+            })?;
+        }
         Ok(())
     }
 
+    fn update_states_partial(&mut self, patch: &ZkBlockchainPatch) -> Result<(), BlockchainError> {
+        self.apply_state_patch(patch, true)
+    }
+
     fn cleanup_mempool(
         &self,
         mempool: &mut HashMap<TransactionAndDelta, TransactionStats>,
@@ -1203,6 +2124,7 @@ impl<K: KvStore> Blockchain for KvStoreChain<K> {
         &self,
         tx_delta: &TransactionAndDelta,
     ) -> Result<bool, BlockchainError> {
+        Self::check_initial_state(tx_delta)?;
         Ok(self
             .isolated(|chain| {
                 // TODO: Also check for delta validity
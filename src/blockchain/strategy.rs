@@ -0,0 +1,98 @@
+use super::TransactionStats;
+use crate::config::blockchain::MPN_CONTRACT_ID;
+use crate::core::{fee_rate, Money, TransactionAndDelta, TransactionData};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+// Decides the order in which mempool transactions are considered when drafting
+// a block. Transactions are then taken greedily, skipping the ones which don't
+// fit in the block budgets or fail to apply on the chain state.
+pub trait MempoolStrategy: std::fmt::Debug + Send + Sync {
+    fn order(
+        &self,
+        mempool: &HashMap<TransactionAndDelta, TransactionStats>,
+    ) -> Vec<TransactionAndDelta>;
+}
+
+// MPN updates are always considered last
+fn is_mpn_update(tx: &TransactionAndDelta) -> bool {
+    if let TransactionData::UpdateContract { contract_id, .. } = &tx.tx.data {
+        *contract_id == *MPN_CONTRACT_ID
+    } else {
+        false
+    }
+}
+
+// Lowest nonces first
+#[derive(Debug, Clone, Default)]
+pub struct FifoStrategy;
+
+impl MempoolStrategy for FifoStrategy {
+    fn order(
+        &self,
+        mempool: &HashMap<TransactionAndDelta, TransactionStats>,
+    ) -> Vec<TransactionAndDelta> {
+        let mut sorted = mempool.keys().cloned().collect::<Vec<_>>();
+        sorted.sort_by_key(|tx| (is_mpn_update(tx), tx.tx.nonce));
+        sorted
+    }
+}
+
+// Length and fee rate of the prefix of a sender's transactions with the highest
+// fee rate
+fn best_prefix(chain: &VecDeque<(TransactionAndDelta, usize)>) -> Option<(usize, Money)> {
+    let mut fee: Money = 0;
+    let mut size = 0;
+    let mut best: Option<(usize, Money)> = None;
+    for (i, (tx, tx_size)) in chain.iter().enumerate() {
+        fee = fee.saturating_add(tx.tx.fee);
+        size += tx_size;
+        let rate = fee_rate(fee, size);
+        if best.map(|(_, best_rate)| rate > best_rate).unwrap_or(true) {
+            best = Some((i + 1, rate));
+        }
+    }
+    best
+}
+
+// Highest fee rates first, maximizing the fees collected in a block. Transactions
+// of a sender are only valid in nonce order, so they are taken as chains, and a
+// cheap transaction is taken early when the ones after it pay enough for it.
+#[derive(Debug, Clone, Default)]
+pub struct FeeMaxStrategy;
+
+impl MempoolStrategy for FeeMaxStrategy {
+    fn order(
+        &self,
+        mempool: &HashMap<TransactionAndDelta, TransactionStats>,
+    ) -> Vec<TransactionAndDelta> {
+        let mut txs = mempool.keys().cloned().collect::<Vec<_>>();
+        txs.sort_by_key(|tx| tx.tx.nonce);
+
+        let mut mpn_updates = Vec::new();
+        let mut chains: BTreeMap<String, VecDeque<(TransactionAndDelta, usize)>> = BTreeMap::new();
+        for tx in txs {
+            if is_mpn_update(&tx) {
+                mpn_updates.push(tx);
+            } else {
+                let size = tx.size();
+                chains
+                    .entry(tx.tx.src.to_string())
+                    .or_default()
+                    .push_back((tx, size));
+            }
+        }
+        let mut chains = chains.into_values().collect::<Vec<_>>();
+
+        let mut sorted = Vec::new();
+        while let Some((i, len, _)) = chains
+            .iter()
+            .enumerate()
+            .filter_map(|(i, chain)| best_prefix(chain).map(|(len, rate)| (i, len, rate)))
+            .max_by_key(|(_, _, rate)| *rate)
+        {
+            sorted.extend(chains[i].drain(..len).map(|(tx, _)| tx));
+        }
+        sorted.extend(mpn_updates);
+        sorted
+    }
+}
@@ -51,9 +51,7 @@ fn test_contract_create_patch() -> Result<(), BlockchainError> {
 fn test_contract_update() -> Result<(), BlockchainError> {
     let miner = Wallet::new(Vec::from("MINER"));
     let alice = Wallet::new(Vec::from("ABC"));
-    let cid =
-        ContractId::from_str("764c9a719a203d34dae8d8538bf4667c7fcb84030fb2e476e4aeb6060c4419e0")
-            .unwrap();
+    let cid = blockchain::get_test_mpn_contract_id();
     let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
 
     let state_model = zk::ZkStateModel::List {
@@ -300,3 +298,1012 @@ fn test_contract_update() -> Result<(), BlockchainError> {
 
     Ok(())
 }
+
+#[test]
+fn test_oversized_state_patch_is_rejected() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let mut conf = easy_config();
+    conf.max_state_patch_size = 10;
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), conf)?;
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 5,
+    };
+    let full_state = zk::ZkState {
+        rollbacks: vec![],
+        data: Default::default(),
+    };
+    let tx = alice.create_contract(
+        zk::ZkContract {
+            state_model: state_model.clone(),
+            initial_state: state_model.compress::<ZkHasher>(&full_state.data)?,
+            log4_deposit_withdraw_capacity: 1,
+            deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+            functions: Vec::new(),
+        },
+        full_state.data.clone(),
+        0,
+        1,
+    );
+    let cid = ContractId::new(&tx.tx);
+
+    let draft = chain
+        .draft_block(1, &with_dummy_stats(&[tx]), &miner, true)?
+        .unwrap();
+    chain.apply_block(&draft.block, true)?;
+    assert_eq!(chain.get_outdated_contracts()?.len(), 1);
+
+    let huge_state = zk::ZkState {
+        rollbacks: vec![],
+        data: zk::ZkDataPairs(
+            (0..20)
+                .map(|i| (zk::ZkDataLocator(vec![i]), zk::ZkScalar::from(1)))
+                .collect(),
+        ),
+    };
+    assert!(matches!(
+        chain.update_states(&ZkBlockchainPatch {
+            patches: [(cid, zk::ZkStatePatch::Full(huge_state))]
+                .into_iter()
+                .collect(),
+        }),
+        Err(BlockchainError::StatePatchTooBig)
+    ));
+    assert_eq!(chain.get_outdated_contracts()?.len(), 1);
+
+    chain.update_states(&draft.patch)?;
+    assert_eq!(chain.get_outdated_contracts()?.len(), 0);
+
+    rollback_till_empty(&mut chain)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_max_contracts_per_block() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+
+    let mut conf = easy_config();
+    conf.max_contracts_per_block = 2;
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), conf)?;
+
+    let mut loose_conf = easy_config();
+    loose_conf.max_contracts_per_block = 3;
+    let loose_chain = KvStoreChain::new(db::RamKvStore::new(), loose_conf)?;
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 5,
+    };
+    let full_state = zk::ZkState {
+        rollbacks: vec![],
+        data: Default::default(),
+    };
+    let txs = (1..=3)
+        .map(|nonce| {
+            alice.create_contract(
+                zk::ZkContract {
+                    state_model: state_model.clone(),
+                    initial_state: state_model.compress::<ZkHasher>(&full_state.data).unwrap(),
+                    log4_deposit_withdraw_capacity: 1,
+                    deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+                    functions: Vec::new(),
+                },
+                full_state.data.clone(),
+                0,
+                nonce,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // Excess contracts are excluded when drafting
+    let draft = chain
+        .draft_block(1, &with_dummy_stats(&txs), &miner, true)?
+        .unwrap();
+    assert_eq!(draft.block.body.len(), 3);
+
+    // Blocks with too many contracts are rejected
+    let too_many = loose_chain
+        .draft_block(1, &with_dummy_stats(&txs), &miner, true)?
+        .unwrap();
+    assert_eq!(too_many.block.body.len(), 4);
+    assert!(matches!(
+        chain.fork_on_ram().apply_block(&too_many.block, true),
+        Err(BlockchainError::TooManyContracts)
+    ));
+
+    chain.apply_block(&draft.block, true)?;
+    chain.update_states(&draft.patch)?;
+    assert_eq!(chain.get_outdated_contracts()?.len(), 0);
+
+    rollback_till_empty(&mut chain)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_proof_verification_budget() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let cid = blockchain::get_test_mpn_contract_id();
+
+    let mut conf = easy_config();
+    conf.max_proof_verifications_per_block = 2;
+    let chain = KvStoreChain::new(db::RamKvStore::new(), conf)?;
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 5,
+    };
+    let state_delta = zk::ZkDeltaPairs(
+        [(zk::ZkDataLocator(vec![123]), Some(zk::ZkScalar::from(234)))]
+            .into_iter()
+            .collect(),
+    );
+    let txs = (1..=3)
+        .map(|nonce| {
+            alice.call_function(
+                cid,
+                0,
+                state_delta.clone(),
+                state_model
+                    .compress::<ZkHasher>(&Default::default())
+                    .unwrap(),
+                zk::ZkProof::Dummy(true),
+                0,
+                nonce,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // The budget limits inclusion
+    let selected = chain.select_transactions(&with_dummy_stats(&txs), true, &FifoStrategy)?;
+    assert_eq!(selected.len(), 2);
+
+    // Blocks exceeding the budget are rejected
+    let mut draft = chain
+        .draft_block(1, &HashMap::new(), &miner, true)?
+        .unwrap();
+    draft.block.body.extend(txs.iter().map(|tx| tx.tx.clone()));
+    draft.block.header.block_root = draft.block.merkle_tree().root();
+    assert!(matches!(
+        chain.fork_on_ram().apply_block(&draft.block, false),
+        Err(BlockchainError::ProofBudgetExceeded)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_missing_outdated_key_is_recovered() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 5,
+    };
+    let full_state = zk::ZkState {
+        rollbacks: vec![],
+        data: zk::ZkDataPairs(
+            [(zk::ZkDataLocator(vec![100]), zk::ZkScalar::from(200))]
+                .into_iter()
+                .collect(),
+        ),
+    };
+    let tx = alice.create_contract(
+        zk::ZkContract {
+            state_model: state_model.clone(),
+            initial_state: state_model.compress::<ZkHasher>(&full_state.data)?,
+            log4_deposit_withdraw_capacity: 1,
+            deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+            functions: Vec::new(),
+        },
+        full_state.data.clone(),
+        0,
+        1,
+    );
+    let cid = ContractId::new(&tx.tx);
+
+    let draft = chain
+        .draft_block(1, &with_dummy_stats(&[tx]), &miner, true)?
+        .unwrap();
+    chain.apply_block(&draft.block, true)?;
+    assert_eq!(chain.get_outdated_contracts()?, vec![cid]);
+
+    // Simulate a corrupted database
+    chain
+        .database
+        .update(&[WriteOp::Remove("outdated".into())])?;
+    assert!(chain.get_outdated_contracts()?.is_empty());
+
+    let mut chain = KvStoreChain::new(chain.database, easy_config())?;
+    assert_eq!(chain.get_outdated_contracts()?, vec![cid]);
+
+    chain.update_states(&draft.patch)?;
+    assert!(chain.get_outdated_contracts()?.is_empty());
+
+    // Nothing to recover when states are up to date
+    let chain = KvStoreChain::new(chain.database, easy_config())?;
+    assert!(chain.get_outdated_contracts()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_only_owner_can_update_owned_contract() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 5,
+    };
+    let full_state = zk::ZkState {
+        rollbacks: vec![],
+        data: Default::default(),
+    };
+    let tx = alice.create_owned_contract(
+        zk::ZkContract {
+            state_model: state_model.clone(),
+            initial_state: state_model.compress::<ZkHasher>(&full_state.data)?,
+            log4_deposit_withdraw_capacity: 1,
+            deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+            functions: vec![zk::ZkVerifierKey::Dummy],
+        },
+        alice.get_address(),
+        full_state.data.clone(),
+        0,
+        1,
+    );
+    let cid = ContractId::new(&tx.tx);
+    let draft = chain
+        .draft_block(1, &with_dummy_stats(&[tx]), &miner, true)?
+        .unwrap();
+    chain.apply_block(&draft.block, true)?;
+    chain.update_states(&draft.patch)?;
+    assert_eq!(chain.get_contract_owner(cid)?, Some(alice.get_address()));
+
+    let state_delta = zk::ZkDeltaPairs(
+        [(zk::ZkDataLocator(vec![123]), Some(zk::ZkScalar::from(234)))]
+            .into_iter()
+            .collect(),
+    );
+    let mut next_state = full_state.clone();
+    next_state.apply_delta(&state_delta);
+    let update = |wallet: &Wallet, nonce| {
+        wallet.call_function(
+            cid,
+            0,
+            state_delta.clone(),
+            state_model.compress::<ZkHasher>(&next_state.data).unwrap(),
+            zk::ZkProof::Dummy(true),
+            0,
+            nonce,
+        )
+    };
+
+    assert!(matches!(
+        chain.fork_on_ram().apply_tx(&update(&bob, 1).tx, false),
+        Err(BlockchainError::Unauthorized)
+    ));
+    chain.fork_on_ram().apply_tx(&update(&alice, 2).tx, false)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_paused_contract_rejects_updates() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 5,
+    };
+    let full_state = zk::ZkState {
+        rollbacks: vec![],
+        data: Default::default(),
+    };
+    let tx = alice.create_owned_contract(
+        zk::ZkContract {
+            state_model: state_model.clone(),
+            initial_state: state_model.compress::<ZkHasher>(&full_state.data)?,
+            log4_deposit_withdraw_capacity: 1,
+            deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+            functions: vec![zk::ZkVerifierKey::Dummy],
+        },
+        alice.get_address(),
+        full_state.data.clone(),
+        0,
+        1,
+    );
+    let cid = ContractId::new(&tx.tx);
+    let draft = chain
+        .draft_block(1, &with_dummy_stats(&[tx]), &miner, true)?
+        .unwrap();
+    chain.apply_block(&draft.block, true)?;
+    chain.update_states(&draft.patch)?;
+
+    let state_delta = zk::ZkDeltaPairs(
+        [(zk::ZkDataLocator(vec![123]), Some(zk::ZkScalar::from(234)))]
+            .into_iter()
+            .collect(),
+    );
+    let mut next_state = full_state.clone();
+    next_state.apply_delta(&state_delta);
+    let update = |nonce| {
+        alice.call_function(
+            cid,
+            0,
+            state_delta.clone(),
+            state_model.compress::<ZkHasher>(&next_state.data).unwrap(),
+            zk::ZkProof::Dummy(true),
+            0,
+            nonce,
+        )
+    };
+
+    // Only the owner can pause the contract
+    assert!(matches!(
+        chain
+            .fork_on_ram()
+            .apply_tx(&bob.pause_contract(cid, true, 0, 1).tx, false),
+        Err(BlockchainError::Unauthorized)
+    ));
+    chain.apply_tx(&alice.pause_contract(cid, true, 0, 2).tx, false)?;
+    assert!(chain.is_contract_paused(cid)?);
+    assert!(matches!(
+        chain.fork_on_ram().apply_tx(&update(3).tx, false),
+        Err(BlockchainError::ContractPaused)
+    ));
+
+    chain.apply_tx(&alice.pause_contract(cid, false, 0, 3).tx, false)?;
+    assert!(!chain.is_contract_paused(cid)?);
+    chain.fork_on_ram().apply_tx(&update(4).tx, false)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_draft_block_with_unrelated_outdated_contract() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 5,
+    };
+    let full_state = zk::ZkState {
+        rollbacks: vec![],
+        data: Default::default(),
+    };
+    let tx = alice.create_contract(
+        zk::ZkContract {
+            state_model: state_model.clone(),
+            initial_state: state_model.compress::<ZkHasher>(&full_state.data)?,
+            log4_deposit_withdraw_capacity: 1,
+            deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+            functions: vec![zk::ZkVerifierKey::Dummy],
+        },
+        full_state.data.clone(),
+        0,
+        1,
+    );
+    let cid = ContractId::new(&tx.tx);
+
+    // Contract gets created but its state is not provided
+    let create_draft = chain
+        .draft_block(1, &with_dummy_stats(&[tx]), &miner, true)?
+        .unwrap();
+    chain.apply_block(&create_draft.block, true)?;
+    assert_eq!(chain.get_outdated_contracts()?, vec![cid]);
+
+    // Unrelated transactions can still be mined
+    let draft = chain
+        .draft_block(
+            1,
+            &with_dummy_stats(&[alice.create_transaction(bob.get_address(), 100, 0, 2)]),
+            &miner,
+            true,
+        )?
+        .unwrap();
+    assert_eq!(draft.block.body.len(), 2);
+    chain.apply_block(&draft.block, true)?;
+    assert_eq!(chain.get_height()?, 3);
+    assert_eq!(chain.get_outdated_contracts()?, vec![cid]);
+
+    // Transactions needing the outdated state are left out
+    let state_delta = zk::ZkDeltaPairs(
+        [(zk::ZkDataLocator(vec![123]), Some(zk::ZkScalar::from(234)))]
+            .into_iter()
+            .collect(),
+    );
+    let mut next_state = full_state.clone();
+    next_state.apply_delta(&state_delta);
+    let call = alice.call_function(
+        cid,
+        0,
+        state_delta,
+        state_model.compress::<ZkHasher>(&next_state.data)?,
+        zk::ZkProof::Dummy(true),
+        0,
+        3,
+    );
+    let draft = chain
+        .draft_block(
+            1,
+            &with_dummy_stats(std::slice::from_ref(&call)),
+            &miner,
+            true,
+        )?
+        .unwrap();
+    assert_eq!(draft.block.body.len(), 1);
+    assert!(!draft.block.body.contains(&call.tx));
+
+    chain.update_states(&create_draft.patch)?;
+    assert!(chain.get_outdated_contracts()?.is_empty());
+    let draft = chain
+        .draft_block(
+            1,
+            &with_dummy_stats(std::slice::from_ref(&call)),
+            &miner,
+            true,
+        )?
+        .unwrap();
+    assert!(draft.block.body.contains(&call.tx));
+
+    rollback_till_empty(&mut chain)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_contract_byte_size_follows_state() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let cid = blockchain::get_test_mpn_contract_id();
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 5,
+    };
+    let state_delta = zk::ZkDeltaPairs(
+        [
+            (zk::ZkDataLocator(vec![100]), Some(zk::ZkScalar::from(200))),
+            (zk::ZkDataLocator(vec![123]), Some(zk::ZkScalar::from(234))),
+        ]
+        .into_iter()
+        .collect(),
+    );
+    let mut full_state = zk::ZkState {
+        rollbacks: vec![],
+        data: Default::default(),
+    };
+    full_state.apply_delta(&state_delta);
+
+    let prev_byte_size = chain.get_contract_account(cid)?.byte_size;
+
+    let tx = alice.call_function(
+        cid,
+        0,
+        state_delta,
+        state_model.compress::<ZkHasher>(&full_state.data)?,
+        zk::ZkProof::Dummy(true),
+        0,
+        1,
+    );
+    let draft = chain
+        .draft_block(1, &with_dummy_stats(&[tx]), &miner, false)?
+        .unwrap();
+    chain.apply_block(&draft.block, true)?;
+
+    let byte_size = chain.get_contract_account(cid)?.byte_size;
+    assert_eq!(byte_size, 2 * std::mem::size_of::<zk::ZkScalar>() as u64);
+    assert_ne!(byte_size, prev_byte_size);
+
+    chain.rollback()?;
+    assert_eq!(chain.get_contract_account(cid)?.byte_size, prev_byte_size);
+
+    Ok(())
+}
+
+#[test]
+fn test_contract_storage_size_grows() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 5,
+    };
+    let create_tx = alice.create_contract(
+        zk::ZkContract {
+            initial_state: state_model.compress::<ZkHasher>(&Default::default())?,
+            state_model: state_model.clone(),
+            deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+            log4_deposit_withdraw_capacity: 0,
+            functions: vec![zk::ZkVerifierKey::Dummy],
+        },
+        zk::ZkDataPairs(Default::default()),
+        0,
+        1,
+    );
+    let cid = ContractId::new(&create_tx.tx);
+    assert!(matches!(
+        chain.contract_storage_size(cid),
+        Err(BlockchainError::ContractNotFound)
+    ));
+
+    let draft = chain
+        .draft_block(1, &with_dummy_stats(&[create_tx]), &miner, false)?
+        .unwrap();
+    chain.apply_block(&draft.block, true)?;
+    chain.update_states(&draft.patch)?;
+    let created_size = chain.contract_storage_size(cid)?;
+    assert!(created_size > 0);
+
+    let state_delta = zk::ZkDataPairs(
+        [(zk::ZkDataLocator(vec![10]), zk::ZkScalar::from(20))]
+            .into_iter()
+            .collect(),
+    );
+    let update_tx = alice.call_function(
+        cid,
+        0,
+        state_delta.as_delta(),
+        state_model.compress::<ZkHasher>(&state_delta)?,
+        zk::ZkProof::Dummy(true),
+        0,
+        2,
+    );
+    let draft = chain
+        .draft_block(2, &with_dummy_stats(&[update_tx]), &miner, false)?
+        .unwrap();
+    chain.apply_block(&draft.block, true)?;
+    chain.update_states(&draft.patch)?;
+    assert!(chain.contract_storage_size(cid)? > created_size);
+
+    Ok(())
+}
+
+#[test]
+fn test_contract_with_mismatched_initial_state() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 5,
+    };
+    let declared_state = zk::ZkDataPairs(
+        [(zk::ZkDataLocator(vec![100]), zk::ZkScalar::from(200))]
+            .into_iter()
+            .collect(),
+    );
+    let provided_state = zk::ZkDataPairs(
+        [(zk::ZkDataLocator(vec![100]), zk::ZkScalar::from(201))]
+            .into_iter()
+            .collect(),
+    );
+
+    let contract = zk::ZkContract {
+        state_model: state_model.clone(),
+        initial_state: state_model.compress::<ZkHasher>(&declared_state)?,
+        log4_deposit_withdraw_capacity: 1,
+        deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+        functions: Vec::new(),
+    };
+    let tx = alice.create_contract(contract.clone(), provided_state, 0, 1);
+
+    assert!(matches!(
+        chain.validate_transaction(&tx),
+        Err(BlockchainError::InitialStateMismatch)
+    ));
+
+    // Not included in the drafted blocks either, only the miner reward is there
+    let draft = chain
+        .draft_block(
+            1,
+            &with_dummy_stats(std::slice::from_ref(&tx)),
+            &miner,
+            true,
+        )?
+        .unwrap();
+    assert_eq!(draft.block.body.len(), 1);
+
+    // Nor accepted along with a block, whose patch doesn't match the declared state
+    let honest = alice.create_contract(contract, declared_state, 0, 1);
+    let cid = ContractId::new(&honest.tx);
+    let mut draft = chain
+        .draft_block(1, &with_dummy_stats(&[honest]), &miner, true)?
+        .unwrap();
+    chain.check_initial_states(&draft.block, &draft.patch)?;
+    draft
+        .patch
+        .patches
+        .insert(cid, zk::ZkStatePatch::Delta(tx.state_delta.unwrap()));
+    assert!(matches!(
+        chain.check_initial_states(&draft.block, &draft.patch),
+        Err(BlockchainError::InitialStateMismatch)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_contract_value_by_locator() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let state_model = zk::ZkStateModel::Struct {
+        field_types: vec![zk::ZkStateModel::Scalar, zk::ZkStateModel::Scalar],
+    };
+    let initial_state = zk::ZkDataPairs(
+        [(zk::ZkDataLocator(vec![0]), zk::ZkScalar::from(123))]
+            .into_iter()
+            .collect(),
+    );
+    let tx = alice.create_contract(
+        zk::ZkContract {
+            state_model: state_model.clone(),
+            initial_state: state_model.compress::<ZkHasher>(&initial_state)?,
+            log4_deposit_withdraw_capacity: 1,
+            deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+            functions: Vec::new(),
+        },
+        initial_state,
+        0,
+        1,
+    );
+    let cid = ContractId::new(&tx.tx);
+
+    let draft = chain
+        .draft_block(1, &with_dummy_stats(&[tx]), &miner, true)?
+        .unwrap();
+    chain.apply_block(&draft.block, true)?;
+
+    // States are not available yet
+    assert!(matches!(
+        chain.get_contract_value(cid, &zk::ZkDataLocator(vec![0])),
+        Err(BlockchainError::StatesUnavailable)
+    ));
+
+    chain.update_states(&draft.patch)?;
+
+    assert_eq!(
+        chain.get_contract_value(cid, &zk::ZkDataLocator(vec![0]))?,
+        zk::ZkScalar::from(123)
+    );
+    assert_eq!(
+        chain.get_contract_value(cid, &zk::ZkDataLocator(vec![1]))?,
+        zk::ZkScalar::from(0)
+    );
+
+    // Locators outside of the state-model
+    assert!(matches!(
+        chain.get_contract_value(cid, &zk::ZkDataLocator(vec![2])),
+        Err(BlockchainError::StateManagerError(_))
+    ));
+    assert!(matches!(
+        chain.get_contract_value(cid, &zk::ZkDataLocator(vec![0, 1])),
+        Err(BlockchainError::StateManagerError(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_outdated_contracts_are_updated_in_order() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 5,
+    };
+    let txs = (1..=3)
+        .map(|i| {
+            let state = zk::ZkDataPairs(
+                [(zk::ZkDataLocator(vec![i]), zk::ZkScalar::from(i as u64))]
+                    .into_iter()
+                    .collect(),
+            );
+            alice.create_contract(
+                zk::ZkContract {
+                    state_model: state_model.clone(),
+                    initial_state: state_model.compress::<ZkHasher>(&state).unwrap(),
+                    log4_deposit_withdraw_capacity: 1,
+                    deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+                    functions: Vec::new(),
+                },
+                state,
+                0,
+                i,
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut cids = txs
+        .iter()
+        .map(|tx| ContractId::new(&tx.tx))
+        .collect::<Vec<_>>();
+    cids.sort_by_key(|cid| cid.to_string());
+
+    let draft = chain
+        .draft_block(1, &with_dummy_stats(&txs), &miner, true)?
+        .unwrap();
+    chain.apply_block(&draft.block, true)?;
+    assert_eq!(chain.get_outdated_contracts()?.len(), 3);
+
+    let invalid = zk::ZkStatePatch::Delta(zk::ZkDeltaPairs(
+        [(zk::ZkDataLocator(vec![0]), Some(zk::ZkScalar::from(1000)))]
+            .into_iter()
+            .collect(),
+    ));
+
+    // The first contract (In id order) decides the failure, whatever the order
+    // of the contracts in the chain is.
+    for _ in 0..4 {
+        assert!(matches!(
+            chain.fork_on_ram().update_states(&ZkBlockchainPatch {
+                patches: [(cids[0], invalid.clone())].into_iter().collect()
+            }),
+            Err(BlockchainError::FullStateNotValid)
+        ));
+        assert!(matches!(
+            chain.fork_on_ram().update_states(&ZkBlockchainPatch {
+                patches: [(cids[1], invalid.clone()), (cids[2], invalid.clone())]
+                    .into_iter()
+                    .collect()
+            }),
+            Err(BlockchainError::FullStateNotFound)
+        ));
+    }
+
+    // Generated writes are the same on every run
+    let mut fork = chain.fork_on_ram();
+    fork.update_states(&draft.patch)?;
+    let ops = fork.database.to_ops();
+    let mut another_fork = chain.fork_on_ram();
+    another_fork.update_states(&draft.patch)?;
+    assert_eq!(ops, another_fork.database.to_ops());
+
+    chain.update_states(&draft.patch)?;
+    assert!(chain.get_outdated_contracts()?.is_empty());
+
+    rollback_till_empty(&mut chain)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_reapplied_contract_updates_are_consistent() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let cid = blockchain::get_test_mpn_contract_id();
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 5,
+    };
+    let mut full_state = zk::ZkState {
+        rollbacks: vec![],
+        data: zk::ZkDataPairs(
+            [(zk::ZkDataLocator(vec![100]), zk::ZkScalar::from(200))]
+                .into_iter()
+                .collect(),
+        ),
+    };
+    let state_delta = zk::ZkDeltaPairs(
+        [(zk::ZkDataLocator(vec![123]), Some(zk::ZkScalar::from(234)))]
+            .into_iter()
+            .collect(),
+    );
+    full_state.apply_delta(&state_delta);
+    let tx = alice.call_function(
+        cid,
+        0,
+        state_delta,
+        state_model.compress::<ZkHasher>(&full_state.data)?,
+        zk::ZkProof::Dummy(true),
+        0,
+        1,
+    );
+
+    let draft = chain
+        .draft_block(1, &with_dummy_stats(&[tx]), &miner, false)?
+        .unwrap();
+    chain.apply_block(&draft.block, true)?;
+
+    // Re-applying the same block reproduces the same contract updates (The
+    // extension itself is rejected since it doesn't add any power)
+    let updates = chain.get_changed_states(1)?;
+    assert!(matches!(
+        chain.extend(1, std::slice::from_ref(&draft.block)),
+        Err(BlockchainError::PowerNotIncreased)
+    ));
+    assert_eq!(chain.get_changed_states(1)?, updates);
+
+    // A diverging record of the same block means nondeterminism
+    let mut diverged = updates;
+    diverged.get_mut(&cid).unwrap().state = Default::default();
+    chain.database.update(&[WriteOp::Put(
+        height_key("contract_updates", 1),
+        diverged.into(),
+    )])?;
+    assert!(matches!(
+        chain.extend(1, std::slice::from_ref(&draft.block)),
+        Err(BlockchainError::Inconsistency)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_deposit_withdraw_conservation() -> Result<(), BlockchainError> {
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let cid = blockchain::get_test_mpn_contract_id();
+    let payments = vec![
+        alice.contract_deposit_withdraw(cid, 0, 0, 100, 0, false),
+        bob.contract_deposit_withdraw(cid, 1, 0, 30, 0, true),
+    ];
+
+    check_dw_conservation(&payments, 70, -70)?;
+    assert!(matches!(
+        check_dw_conservation(&payments, 80, -70),
+        Err(BlockchainError::Inconsistency)
+    ));
+    assert!(matches!(
+        check_dw_conservation(&payments, 70, -60),
+        Err(BlockchainError::Inconsistency)
+    ));
+    check_dw_conservation(&[], 0, 0)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_deposit_withdraw_of_the_sender_itself() -> Result<(), BlockchainError> {
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+    let cid = blockchain::get_test_mpn_contract_id();
+    let next_state = chain.get_contract_account(cid)?.compressed_state;
+    let deposit = |sender: &Wallet, nonce, dw_nonce, fee| {
+        let mut tx = Transaction {
+            src: sender.get_address(),
+            data: TransactionData::UpdateContract {
+                contract_id: cid,
+                updates: vec![ContractUpdate::DepositWithdraw {
+                    deposit_withdraws: vec![
+                        alice.contract_deposit_withdraw(cid, 0, dw_nonce, 100, 0, false)
+                    ],
+                    next_state,
+                    proof: zk::ZkProof::Dummy(true),
+                }],
+            },
+            nonce,
+            fee,
+            sig: Signature::Unsigned,
+        };
+        sender.sign(&mut tx);
+        tx
+    };
+    let alice_balance = chain.get_account(alice.get_address())?.balance;
+    let contract_balance = chain.get_contract_account(cid)?.balance;
+
+    chain.apply_tx(&deposit(&bob, 1, 0, 0), false)?;
+    assert_eq!(
+        chain.get_account(alice.get_address())?.balance,
+        alice_balance - 100
+    );
+    assert_eq!(
+        chain.get_contract_account(cid)?.balance,
+        contract_balance + 100
+    );
+
+    // The deposit of the sender itself is kept along with its fee, its nonce
+    // follows the nonce of the transaction
+    chain.apply_tx(&deposit(&alice, 2, 2, 3), false)?;
+    let alice_account = chain.get_account(alice.get_address())?;
+    assert_eq!(alice_account.balance, alice_balance - 203);
+    assert_eq!(alice_account.nonce, 3);
+    assert_eq!(
+        chain.get_contract_account(cid)?.balance,
+        contract_balance + 200
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_block_and_delta_size_limits() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 5,
+    };
+    let data = zk::ZkDataPairs(
+        [
+            (zk::ZkDataLocator(vec![1]), zk::ZkScalar::from(10)),
+            (zk::ZkDataLocator(vec![2]), zk::ZkScalar::from(20)),
+        ]
+        .into_iter()
+        .collect(),
+    );
+    let create = alice.create_contract(
+        zk::ZkContract {
+            state_model: state_model.clone(),
+            initial_state: state_model.compress::<ZkHasher>(&data)?,
+            log4_deposit_withdraw_capacity: 1,
+            deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+            functions: Vec::new(),
+        },
+        data,
+        0,
+        1,
+    );
+    let send = alice.create_transaction(bob.get_address(), 100, 0, 1);
+
+    // The contract grows the states, the send doesn't
+    let draft = chain
+        .draft_block(
+            1,
+            &with_dummy_stats(std::slice::from_ref(&create)),
+            &miner,
+            true,
+        )?
+        .unwrap();
+    chain.config.max_delta_size = 1;
+    assert!(matches!(
+        chain.apply_block(&draft.block, true),
+        Err(BlockchainError::DeltaTooBig)
+    ));
+    let draft = chain
+        .draft_block(1, &with_dummy_stats(&[create]), &miner, true)?
+        .unwrap();
+    assert_eq!(draft.block.body.len(), 1);
+    let draft = chain
+        .draft_block(
+            1,
+            &with_dummy_stats(std::slice::from_ref(&send)),
+            &miner,
+            true,
+        )?
+        .unwrap();
+    assert_eq!(draft.block.body.len(), 2);
+
+    // Body size is limited independently (The miner reward doesn't count)
+    chain.config.max_block_size = send.tx.size() - 1;
+    assert!(matches!(
+        chain.apply_block(&draft.block, true),
+        Err(BlockchainError::BlockTooBig)
+    ));
+    let draft = chain
+        .draft_block(1, &with_dummy_stats(&[send]), &miner, true)?
+        .unwrap();
+    assert_eq!(draft.block.body.len(), 1);
+
+    Ok(())
+}
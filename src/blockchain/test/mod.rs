@@ -31,6 +31,76 @@ fn rollback_till_empty<K: KvStore>(b: &mut KvStoreChain<K>) -> Result<(), Blockc
     Ok(())
 }
 
+#[test]
+fn test_height_keys_are_ordered() {
+    let heights = [
+        0,
+        1,
+        9,
+        10,
+        255,
+        256,
+        u32::MAX as u64,
+        u32::MAX as u64 + 1,
+        9_999_999_999,
+        10_000_000_000,
+        u64::MAX - 1,
+        u64::MAX,
+    ];
+    let keys = heights
+        .iter()
+        .map(|h| height_key("header", *h))
+        .collect::<Vec<_>>();
+    let mut sorted = keys.clone();
+    sorted.sort();
+    assert_eq!(keys, sorted);
+    assert!(keys.iter().all(|k| k.0.len() == "header_".len() + 16));
+}
+
+#[test]
+fn test_databases_of_old_versions_are_refused() -> Result<(), BlockchainError> {
+    let chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+    let chain = KvStoreChain::new(chain.database, easy_config())?;
+
+    // Databases created before the version marker had decimal height keys
+    let mut db = chain.database;
+    db.update(&[WriteOp::Remove("db_version".into())])?;
+    assert!(matches!(
+        KvStoreChain::new(db, easy_config()),
+        Err(BlockchainError::IncompatibleDatabase(None))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_genesis_with_invalid_merkle_root() {
+    let mut conf = easy_config();
+    conf.genesis.block.header.block_root = Hasher::hash(b"corrupted");
+    assert!(matches!(
+        KvStoreChain::new(db::RamKvStore::new(), conf),
+        Err(BlockchainError::InvalidMerkleRoot)
+    ));
+
+    let mut conf = easy_config();
+    conf.genesis.block.header.number = 1;
+    assert!(matches!(
+        KvStoreChain::new(db::RamKvStore::new(), conf),
+        Err(BlockchainError::InvalidBlockNumber)
+    ));
+
+    let mut conf = easy_config();
+    conf.genesis.block.header.parent_hash = Hasher::hash(b"parent");
+    assert!(matches!(
+        KvStoreChain::new(db::RamKvStore::new(), conf),
+        Err(BlockchainError::InvalidParentHash)
+    ));
+
+    // Left unset, as in the mainnet genesis
+    let mut conf = easy_config();
+    conf.genesis.block.header.block_root = Default::default();
+    assert!(KvStoreChain::new(db::RamKvStore::new(), conf).is_ok());
+}
+
 #[test]
 fn test_get_header_and_get_block() -> Result<(), BlockchainError> {
     let miner = Wallet::new(Vec::from("MINER"));
@@ -208,6 +278,48 @@ fn test_difficulty_target_recalculation() -> Result<(), BlockchainError> {
     Ok(())
 }
 
+#[test]
+fn test_equal_timestamps_keep_difficulty_sane() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let mut conf = easy_config();
+    conf.difficulty_calc_interval = 3;
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), conf)?;
+
+    // All blocks share the genesis timestamp, which the median rule allows
+    let mut targets = Vec::new();
+    for i in 1..7 {
+        let mut draft = chain
+            .draft_block(0, &HashMap::new(), &miner, true)?
+            .unwrap();
+        mine_block(&chain, &mut draft)?;
+        targets.push(draft.block.header.proof_of_work.target);
+        chain.extend(i, &[draft.block])?;
+    }
+    // Difficulty doubles on each retarget, the maximum allowed change
+    assert_eq!(
+        targets,
+        vec![0x00ffffff, 0x00ffffff, 0x007fffff, 0x007fffff, 0x007fffff, 0x003fffff]
+    );
+
+    // Decreasing timestamps don't underflow
+    let mut last_pow = chain.get_header(5)?.proof_of_work;
+    let mut prev_pow = last_pow;
+    prev_pow.timestamp = 100;
+    last_pow.timestamp = 50;
+    assert_eq!(
+        utils::calc_pow_difficulty(3, 60, &last_pow, &prev_pow),
+        0x003fffff
+    );
+    assert_eq!(
+        utils::calc_pow_difficulty(1, 60, &last_pow, &prev_pow),
+        0x003fffff
+    );
+
+    rollback_till_empty(&mut chain)?;
+
+    Ok(())
+}
+
 #[test]
 fn test_pow_key_correctness() -> Result<(), BlockchainError> {
     let miner = Wallet::new(Vec::from("MINER"));
@@ -246,6 +358,40 @@ fn test_pow_key_correctness() -> Result<(), BlockchainError> {
     Ok(())
 }
 
+#[test]
+fn test_max_timestamp_regression() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    for i in 10..30 {
+        chain.apply_block(
+            &chain
+                .draft_block(i, &HashMap::new(), &miner, true)?
+                .unwrap()
+                .block,
+            true,
+        )?;
+    }
+
+    // Median is 25 and the tip is at 29
+    assert!(chain.draft_block(26, &HashMap::new(), &miner, true).is_ok());
+
+    chain.config.max_timestamp_regression = Some(2);
+    assert!(matches!(
+        chain.draft_block(26, &HashMap::new(), &miner, true),
+        Err(BlockchainError::InvalidTimestamp)
+    ));
+    chain.apply_block(
+        &chain
+            .draft_block(27, &HashMap::new(), &miner, true)?
+            .unwrap()
+            .block,
+        true,
+    )?;
+
+    Ok(())
+}
+
 #[test]
 fn test_median_timestamp_correctness_check() -> Result<(), BlockchainError> {
     let miner = Wallet::new(Vec::from("MINER"));
@@ -462,6 +608,30 @@ fn test_merkle_root_check() -> Result<(), BlockchainError> {
     Ok(())
 }
 
+#[test]
+fn test_nonce_order_check() -> Result<(), BlockchainError> {
+    let alice = Wallet::new(Vec::from("ABC"));
+    let miner = Wallet::new(Vec::from("MINER"));
+    let mut conf = easy_config();
+    conf.enforce_nonce_order = true;
+    let chain = KvStoreChain::new(db::RamKvStore::new(), conf)?;
+
+    let mut draft = chain
+        .draft_block(1, &HashMap::new(), &miner, true)?
+        .unwrap();
+    draft.block.body.extend([
+        alice.create_transaction(miner.get_address(), 200, 0, 2).tx,
+        alice.create_transaction(miner.get_address(), 100, 0, 1).tx,
+    ]);
+    draft.block.header.block_root = draft.block.merkle_tree().root();
+    assert!(matches!(
+        chain.fork_on_ram().apply_block(&draft.block, true),
+        Err(BlockchainError::UnorderedNonces)
+    ));
+
+    Ok(())
+}
+
 #[test]
 fn test_txs_cant_be_duplicated() -> Result<(), BlockchainError> {
     let miner = Wallet::new(Vec::from("MINER"));
@@ -650,6 +820,128 @@ fn test_cant_apply_invalid_signed_tx() -> Result<(), BlockchainError> {
     Ok(())
 }
 
+#[test]
+fn test_trace_regular_send() -> Result<(), BlockchainError> {
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+
+    let chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let tx = alice.create_transaction(bob.get_address(), 2700, 300, 1).tx;
+    let (ops, side_effect) = chain.trace_tx(&tx)?;
+
+    assert!(matches!(side_effect, TxSideEffect::Nothing));
+    assert_eq!(ops.len(), 2);
+    for expected in [
+        WriteOp::Put(
+            format!("account_{}", alice.get_address()).into(),
+            Account {
+                balance: 7000,
+                nonce: 1,
+            }
+            .into(),
+        ),
+        WriteOp::Put(
+            format!("account_{}", bob.get_address()).into(),
+            Account {
+                balance: 2700,
+                nonce: 0,
+            }
+            .into(),
+        ),
+    ] {
+        assert!(ops.contains(&expected));
+    }
+
+    // Nothing is committed
+    assert_eq!(chain.get_account(alice.get_address())?.balance, 10000);
+    assert_eq!(chain.get_account(bob.get_address())?.balance, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_mempool_strategies() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+    chain.apply_block(
+        &chain
+            .draft_block(
+                1,
+                &with_dummy_stats(&[alice.create_transaction(bob.get_address(), 1000, 0, 1)]),
+                &miner,
+                true,
+            )?
+            .unwrap()
+            .block,
+        true,
+    )?;
+
+    // Bob's transaction has the lower nonce, Alice's pays the higher fee
+    let alice_tx = alice.create_transaction(bob.get_address(), 10, 50, 2);
+    let bob_tx = bob.create_transaction(alice.get_address(), 10, 1, 1);
+    let mempool = with_dummy_stats(&[alice_tx.clone(), bob_tx.clone()]);
+
+    // Only one of them fits
+    chain.config.max_block_size = alice_tx.tx.size();
+
+    assert_eq!(
+        chain.select_transactions(&mempool, true, &FifoStrategy)?,
+        vec![bob_tx]
+    );
+    assert_eq!(
+        chain.select_transactions(&mempool, true, &FeeMaxStrategy)?,
+        vec![alice_tx]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_fee_max_strategy_keeps_nonce_order() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+    chain.apply_block(
+        &chain
+            .draft_block(
+                1,
+                &with_dummy_stats(&[alice.create_transaction(bob.get_address(), 1000, 0, 1)]),
+                &miner,
+                true,
+            )?
+            .unwrap()
+            .block,
+        true,
+    )?;
+
+    // Alice's cheap transaction is followed by a very expensive one, making her
+    // chain denser than Bob's single transaction
+    let alice_cheap = alice.create_transaction(bob.get_address(), 10, 1, 2);
+    let alice_expensive = alice.create_transaction(bob.get_address(), 10, 100, 3);
+    let bob_tx = bob.create_transaction(alice.get_address(), 10, 30, 1);
+    let mempool = with_dummy_stats(&[alice_expensive.clone(), bob_tx.clone(), alice_cheap.clone()]);
+
+    // Only two of them fit
+    chain.config.max_block_size = alice_cheap.tx.size() * 2;
+
+    assert_eq!(
+        FeeMaxStrategy.order(&mempool),
+        vec![alice_cheap.clone(), alice_expensive.clone(), bob_tx]
+    );
+    assert_eq!(
+        chain.select_transactions(&mempool, true, &FeeMaxStrategy)?,
+        vec![alice_cheap, alice_expensive]
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_balances_are_correct_after_tx() -> Result<(), BlockchainError> {
     let miner = Wallet::new(Vec::from("MINER"));
@@ -763,6 +1055,244 @@ fn test_balances_are_correct_after_tx() -> Result<(), BlockchainError> {
     Ok(())
 }
 
+#[test]
+fn test_total_circulating_audit() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+
+    let conf = easy_config();
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), conf.clone())?;
+    chain.total_circulating()?;
+
+    let txs = [
+        alice.create_transaction(bob.get_address(), 2700, 300, 1),
+        alice.create_transaction(miner.get_address(), 1000, 100, 2),
+        alice.create_transaction(bob.get_address(), 500, 0, 3),
+    ];
+    for tx in txs {
+        let blk = chain
+            .draft_block(1, &with_dummy_stats(&[tx]), &miner, true)?
+            .unwrap()
+            .block;
+        chain.apply_block(&blk, true)?;
+    }
+
+    let accounts = chain.all_accounts()?;
+    assert!(accounts
+        .iter()
+        .any(|(addr, acc)| *addr == bob.get_address() && acc.balance == 3200));
+    assert!(accounts.iter().any(|(addr, _)| *addr == Address::Treasury));
+
+    let treasury = chain.get_account(Address::Treasury)?.balance;
+    // 400 has been burnt as fees
+    assert_eq!(
+        chain.total_circulating()?,
+        conf.total_supply - treasury - 400
+    );
+
+    // The fees burnt by the undone blocks are given back
+    chain.rollback()?;
+    chain.rollback()?;
+    let treasury = chain.get_account(Address::Treasury)?.balance;
+    assert_eq!(
+        chain.total_circulating()?,
+        conf.total_supply - treasury - 300
+    );
+
+    rollback_till_empty(&mut chain)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_empty_blocks() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+    let draft = chain
+        .draft_block(1, &HashMap::new(), &miner, true)?
+        .unwrap();
+    assert_eq!(draft.block.body.len(), 1);
+
+    chain.config.allow_empty_blocks = false;
+    assert!(matches!(
+        chain.draft_block(1, &HashMap::new(), &miner, true),
+        Err(BlockchainError::NothingToMine)
+    ));
+
+    // Transactions which can't be included don't count
+    let unpayable = with_dummy_stats(&[bob.create_transaction(alice.get_address(), 100, 0, 1)]);
+    assert!(matches!(
+        chain.draft_block(1, &unpayable, &miner, true),
+        Err(BlockchainError::NothingToMine)
+    ));
+
+    let payable = with_dummy_stats(&[alice.create_transaction(bob.get_address(), 100, 0, 1)]);
+    let draft = chain.draft_block(1, &payable, &miner, true)?.unwrap();
+    assert_eq!(draft.block.body.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_get_transaction() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+    let tx_delta = alice.create_transaction(bob.get_address(), 100, 0, 1);
+    let tx = tx_delta.tx.clone();
+    assert!(chain.get_transaction(tx.hash())?.is_none());
+
+    let draft = chain
+        .draft_block(1, &with_dummy_stats(&[tx_delta]), &miner, true)?
+        .unwrap();
+    chain.apply_block(&draft.block, true)?;
+    assert_eq!(chain.get_transaction(tx.hash())?, Some((1, tx.clone())));
+    let reward = draft.block.body[0].clone();
+    assert_eq!(
+        chain.get_transaction(reward.hash())?,
+        Some((1, reward.clone()))
+    );
+
+    chain.rollback()?;
+    assert!(chain.get_transaction(tx.hash())?.is_none());
+    assert!(chain.get_transaction(reward.hash())?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_ram_forks_are_capped() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+
+    let mut conf = easy_config();
+    conf.max_ram_forks = 2;
+    let chain = KvStoreChain::new(db::RamKvStore::new(), conf)?;
+    let mempool = with_dummy_stats(&[alice.create_transaction(bob.get_address(), 100, 0, 1)]);
+
+    // Drafting forks the chain more than once, concurrent drafts wait for each other
+    std::thread::scope(|s| {
+        let drafts = (0..16)
+            .map(|_| s.spawn(|| chain.draft_block(1, &mempool, &miner, true)))
+            .collect::<Vec<_>>();
+        for draft in drafts {
+            assert_eq!(draft.join().unwrap()?.unwrap().block.body.len(), 2);
+        }
+        Ok::<(), BlockchainError>(())
+    })?;
+    assert!(chain.forks().peak() <= 2);
+    assert_eq!(chain.forks().live(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_fee_split() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+
+    let mut conf = easy_config();
+    conf.fee_split = FeeSplit {
+        miner_bps: 5000,
+        treasury_bps: 5000,
+        burn_bps: 0,
+    };
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), conf.clone())?;
+    let treasury_before = chain.get_account(Address::Treasury)?.balance;
+    let next_reward = chain.next_reward()?;
+
+    let txs = [
+        alice.create_transaction(bob.get_address(), 100, 300, 1),
+        alice.create_transaction(bob.get_address(), 100, 100, 2),
+    ];
+    let blk = chain
+        .draft_block(1, &with_dummy_stats(&txs), &miner, true)?
+        .unwrap()
+        .block;
+
+    // The miner's share of the fees is paid through the reward
+    let mut underpaid = blk.clone();
+    underpaid.body[0].data = TransactionData::RegularSend {
+        dst: miner.get_address(),
+        amount: next_reward,
+    };
+    underpaid.header.block_root = underpaid.merkle_tree().root();
+    assert!(matches!(
+        chain.apply_block(&underpaid, true),
+        Err(BlockchainError::InvalidMinerReward)
+    ));
+
+    chain.apply_block(&blk, true)?;
+    assert_eq!(
+        chain.get_account(miner.get_address())?.balance,
+        next_reward + 200
+    );
+    let treasury = chain.get_account(Address::Treasury)?.balance;
+    assert_eq!(treasury, treasury_before - next_reward + 200);
+    // Nothing has been burnt
+    assert_eq!(chain.total_circulating()?, conf.total_supply - treasury);
+
+    rollback_till_empty(&mut chain)?;
+
+    conf.fee_split.burn_bps = 1;
+    assert!(matches!(
+        KvStoreChain::new(db::RamKvStore::new(), conf),
+        Err(BlockchainError::InvalidFeeSplit)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_all_accounts_are_sorted() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let receivers = (0..5)
+        .map(|i| Wallet::new(format!("RECEIVER{}", i).into_bytes()).get_address())
+        .collect::<Vec<_>>();
+    let txs = receivers
+        .iter()
+        .enumerate()
+        .map(|(i, addr)| alice.create_transaction(addr.clone(), 100, 0, i as u32 + 1))
+        .collect::<Vec<_>>();
+    let blk = chain
+        .draft_block(1, &with_dummy_stats(&txs), &miner, true)?
+        .unwrap()
+        .block;
+    chain.apply_block(&blk, true)?;
+
+    let accounts = chain.all_accounts()?;
+    for addr in
+        receivers
+            .into_iter()
+            .chain([alice.get_address(), miner.get_address(), Address::Treasury])
+    {
+        assert!(accounts.iter().any(|(a, _)| *a == addr));
+    }
+    for (addr, acc) in accounts.iter() {
+        assert_eq!(chain.get_account(addr.clone())?, *acc);
+    }
+    let keys = accounts
+        .iter()
+        .map(|(addr, _)| addr.to_string())
+        .collect::<Vec<_>>();
+    let mut sorted = keys.clone();
+    sorted.sort();
+    assert_eq!(keys, sorted);
+
+    Ok(())
+}
+
 #[test]
 fn test_genesis_is_not_replaceable() -> Result<(), BlockchainError> {
     let conf = blockchain::get_blockchain_config();
@@ -785,6 +1315,207 @@ fn test_genesis_is_not_replaceable() -> Result<(), BlockchainError> {
     Ok(())
 }
 
+#[test]
+fn test_pow_disabled() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+
+    // Practically unreachable target, blocks are applied without any mining
+    let mut conf = easy_config();
+    conf.genesis.block.header.proof_of_work.target = 0x08ffffff;
+    conf.pow_enabled = false;
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), conf.clone())?;
+    for ts in 1..20 {
+        let draft = chain
+            .draft_block(ts, &HashMap::new(), &miner, true)?
+            .unwrap();
+        chain.extend(ts as u64, &[draft.block])?;
+    }
+    assert_eq!(chain.get_height()?, 20);
+    assert_eq!(
+        chain.get_header(19)?.proof_of_work.target,
+        conf.genesis.block.header.proof_of_work.target
+    );
+
+    // The same blocks are rejected by a chain with PoW enabled
+    let mut pow_conf = conf.clone();
+    pow_conf.pow_enabled = true;
+    let mut pow_chain = KvStoreChain::new(db::RamKvStore::new(), pow_conf.clone())?;
+    assert!(matches!(
+        pow_chain.extend(1, &[chain.get_block(1)?]),
+        Err(BlockchainError::DifficultyTargetUnmet)
+    ));
+
+    // PoW can't be disabled on a chain started with PoW
+    let pow_chain = KvStoreChain::new(pow_chain.database, conf.clone());
+    assert!(matches!(
+        pow_chain,
+        Err(BlockchainError::PowCannotBeDisabled)
+    ));
+
+    // But a chain started without PoW can be reopened
+    let mut chain = KvStoreChain::new(chain.database, conf)?;
+    rollback_till_empty(&mut chain)?;
+
+    // The mainnet can't be started without PoW
+    let mut mainnet_conf = blockchain::get_blockchain_config();
+    mainnet_conf.pow_enabled = false;
+    assert!(matches!(
+        KvStoreChain::new(db::RamKvStore::new(), mainnet_conf),
+        Err(BlockchainError::PowCannotBeDisabled)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_genesis_rules() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+
+    // Genesis is exempt from PoW, even with a practically unreachable target.
+    // Its transactions draw from the Treasury without a miner reward.
+    let mut conf = easy_config();
+    conf.genesis.block.header.proof_of_work.target = 0x08ffffff;
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), conf.clone())?;
+    assert_eq!(chain.get_account(alice.get_address())?.balance, 10000);
+
+    // It can't be applied again on a non-empty chain
+    assert!(matches!(
+        chain.apply_block(&conf.genesis.block, false),
+        Err(BlockchainError::UnexpectedGenesis)
+    ));
+    assert!(matches!(
+        chain.extend(0, &[conf.genesis.block.clone()]),
+        Err(BlockchainError::ExtendFromGenesis)
+    ));
+
+    // Other blocks can only draw from the Treasury through the miner reward
+    let mut blk = chain
+        .draft_block(1, &HashMap::new(), &miner, false)?
+        .unwrap()
+        .block;
+    blk.body.push(Transaction {
+        src: Address::Treasury,
+        data: TransactionData::RegularSend {
+            dst: alice.get_address(),
+            amount: 100,
+        },
+        nonce: chain.get_account(Address::Treasury)?.nonce + 2,
+        fee: 0,
+        sig: Signature::Unsigned,
+    });
+    blk.header.block_root = blk.merkle_tree().root();
+    assert!(matches!(
+        chain.apply_block(&blk, false),
+        Err(BlockchainError::MultipleCoinbase)
+    ));
+
+    // ...and must have one
+    let mut blk = chain
+        .draft_block(1, &HashMap::new(), &miner, false)?
+        .unwrap()
+        .block;
+    blk.body.clear();
+    blk.header.block_root = blk.merkle_tree().root();
+    assert!(matches!(
+        chain.apply_block(&blk, false),
+        Err(BlockchainError::MinerRewardNotFound)
+    ));
+
+    // Only the genesis block may be applied on an empty chain
+    let blk = chain
+        .draft_block(1, &HashMap::new(), &miner, false)?
+        .unwrap()
+        .block;
+    chain.apply_block(&blk, false)?;
+    rollback_till_empty(&mut chain)?;
+    assert!(matches!(
+        chain.apply_block(&blk, false),
+        Err(BlockchainError::InvalidBlockNumber)
+    ));
+    chain.apply_block(&conf.genesis.block, true)?;
+    assert_eq!(chain.get_height()?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_too_many_transactions() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let mut conf = easy_config();
+    conf.max_txs_per_block = 3;
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), conf)?;
+
+    // Drafted blocks respect the cap
+    let txs = (1..=3)
+        .map(|nonce| alice.create_transaction(bob.get_address(), 10, 0, nonce))
+        .collect::<Vec<_>>();
+    let draft = chain
+        .draft_block(1, &with_dummy_stats(&txs), &miner, true)?
+        .unwrap();
+    assert_eq!(draft.block.body.len(), 3);
+    chain.fork_on_ram().apply_block(&draft.block, false)?;
+
+    // Unsigned transactions would fail the signature check, if it was reached
+    let mut blk = chain
+        .draft_block(1, &HashMap::new(), &miner, false)?
+        .unwrap()
+        .block;
+    for nonce in 1..=1000 {
+        let mut tx = alice.create_transaction(bob.get_address(), 10, 0, nonce).tx;
+        tx.sig = Signature::Unsigned;
+        blk.body.push(tx);
+    }
+    blk.header.block_root = blk.merkle_tree().root();
+    assert!(matches!(
+        chain.apply_block(&blk, false),
+        Err(BlockchainError::TooManyTransactions)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_multiple_coinbase() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let mempool = [(
+        alice.create_transaction(bob.get_address(), 100, 0, 1),
+        TransactionStats { first_seen: 0 },
+    )]
+    .into_iter()
+    .collect::<HashMap<_, _>>();
+    let draft = chain
+        .draft_block(1, &mempool, &miner, false)?
+        .unwrap()
+        .block;
+    assert_eq!(draft.body.len(), 2);
+
+    // A second reward, placed anywhere in the body, is rejected
+    for pos in [1, 2] {
+        let mut blk = draft.clone();
+        let mut coinbase = blk.body[0].clone();
+        coinbase.nonce += 1;
+        blk.body.insert(pos, coinbase);
+        blk.header.block_root = blk.merkle_tree().root();
+        assert!(matches!(
+            chain.apply_block(&blk, false),
+            Err(BlockchainError::MultipleCoinbase)
+        ));
+        assert_eq!(chain.get_height()?, 1);
+    }
+
+    chain.apply_block(&draft, false)?;
+    assert_eq!(chain.get_height()?, 2);
+
+    Ok(())
+}
+
 #[test]
 fn test_chain_should_apply_mined_draft_block() -> Result<(), BlockchainError> {
     let wallet_miner = Wallet::new(Vec::from("MINER"));
@@ -842,6 +1573,147 @@ fn test_chain_should_apply_mined_draft_block() -> Result<(), BlockchainError> {
     Ok(())
 }
 
+#[test]
+fn test_reward_only_blocks() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    for i in 0..3 {
+        let prev_power = chain.get_power()?;
+        let mut draft = chain
+            .draft_block(i + 1, &HashMap::new(), &miner, true)?
+            .unwrap();
+        assert_eq!(draft.block.body.len(), 1);
+        assert!(draft.patch.patches.is_empty());
+
+        mine_block(&chain, &mut draft)?;
+        chain.apply_block(&draft.block, true)?;
+        assert_eq!(chain.get_power()?, prev_power + draft.block.header.power());
+    }
+    assert_eq!(chain.get_height()?, 4);
+    assert!(chain.get_account(miner.get_address())?.balance > 0);
+    rollback_till_empty(&mut chain)?;
+
+    // Reward-only blocks can't satisfy the required MPN updates
+    let mut conf = easy_config();
+    conf.mpn_num_deposit_withdraws = 1;
+    let chain = KvStoreChain::new(db::RamKvStore::new(), conf)?;
+    assert!(chain
+        .draft_block(1, &HashMap::new(), &miner, true)?
+        .is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_lenient_headers_with_corrupt_header() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+    for i in 0..4 {
+        let mut draft = chain
+            .draft_block(i + 1, &HashMap::new(), &miner, true)?
+            .unwrap();
+        mine_block(&chain, &mut draft)?;
+        chain.apply_block(&draft.block, true)?;
+    }
+    let headers = chain.get_headers(0, None)?;
+    assert_eq!(headers.len(), 5);
+
+    chain
+        .database
+        .update(&[WriteOp::Put(height_key("header", 2), 0u32.into())])?;
+
+    assert!(chain.get_headers(0, None).is_err());
+    let (prefix, err) = chain.get_headers_lenient(0, None)?;
+    assert_eq!(prefix, headers[..2]);
+    assert!(matches!(err, Some(BlockchainError::KvStoreError(_))));
+
+    // Ranges not touching the corrupt header are unaffected
+    let (suffix, err) = chain.get_headers_lenient(3, Some(10))?;
+    assert_eq!(suffix, headers[3..]);
+    assert!(err.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_pow_is_not_checked_below_checkpoint() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let mut conf = blockchain::get_test_blockchain_config();
+    conf.genesis.block.header.proof_of_work.target = 0x0000ffff;
+
+    // Blocks which don't meet their PoW target
+    let mut source = KvStoreChain::new(db::RamKvStore::new(), conf.clone())?;
+    let mut blocks = Vec::new();
+    for i in 0..4 {
+        let blk = source
+            .draft_block(1650000000 + i, &HashMap::new(), &miner, true)?
+            .unwrap()
+            .block;
+        source.apply_block(&blk, false)?;
+        blocks.push(blk);
+    }
+
+    let checkpoint = Checkpoint {
+        height: 2,
+        hash: source.get_header(2)?.hash(),
+        power: source
+            .database
+            .get(height_key("power", 2))?
+            .unwrap()
+            .try_into()?,
+    };
+
+    let mut wrong_conf = conf.clone();
+    wrong_conf.checkpoint = Some(Checkpoint {
+        hash: source.get_header(1)?.hash(),
+        ..checkpoint.clone()
+    });
+    let mut wrong = KvStoreChain::new(db::RamKvStore::new(), wrong_conf)?;
+    assert!(matches!(
+        wrong.extend(1, &blocks[0..2]),
+        Err(BlockchainError::CheckpointMismatch)
+    ));
+    assert_eq!(wrong.get_height()?, 1);
+
+    conf.checkpoint = Some(checkpoint);
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), conf)?;
+
+    // Blocks not reaching the checkpoint aren't pinned down by it yet
+    assert!(matches!(
+        chain.apply_block(&blocks[0], true),
+        Err(BlockchainError::DifficultyTargetUnmet)
+    ));
+    assert!(matches!(
+        chain.will_extend(1, &[blocks[0].header.clone()], true),
+        Err(BlockchainError::DifficultyTargetUnmet)
+    ));
+
+    let headers = blocks[0..2]
+        .iter()
+        .map(|b| b.header.clone())
+        .collect::<Vec<_>>();
+    assert!(chain.will_extend(1, &headers, true)?);
+    chain.extend(1, &blocks[0..2])?;
+    assert!(matches!(
+        chain.apply_block(&blocks[2], true),
+        Err(BlockchainError::DifficultyTargetUnmet)
+    ));
+    assert_eq!(chain.get_height()?, 3);
+
+    // The chain is final up to the checkpoint
+    assert!(matches!(
+        chain.will_extend(2, &headers[1..], true),
+        Err(BlockchainError::ForkBelowCheckpoint)
+    ));
+    assert!(matches!(
+        chain.extend(1, &blocks[0..2]),
+        Err(BlockchainError::ForkBelowCheckpoint)
+    ));
+
+    Ok(())
+}
+
 #[test]
 fn test_chain_should_not_draft_invalid_transactions() -> Result<(), BlockchainError> {
     let wallet_miner = Wallet::new(Vec::from("MINER"));
@@ -1024,6 +1896,122 @@ fn test_chain_should_rollback_applied_block() -> Result<(), BlockchainError> {
     Ok(())
 }
 
+#[test]
+fn test_rollback_ops_restore_previous_values() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    let mempool = vec![alice.create_transaction(bob.get_address(), 100, 0, 1)];
+    let mut draft = chain
+        .draft_block(1650000000, &with_dummy_stats(&mempool), &miner, true)?
+        .unwrap();
+    mine_block(&chain, &mut draft)?;
+
+    let before = chain.database.pairs("".into())?;
+    chain.apply_block(&draft.block, true)?;
+    assert!(matches!(
+        chain.get_rollback_ops(2),
+        Err(BlockchainError::BlockNotFound)
+    ));
+
+    // Each op brings back the value the key had before the block
+    let ops = chain.get_rollback_ops(1)?;
+    assert!(!ops.is_empty());
+    for op in ops.iter() {
+        match op {
+            WriteOp::Put(k, v) => assert_eq!(before.get(k), Some(v)),
+            WriteOp::Remove(k) => assert!(!before.contains_key(k)),
+        }
+    }
+
+    // Reading the ops doesn't apply them
+    assert_eq!(chain.get_height()?, 2);
+    assert_eq!(chain.get_rollback_ops(1)?, ops);
+
+    chain.rollback()?;
+    for op in ops.iter() {
+        match op {
+            WriteOp::Put(k, v) => assert_eq!(chain.database.get(k.clone())?.as_ref(), Some(v)),
+            WriteOp::Remove(k) => assert!(chain.database.get(k.clone())?.is_none()),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_trusted_block_application_matches_verified() -> Result<(), BlockchainError> {
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let miner = Wallet::new(Vec::from("MINER"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+    let mut trusted_chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    for i in 0..3 {
+        let draft = chain
+            .draft_block(
+                i * 60,
+                &with_dummy_stats(&[alice.create_transaction(bob.get_address(), 100, 0, i + 1)]),
+                &miner,
+                true,
+            )?
+            .unwrap();
+        chain.apply_block(&draft.block, true)?;
+        trusted_chain.apply_block_checked(&draft.block, true, false)?;
+    }
+
+    assert_eq!(
+        chain.database.checksum::<Hasher>()?,
+        trusted_chain.database.checksum::<Hasher>()?
+    );
+    assert_eq!(trusted_chain.get_account(bob.get_address())?.balance, 300);
+
+    rollback_till_empty(&mut chain)?;
+    rollback_till_empty(&mut trusted_chain)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_version_bits_support() -> Result<(), BlockchainError> {
+    let miner = Wallet::new(Vec::from("MINER"));
+    let mut chain = KvStoreChain::new(db::RamKvStore::new(), easy_config())?;
+
+    // Only the genesis block may come without a version
+    let mut draft = chain
+        .draft_block(0, &HashMap::new(), &miner, true)?
+        .unwrap();
+    draft.block.header.version = 0;
+    mine_block(&chain, &mut draft)?;
+    assert!(matches!(
+        chain.apply_block(&draft.block, true),
+        Err(BlockchainError::UnsupportedHeaderVersion(0))
+    ));
+
+    for (i, version) in [2, 2, 1, 3].into_iter().enumerate() {
+        let mut draft = chain
+            .draft_block(i as u32 * 60, &HashMap::new(), &miner, true)?
+            .unwrap();
+        assert_eq!(draft.block.header.version, HEADER_VERSION);
+        draft.block.header.version = version;
+        mine_block(&chain, &mut draft)?;
+        chain.apply_block(&draft.block, true)?;
+    }
+
+    assert_eq!(chain.version_bits_support(2, 4)?, 0.75);
+    // Genesis block doesn't signal anything
+    assert_eq!(chain.version_bits_support(2, 5)?, 0.6);
+    assert_eq!(chain.version_bits_support(2, 100)?, 0.6);
+    assert_eq!(chain.version_bits_support(3, 2)?, 0.5);
+    assert_eq!(chain.version_bits_support(4, 4)?, 0.0);
+
+    rollback_till_empty(&mut chain)?;
+
+    Ok(())
+}
+
 fn mine_block<B: Blockchain>(chain: &B, draft: &mut BlockAndPatch) -> Result<(), BlockchainError> {
     let pow_key = chain.pow_key(draft.block.header.number)?;
 
@@ -0,0 +1,61 @@
+use flate2::read::{GzDecoder, GzEncoder};
+use flate2::Compression;
+use futures::stream::{self, StreamExt};
+use hyper::Body;
+use std::io::{Read, Write};
+
+pub const GZIP: &str = "gzip";
+
+pub fn accepts_gzip(accept_encoding: &str) -> bool {
+    accept_encoding
+        .split(',')
+        .any(|enc| enc.split(';').next().unwrap_or("").trim() == GZIP)
+}
+
+pub fn gzip_compress(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let mut compressed = Vec::new();
+    GzEncoder::new(data, Compression::default()).read_to_end(&mut compressed)?;
+    Ok(compressed)
+}
+
+// Compresses the body chunk by chunk, streamed bodies stay streamed
+pub fn gzip_compress_body(body: Body) -> Body {
+    let encoder = flate2::write::GzEncoder::new(Vec::new(), Compression::default());
+    Body::wrap_stream(stream::unfold(
+        (body, Some(encoder)),
+        |(mut body, encoder)| async move {
+            let mut encoder = encoder?;
+            match body.next().await {
+                Some(Ok(chunk)) => {
+                    let compressed = encoder
+                        .write_all(&chunk)
+                        .map(|_| std::mem::take(encoder.get_mut()));
+                    Some((compressed, (body, Some(encoder))))
+                }
+                Some(Err(e)) => Some((Err(std::io::Error::other(e)), (body, None))),
+                None => Some((encoder.finish(), (body, None))),
+            }
+        },
+    ))
+}
+
+// Decompressed data is capped to `limit` bytes, preventing decompression bombs
+pub fn gzip_decompress(data: &[u8], limit: Option<u64>) -> Result<Vec<u8>, std::io::Error> {
+    let mut decompressed = Vec::new();
+    let mut decoder = GzDecoder::new(data);
+    match limit {
+        Some(limit) => {
+            decoder.take(limit + 1).read_to_end(&mut decompressed)?;
+            if decompressed.len() as u64 > limit {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "decompressed data too big",
+                ));
+            }
+        }
+        None => {
+            decoder.read_to_end(&mut decompressed)?;
+        }
+    }
+    Ok(decompressed)
+}
@@ -29,12 +29,20 @@ pub enum NodeError {
     AddrParseError(#[from] std::net::AddrParseError),
     #[error("cannot parse account address: {0}")]
     AccountParseAddressError(#[from] crate::core::ParseAddressError),
+    #[error("cannot parse contract id: {0}")]
+    ContractIdParseError(#[from] crate::core::ParseContractIdError),
+    #[error("cannot parse state locator: {0}")]
+    LocatorParseError(#[from] crate::zk::ParseZkDataLocatorError),
     #[error("no wallet available")]
     NoWalletError,
     #[error("no block is currently being mined")]
     NoCurrentlyMiningBlockError,
+    #[error("miner stopped unexpectedly")]
+    MinerError,
     #[error("timeout reached: {0}")]
     TimeoutError(#[from] tokio::time::error::Elapsed),
+    #[error("io error happened: {0}")]
+    IoError(#[from] std::io::Error),
     #[error("http body size limit error")]
     SizeLimitError,
     #[error("bad input")]
@@ -43,4 +51,24 @@ pub enum NodeError {
     InvalidSignatureHeader,
     #[error("signature required on this message")]
     SignatureRequired,
+    #[error("request should be signed by the node's own key")]
+    AdminRequired,
+    #[error("too many open connections")]
+    TooManyConnections,
+    #[error("peer belongs to a different network")]
+    NetworkMagicMismatch,
+    #[error("sender has too many transactions in the mempool")]
+    SenderMempoolFull,
+    #[error("a conflicting contract update with a higher fee exists")]
+    ConflictingContractUpdate,
+    #[error("replacement doesn't pay more than the fees of the transactions it evicts")]
+    ReplacementFeeTooLow,
+    #[error("self-test failed: {0}")]
+    SelfTestFailed(String),
+    #[error("invalid node options: {0}")]
+    InvalidOptions(String),
+    #[error("non-canonical encoding")]
+    NonCanonicalEncoding,
+    #[error("request failed with status {0}")]
+    RequestFailed(hyper::StatusCode),
 }
@@ -0,0 +1,187 @@
+use crate::blockchain::FeeSplit;
+use crate::core::{
+    Account, Address, Block, ContractId, Header, Money, Transaction, TransactionData,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// Stable JSON representations of the chain data served to wallets and explorers.
+// These are intentionally decoupled from the internal (bincode) types, so that
+// refactoring the internals doesn't break third-party clients. Hashes are hex-encoded.
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct AccountResponse {
+    pub balance: Money,
+    pub nonce: u32,
+}
+
+impl From<Account> for AccountResponse {
+    fn from(acc: Account) -> Self {
+        Self {
+            balance: acc.balance,
+            nonce: acc.nonce,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct HeaderResponse {
+    pub hash: String,
+    pub version: u32,
+    pub parent_hash: String,
+    pub number: u64,
+    pub block_root: String,
+    pub timestamp: u32,
+    pub target: u32,
+    pub nonce: u64,
+}
+
+impl From<&Header> for HeaderResponse {
+    fn from(header: &Header) -> Self {
+        Self {
+            hash: hex::encode(header.hash()),
+            version: header.version,
+            parent_hash: hex::encode(header.parent_hash),
+            number: header.number,
+            block_root: hex::encode(header.block_root),
+            timestamp: header.proof_of_work.timestamp,
+            target: header.proof_of_work.target,
+            nonce: header.proof_of_work.nonce,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum TransactionDataResponse {
+    RegularSend {
+        dst: String,
+        amount: Money,
+    },
+    CreateContract {
+        contract_id: String,
+    },
+    UpdateContract {
+        contract_id: String,
+        num_updates: usize,
+    },
+    PauseContract {
+        contract_id: String,
+        paused: bool,
+    },
+    CreateOwnedContract {
+        contract_id: String,
+        owner: String,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct TransactionResponse {
+    pub hash: String,
+    pub src: String,
+    pub nonce: u32,
+    pub fee: Money,
+    pub data: TransactionDataResponse,
+}
+
+impl From<&Transaction> for TransactionResponse {
+    fn from(tx: &Transaction) -> Self {
+        Self {
+            hash: hex::encode(tx.hash()),
+            src: tx.src.to_string(),
+            nonce: tx.nonce,
+            fee: tx.fee,
+            data: match &tx.data {
+                TransactionData::RegularSend { dst, amount } => {
+                    TransactionDataResponse::RegularSend {
+                        dst: dst.to_string(),
+                        amount: *amount,
+                    }
+                }
+                TransactionData::CreateContract { .. } => TransactionDataResponse::CreateContract {
+                    contract_id: ContractId::new(tx).to_string(),
+                },
+                TransactionData::UpdateContract {
+                    contract_id,
+                    updates,
+                } => TransactionDataResponse::UpdateContract {
+                    contract_id: contract_id.to_string(),
+                    num_updates: updates.len(),
+                },
+                TransactionData::PauseContract {
+                    contract_id,
+                    paused,
+                } => TransactionDataResponse::PauseContract {
+                    contract_id: contract_id.to_string(),
+                    paused: *paused,
+                },
+                TransactionData::CreateOwnedContract { owner, .. } => {
+                    TransactionDataResponse::CreateOwnedContract {
+                        contract_id: ContractId::new(tx).to_string(),
+                        owner: owner.to_string(),
+                    }
+                }
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct BlockResponse {
+    pub header: HeaderResponse,
+    pub body: Vec<TransactionResponse>,
+}
+
+impl From<&Block> for BlockResponse {
+    fn from(block: &Block) -> Self {
+        Self {
+            header: (&block.header).into(),
+            body: block.body.iter().map(|tx| tx.into()).collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct BlockSummaryResponse {
+    pub height: u64,
+    pub hash: String,
+    pub timestamp: u32,
+    pub num_txs: usize,
+    pub total_fees: Money,
+    pub reward: Money,
+    pub miner: Option<String>,
+}
+
+impl BlockSummaryResponse {
+    pub fn new(block: &Block, fee_split: &FeeSplit) -> Self {
+        let total_fees = block.body.iter().map(|tx| tx.fee).sum();
+        // The miner reward is always the first transaction of a non-genesis block,
+        // paying the miner's share of the fees too, which is already counted in the
+        // total fees
+        let (reward, miner) = match block.body.first() {
+            Some(Transaction {
+                src: Address::Treasury,
+                data: TransactionData::RegularSend { dst, amount },
+                ..
+            }) if block.header.number > 0 => (
+                amount.saturating_sub(fee_split.split(total_fees).0),
+                Some(dst.to_string()),
+            ),
+            _ => (0, None),
+        };
+        Self {
+            height: block.header.number,
+            hash: hex::encode(block.header.hash()),
+            timestamp: block.header.proof_of_work.timestamp,
+            num_txs: block.body.len(),
+            total_fees,
+            reward,
+            miner,
+        }
+    }
+}
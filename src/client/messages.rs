@@ -1,51 +1,145 @@
-use crate::blockchain::ZkBlockchainPatch;
+use crate::blockchain::{TxSideEffect, ZkBlockchainPatch};
 use crate::core::{
-    Account, Address, Block, ContractId, ContractPayment, Header, Money, TransactionAndDelta,
+    Address, Block, ContractId, ContractPayment, Header, Money, Transaction, TransactionAndDelta,
 };
+use crate::db::WriteOp;
 use crate::zk;
 use std::collections::HashMap;
 
-use super::{Peer, PeerAddress, PeerInfo};
+use super::explorer::{
+    AccountResponse, BlockResponse, BlockSummaryResponse, HeaderResponse, TransactionResponse,
+};
+use super::{Histogram, Peer, PeerAddress, PeerInfo, PunishmentEvent, SyncStatus};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct GetStatsRequest {}
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct GetStatsResponse {
     pub height: u64,
     pub power: u128,
     pub next_reward: Money,
     pub timestamp: u32,
+    pub state_unavailable_peers: usize,
+    // Fraction of recent blocks signaling the header version of this node
+    pub version_support: f64,
+    // Recent blocks are much slower than the target block time
+    pub hashrate_warning: bool,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetMetricsRequest {}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetMetricsResponse {
+    pub open_connections: usize,
+    pub max_connections: usize,
+    pub num_active_peers: usize,
+    pub mempool_size: usize,
+    // Seconds between the timestamp of a block and its local application, per
+    // the peer the block was synced from
+    pub block_propagation: HashMap<String, Histogram>,
+    // Milliseconds taken by the sync requests, per peer
+    pub request_latency: HashMap<String, Histogram>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct GetAccountRequest {
     pub address: String,
+    #[serde(default)]
+    pub include_pending: bool,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetAccountsRequest {
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct AccountEntry {
+    pub address: String,
+    pub account: AccountResponse,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetAccountsResponse {
+    // Sorted by the address
+    pub accounts: Vec<AccountEntry>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct GetAccountResponse {
-    pub account: Account,
+    pub account: AccountResponse,
+    // False if the account has never been stored on the chain
+    pub exists: bool,
+    // The account as if the mempool transactions were confirmed (When requested)
+    pub pending: Option<AccountResponse>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetPendingBalanceRequest {
+    pub address: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetPendingBalanceResponse {
+    pub pending_delta: i128,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetContractInfoRequest {
+    pub contract_id: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetContractValueRequest {
+    pub id: String,
+    pub locator: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetContractValueResponse {
+    // Decimal representation of the scalar
+    pub value: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetContractInfoResponse {
+    pub state_model: zk::ZkStateModel,
+    pub log4_deposit_withdraw_capacity: u8,
+    pub num_functions: usize,
+    pub byte_size: u64,
+    pub storage_size: usize,
+    // Hex-encoded hashes of the verifier keys
+    pub deposit_withdraw_function: String,
+    pub functions: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct PostMinerSolutionRequest {
     pub nonce: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct PostMinerSolutionResponse {}
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct PostMinerSolutionResponse {
+    pub accepted: bool,
+    // Why the solution was rejected (E.g "stale" or "invalid nonce")
+    pub reason: Option<String>,
+}
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct GetMinerPuzzleRequest {}
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct GetMinerPuzzleResponse {
     pub puzzle: Option<Puzzle>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct Puzzle {
     pub key: String,   // Puzzle key encoded in hex
     pub blob: String,  // Blob encoded in hex
@@ -54,23 +148,25 @@ pub struct Puzzle {
     pub target: u32,   // Difficulty target
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct PostPeerRequest {
     pub address: PeerAddress,
     pub info: PeerInfo,
     pub timestamp: u32,
+    pub network_magic: u32,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct PostPeerResponse {
     pub info: PeerInfo,
     pub timestamp: u32,
+    pub network_magic: u32,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct GetPeersRequest {}
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct GetPeersResponse {
     pub peers: Vec<Peer>,
 }
@@ -82,9 +178,11 @@ pub struct PostBlockRequest {
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct PostBlockResponse {}
+pub struct PostBlockResponse {
+    pub already_known: bool,
+}
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct GetBlocksRequest {
     pub since: u64,
     pub until: Option<u64>,
@@ -103,6 +201,15 @@ pub struct GetOutdatedHeightsResponse {
     pub outdated_heights: HashMap<ContractId, u64>,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetOutdatedContractsRequest {}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetOutdatedContractsResponse {
+    // Current compressed-states of the contracts the full states are missing for
+    pub outdated_contracts: HashMap<ContractId, zk::ZkCompressedState>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct GetStatesRequest {
     pub outdated_heights: HashMap<ContractId, u64>,
@@ -115,6 +222,21 @@ pub struct GetStatesResponse {
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetStateChunksRequest {
+    pub outdated_heights: HashMap<ContractId, u64>,
+    pub to: String,
+    // Index of the requested chunk, for the full-states served in chunks
+    pub chunk: usize,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetStateChunksResponse {
+    pub patch: ZkBlockchainPatch,
+    // Some of the requested contracts were left out, due to the size limits
+    pub more_available: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct GetHeadersRequest {
     pub since: u64,
     pub until: Option<u64>,
@@ -125,6 +247,80 @@ pub struct GetHeadersResponse {
     pub headers: Vec<Header>,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetHeaderStreamRequest {
+    pub since: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetHeaderStreamResponse {
+    pub headers: Vec<Header>,
+    // Continuation token, the `since` of the next chunk. None when the tip is reached.
+    pub next: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetJsonHeadersResponse {
+    pub headers: Vec<HeaderResponse>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetJsonBlocksResponse {
+    pub blocks: Vec<BlockResponse>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetBlockSummariesRequest {
+    pub since: u64,
+    pub limit: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetBlockSummariesResponse {
+    pub summaries: Vec<BlockSummaryResponse>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetBlockRewardRequest {
+    pub index: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetBlockRewardResponse {
+    pub reward: Money,
+    // Sum of the fees paid by the transactions of the block
+    pub fees: Money,
+    pub total: Money,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetTransactionStatusRequest {
+    pub hash: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case", tag = "status")]
+pub enum GetTransactionStatusResponse {
+    Pending,
+    Confirmed { height: u64 },
+    Unknown,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct DecodeTxRequest {
+    // Hex encoded bincode of the transaction
+    pub tx: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct DecodeTxResponse {
+    pub tx: TransactionResponse,
+    pub valid_signature: bool,
+    pub size: usize,
+    // Fee paid per 1000 bytes
+    pub fee_rate: Money,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct GetBalanceRequest {
     pub addr: Address,
@@ -151,10 +347,99 @@ pub struct TransactZeroRequest {
 #[derive(Deserialize, Serialize, Debug)]
 pub struct TransactZeroResponse {}
 
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetSyncStatusRequest {}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetSyncStatusResponse {
+    pub status: SyncStatus,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetPunishmentsRequest {}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetPunishmentsResponse {
+    pub punishments: Vec<PunishmentEvent>,
+}
+
+// The node options which can be changed while the node is running
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+pub struct AdminOptions {
+    pub num_peers: usize,
+    pub outdated_heights_threshold: u32,
+    pub no_response_punish: u32,
+    pub invalid_data_punish: u32,
+    pub incorrect_power_punish: u32,
+    pub max_punish: u32,
+    pub state_unavailable_ban_time: u32,
+    pub max_txs_per_sender: usize,
+    pub max_concurrent_state_requests: usize,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetAdminOptionsRequest {}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct GetAdminOptionsResponse {
+    pub options: AdminOptions,
+}
+
+// Missing fields are left unchanged, fields which cannot be changed at runtime
+// are rejected.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PostAdminOptionsRequest {
+    #[serde(default)]
+    pub num_peers: Option<usize>,
+    #[serde(default)]
+    pub outdated_heights_threshold: Option<u32>,
+    #[serde(default)]
+    pub no_response_punish: Option<u32>,
+    #[serde(default)]
+    pub invalid_data_punish: Option<u32>,
+    #[serde(default)]
+    pub incorrect_power_punish: Option<u32>,
+    #[serde(default)]
+    pub max_punish: Option<u32>,
+    #[serde(default)]
+    pub state_unavailable_ban_time: Option<u32>,
+    #[serde(default)]
+    pub max_txs_per_sender: Option<usize>,
+    #[serde(default)]
+    pub max_concurrent_state_requests: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct PostAdminOptionsResponse {
+    pub options: AdminOptions,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetRollbackOpsRequest {
+    pub height: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetRollbackOpsResponse {
+    pub ops: Vec<WriteOp>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TraceTxRequest {
+    pub tx: Transaction,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TraceTxResponse {
+    pub ops: Vec<WriteOp>,
+    pub side_effect: TxSideEffect,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct ShutdownRequest {}
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, JsonSchema)]
 pub struct ShutdownResponse {}
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -173,3 +458,62 @@ pub struct TransactDepositWithdrawRequest {
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct TransactDepositWithdrawResponse {}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetSchemaRequest {}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetSchemaResponse {
+    pub definitions: schemars::Map<String, schemars::schema::Schema>,
+}
+
+// JSON schema definitions of all the types used in the JSON endpoints
+pub fn json_schema_definitions() -> schemars::Map<String, schemars::schema::Schema> {
+    let mut gen = schemars::gen::SchemaGenerator::default();
+    gen.subschema_for::<GetStatsRequest>();
+    gen.subschema_for::<GetStatsResponse>();
+    gen.subschema_for::<GetMetricsRequest>();
+    gen.subschema_for::<GetMetricsResponse>();
+    gen.subschema_for::<GetAccountRequest>();
+    gen.subschema_for::<GetAccountResponse>();
+    gen.subschema_for::<GetAccountsRequest>();
+    gen.subschema_for::<GetAccountsResponse>();
+    gen.subschema_for::<GetHeadersRequest>();
+    gen.subschema_for::<GetJsonHeadersResponse>();
+    gen.subschema_for::<GetBlocksRequest>();
+    gen.subschema_for::<GetJsonBlocksResponse>();
+    gen.subschema_for::<GetBlockSummariesRequest>();
+    gen.subschema_for::<GetBlockSummariesResponse>();
+    gen.subschema_for::<GetBlockRewardRequest>();
+    gen.subschema_for::<GetBlockRewardResponse>();
+    gen.subschema_for::<GetTransactionStatusRequest>();
+    gen.subschema_for::<GetTransactionStatusResponse>();
+    gen.subschema_for::<DecodeTxRequest>();
+    gen.subschema_for::<DecodeTxResponse>();
+    gen.subschema_for::<GetPendingBalanceRequest>();
+    gen.subschema_for::<GetPendingBalanceResponse>();
+    gen.subschema_for::<GetContractInfoRequest>();
+    gen.subschema_for::<GetContractInfoResponse>();
+    gen.subschema_for::<GetContractValueRequest>();
+    gen.subschema_for::<GetContractValueResponse>();
+    gen.subschema_for::<PostMinerSolutionRequest>();
+    gen.subschema_for::<PostMinerSolutionResponse>();
+    gen.subschema_for::<GetMinerPuzzleRequest>();
+    gen.subschema_for::<GetMinerPuzzleResponse>();
+    gen.subschema_for::<Puzzle>();
+    gen.subschema_for::<PostPeerRequest>();
+    gen.subschema_for::<PostPeerResponse>();
+    gen.subschema_for::<GetPeersRequest>();
+    gen.subschema_for::<GetPeersResponse>();
+    gen.subschema_for::<GetSyncStatusRequest>();
+    gen.subschema_for::<GetSyncStatusResponse>();
+    gen.subschema_for::<GetPunishmentsRequest>();
+    gen.subschema_for::<GetPunishmentsResponse>();
+    gen.subschema_for::<GetAdminOptionsRequest>();
+    gen.subschema_for::<GetAdminOptionsResponse>();
+    gen.subschema_for::<PostAdminOptionsRequest>();
+    gen.subschema_for::<PostAdminOptionsResponse>();
+    gen.subschema_for::<ShutdownRequest>();
+    gen.subschema_for::<ShutdownResponse>();
+    gen.take_definitions()
+}
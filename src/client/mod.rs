@@ -1,10 +1,12 @@
-use crate::core::{Address, ContractPayment, Signer, TransactionAndDelta};
+use crate::core::{Address, ContractId, ContractPayment, Signer, Transaction, TransactionAndDelta};
 use crate::crypto::ed25519;
 use crate::crypto::SignatureScheme;
 use crate::utils;
+use crate::zk;
 use hyper::body::HttpBody;
-use hyper::header::{HeaderValue, AUTHORIZATION};
+use hyper::header::{HeaderValue, ACCEPT_ENCODING, AUTHORIZATION, CONTENT_ENCODING};
 use hyper::{Body, Method, Request, Response};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 use std::sync::Arc;
@@ -12,14 +14,18 @@ use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::time::timeout;
 
+pub mod compression;
 mod error;
+pub mod explorer;
 pub mod messages;
 pub use error::NodeError;
 use messages::*;
 
 pub type Timestamp = u32;
 
-#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, JsonSchema,
+)]
 pub struct PeerAddress(pub SocketAddr); // ip, port
 
 impl std::fmt::Display for PeerAddress {
@@ -28,20 +34,70 @@ impl std::fmt::Display for PeerAddress {
     }
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
 pub struct PeerInfo {
     pub height: u64,
     pub power: u128,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
 pub struct Peer {
+    #[schemars(with = "Option<Vec<u8>>")]
     pub pub_key: Option<ed25519::PublicKey>,
     pub address: PeerAddress,
     pub punished_until: Timestamp,
     pub info: Option<PeerInfo>,
 }
 
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct SyncStatus {
+    pub tip_height: u64,
+    pub best_peer_height: u64,
+    pub best_peer_power: u128,
+    pub states_outdated: bool,
+    pub is_syncing: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum PunishmentReason {
+    NoResponse,
+    InvalidData,
+    IncorrectPower,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct PunishmentEvent {
+    pub peer: PeerAddress,
+    pub reason: PunishmentReason,
+    pub amount: u32,
+    pub timestamp: Timestamp,
+}
+
+// Number of observations falling under each of the (Increasing) bucket bounds, the
+// last count being the observations above all of the bounds
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Histogram {
+    pub bounds: Vec<u64>,
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    pub fn new(bounds: &[u64]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len() + 1],
+        }
+    }
+    pub fn observe(&mut self, value: u64) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|b| value <= *b)
+            .unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+    }
+}
+
 impl Peer {
     pub fn is_punished(&self) -> bool {
         utils::local_timestamp() < self.punished_until
@@ -84,7 +140,11 @@ impl Limit {
 }
 
 impl OutgoingSender {
-    pub async fn raw(&self, body: Request<Body>, limit: Limit) -> Result<Body, NodeError> {
+    pub async fn raw_response(
+        &self,
+        body: Request<Body>,
+        limit: &Limit,
+    ) -> Result<Response<Body>, NodeError> {
         let (resp_snd, mut resp_rcv) = mpsc::channel::<Result<Response<Body>, NodeError>>(1);
         let req = NodeRequest {
             socket_addr: None,
@@ -95,23 +155,52 @@ impl OutgoingSender {
             .send(req)
             .map_err(|_| NodeError::NotListeningError)?;
 
-        let body = if let Some(time_limit) = limit.time {
+        if let Some(time_limit) = limit.time {
             timeout(time_limit, resp_rcv.recv()).await?
         } else {
             resp_rcv.recv().await
         }
-        .ok_or(NodeError::NotAnsweringError)??
-        .into_body();
+        .ok_or(NodeError::NotAnsweringError)?
+    }
+
+    pub async fn raw(&self, body: Request<Body>, limit: Limit) -> Result<Body, NodeError> {
+        let resp = self.raw_response(body, &limit).await?;
+        if !resp.status().is_success() {
+            return Err(NodeError::RequestFailed(resp.status()));
+        }
+        let is_gzipped = resp
+            .headers()
+            .get(CONTENT_ENCODING)
+            .map(|v| v.as_bytes() == compression::GZIP.as_bytes())
+            .unwrap_or(false);
+        let mut body = resp.into_body();
 
         if let Some(size_limit) = limit.size {
             if body
                 .size_hint()
                 .upper()
                 .map(|u| u > size_limit)
-                .unwrap_or(true)
+                .unwrap_or(false)
             {
                 return Err(NodeError::SizeLimitError);
             }
+            // Streamed bodies don't know their size, the limit is checked while reading
+            let mut bytes = Vec::new();
+            while let Some(chunk) = body.data().await {
+                bytes.extend_from_slice(&chunk?);
+                if bytes.len() as u64 > size_limit {
+                    return Err(NodeError::SizeLimitError);
+                }
+            }
+            body = Body::from(bytes);
+        }
+
+        if is_gzipped {
+            let compressed = hyper::body::to_bytes(body).await?;
+            return Ok(Body::from(compression::gzip_decompress(
+                &compressed,
+                limit.size,
+            )?));
         }
 
         Ok(body)
@@ -141,7 +230,13 @@ impl OutgoingSender {
         limit: Limit,
     ) -> Result<Resp, NodeError> {
         let bytes = bincode::serialize(&req)?;
-        let req = self.sign(Request::builder().method(Method::GET).uri(&addr), bytes)?;
+        let req = self.sign(
+            Request::builder()
+                .method(Method::GET)
+                .uri(&addr)
+                .header(ACCEPT_ENCODING, compression::GZIP),
+            bytes,
+        )?;
         let body = self.raw(req, limit).await?;
         let resp: Resp = bincode::deserialize(&hyper::body::to_bytes(body).await?)?;
         Ok(resp)
@@ -159,7 +254,8 @@ impl OutgoingSender {
             Request::builder()
                 .method(Method::POST)
                 .uri(&addr)
-                .header("content-type", "application/octet-stream"),
+                .header("content-type", "application/octet-stream")
+                .header(ACCEPT_ENCODING, compression::GZIP),
             bytes,
         )?;
         let body = self.raw(req, limit).await?;
@@ -264,6 +360,15 @@ impl BazukaClient {
             )
             .await
     }
+    pub async fn schema(&self) -> Result<GetSchemaResponse, NodeError> {
+        self.sender
+            .json_get::<GetSchemaRequest, GetSchemaResponse>(
+                format!("{}/schema", self.peer),
+                GetSchemaRequest {},
+                Limit::default(),
+            )
+            .await
+    }
     pub async fn peers(&self) -> Result<GetPeersResponse, NodeError> {
         self.sender
             .json_get::<GetPeersRequest, GetPeersResponse>(
@@ -274,6 +379,83 @@ impl BazukaClient {
             .await
     }
 
+    pub async fn sync_status(&self) -> Result<GetSyncStatusResponse, NodeError> {
+        self.sender
+            .json_get::<GetSyncStatusRequest, GetSyncStatusResponse>(
+                format!("{}/sync-status", self.peer),
+                GetSyncStatusRequest {},
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn punishments(&self) -> Result<GetPunishmentsResponse, NodeError> {
+        self.sender
+            .json_get::<GetPunishmentsRequest, GetPunishmentsResponse>(
+                format!("{}/admin/punishments", self.peer),
+                GetPunishmentsRequest {},
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn admin_options(&self) -> Result<GetAdminOptionsResponse, NodeError> {
+        self.sender
+            .json_get::<GetAdminOptionsRequest, GetAdminOptionsResponse>(
+                format!("{}/admin/options", self.peer),
+                GetAdminOptionsRequest {},
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn set_admin_options(
+        &self,
+        req: PostAdminOptionsRequest,
+    ) -> Result<PostAdminOptionsResponse, NodeError> {
+        self.sender
+            .json_post::<PostAdminOptionsRequest, PostAdminOptionsResponse>(
+                format!("{}/admin/options", self.peer),
+                req,
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn balances_csv(&self) -> Result<String, NodeError> {
+        let req = self.sender.sign(
+            Request::builder()
+                .method(Method::GET)
+                .uri(format!("{}/admin/balances.csv", self.peer)),
+            vec![],
+        )?;
+        let body = self.sender.raw(req, Limit::default()).await?;
+        Ok(
+            String::from_utf8(hyper::body::to_bytes(body).await?.to_vec())
+                .map_err(|e| e.utf8_error())?,
+        )
+    }
+
+    pub async fn rollback_ops(&self, height: u64) -> Result<GetRollbackOpsResponse, NodeError> {
+        self.sender
+            .json_get::<GetRollbackOpsRequest, GetRollbackOpsResponse>(
+                format!("{}/admin/rollback-ops", self.peer),
+                GetRollbackOpsRequest { height },
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn trace_tx(&self, tx: Transaction) -> Result<TraceTxResponse, NodeError> {
+        self.sender
+            .bincode_post::<TraceTxRequest, TraceTxResponse>(
+                format!("{}/admin/trace-tx", self.peer),
+                TraceTxRequest { tx },
+                Limit::default(),
+            )
+            .await
+    }
+
     pub async fn get_zero_mempool(&self) -> Result<GetZeroMempoolResponse, NodeError> {
         self.sender
             .bincode_get::<GetZeroMempoolRequest, GetZeroMempoolResponse>(
@@ -307,12 +489,185 @@ impl BazukaClient {
             .await
     }
 
-    pub async fn get_account(&self, address: Address) -> Result<GetAccountResponse, NodeError> {
+    pub async fn outdated_contracts(&self) -> Result<GetOutdatedContractsResponse, NodeError> {
+        self.sender
+            .bincode_get::<GetOutdatedContractsRequest, GetOutdatedContractsResponse>(
+                format!("{}/bincode/outdated-contracts", self.peer),
+                GetOutdatedContractsRequest {},
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn get_account(
+        &self,
+        address: Address,
+        include_pending: bool,
+    ) -> Result<GetAccountResponse, NodeError> {
         self.sender
             .json_get::<GetAccountRequest, GetAccountResponse>(
                 format!("{}/account", self.peer),
                 GetAccountRequest {
                     address: address.to_string(),
+                    include_pending,
+                },
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn metrics(&self) -> Result<GetMetricsResponse, NodeError> {
+        self.sender
+            .json_get::<GetMetricsRequest, GetMetricsResponse>(
+                format!("{}/metrics", self.peer),
+                GetMetricsRequest {},
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn json_headers(
+        &self,
+        since: u64,
+        until: Option<u64>,
+    ) -> Result<GetJsonHeadersResponse, NodeError> {
+        self.sender
+            .json_get::<GetHeadersRequest, GetJsonHeadersResponse>(
+                format!("{}/headers", self.peer),
+                GetHeadersRequest { since, until },
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn json_blocks(
+        &self,
+        since: u64,
+        until: Option<u64>,
+    ) -> Result<GetJsonBlocksResponse, NodeError> {
+        self.sender
+            .json_get::<GetBlocksRequest, GetJsonBlocksResponse>(
+                format!("{}/blocks", self.peer),
+                GetBlocksRequest { since, until },
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn accounts(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<GetAccountsResponse, NodeError> {
+        self.sender
+            .json_get::<GetAccountsRequest, GetAccountsResponse>(
+                format!("{}/accounts", self.peer),
+                GetAccountsRequest { offset, limit },
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn block_summaries(
+        &self,
+        since: u64,
+        limit: u64,
+    ) -> Result<GetBlockSummariesResponse, NodeError> {
+        self.sender
+            .json_get::<GetBlockSummariesRequest, GetBlockSummariesResponse>(
+                format!("{}/block-summaries", self.peer),
+                GetBlockSummariesRequest { since, limit },
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn header_stream(&self, since: u64) -> Result<GetHeaderStreamResponse, NodeError> {
+        self.sender
+            .bincode_get::<GetHeaderStreamRequest, GetHeaderStreamResponse>(
+                format!("{}/bincode/header-stream", self.peer),
+                GetHeaderStreamRequest { since },
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn block_reward(&self, index: u64) -> Result<GetBlockRewardResponse, NodeError> {
+        self.sender
+            .json_get::<GetBlockRewardRequest, GetBlockRewardResponse>(
+                format!("{}/block-reward", self.peer),
+                GetBlockRewardRequest { index },
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn transaction_status(
+        &self,
+        hash: String,
+    ) -> Result<GetTransactionStatusResponse, NodeError> {
+        self.sender
+            .json_get::<GetTransactionStatusRequest, GetTransactionStatusResponse>(
+                format!("{}/tx", self.peer),
+                GetTransactionStatusRequest { hash },
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn decode_tx(&self, tx: &Transaction) -> Result<DecodeTxResponse, NodeError> {
+        self.sender
+            .json_post::<DecodeTxRequest, DecodeTxResponse>(
+                format!("{}/tx/decode", self.peer),
+                DecodeTxRequest {
+                    tx: hex::encode(bincode::serialize(tx)?),
+                },
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn pending_balance(
+        &self,
+        address: Address,
+    ) -> Result<GetPendingBalanceResponse, NodeError> {
+        self.sender
+            .json_get::<GetPendingBalanceRequest, GetPendingBalanceResponse>(
+                format!("{}/account/pending", self.peer),
+                GetPendingBalanceRequest {
+                    address: address.to_string(),
+                },
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn contract_value(
+        &self,
+        contract_id: ContractId,
+        locator: zk::ZkDataLocator,
+    ) -> Result<GetContractValueResponse, NodeError> {
+        self.sender
+            .json_get::<GetContractValueRequest, GetContractValueResponse>(
+                format!("{}/contract-value", self.peer),
+                GetContractValueRequest {
+                    id: contract_id.to_string(),
+                    locator: locator.to_string(),
+                },
+                Limit::default(),
+            )
+            .await
+    }
+
+    pub async fn contract_info(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<GetContractInfoResponse, NodeError> {
+        self.sender
+            .json_get::<GetContractInfoRequest, GetContractInfoResponse>(
+                format!("{}/contract/info", self.peer),
+                GetContractInfoRequest {
+                    contract_id: contract_id.to_string(),
                 },
                 Limit::default(),
             )
@@ -354,7 +709,7 @@ impl BazukaClient {
     }
 }
 
-fn mine_puzzle(puzzle: &Puzzle) -> PostMinerSolutionRequest {
+pub fn mine_puzzle(puzzle: &Puzzle) -> PostMinerSolutionRequest {
     let key = hex::decode(&puzzle.key).unwrap();
     let mut blob = hex::decode(&puzzle.blob).unwrap();
     let mut nonce = 0u64;
@@ -1,4 +1,4 @@
-use crate::blockchain::{BlockAndPatch, BlockchainConfig, ZkBlockchainPatch};
+use crate::blockchain::{BlockAndPatch, BlockchainConfig, FeeSplit, ZkBlockchainPatch};
 use crate::core::{
     Address, Block, ContractId, Header, ProofOfWork, Signature, Transaction, TransactionAndDelta,
     TransactionData, ZkHasher,
@@ -12,9 +12,10 @@ use crate::wallet::Wallet;
 const MPN_LOG4_ACCOUNT_CAPACITY: u8 = 10;
 
 lazy_static! {
-    pub static ref MPN_CONTRACT_ID :ContractId =ContractId::from_str(
+    pub static ref MPN_CONTRACT_ID: ContractId = ContractId::from_str(
         "cf7a4a415d4855b07eca3f2e56bbb9906ecf882ed384d2858e9bda8a90ade641",
-    ).unwrap();
+    )
+    .unwrap();
     pub static ref MPN_UPDATE_VK: zk::groth16::Groth16VerifyingKey =
         bincode::deserialize(&hex::decode("cfcab6bcb1f6d515710e0e7d5270a137a71f0e2c0f01f45fbeeb218d8e2cf472d60ca1fd93a60de2d8bcfcc1c96e2b149d2e0021cda15e551e7978ff370c79c9e2405d8fd5bcf2e2ebc531328f923ba8f2012b11ed0f2b22bd3a35f6c51f2207007e17daa6f4bddff1241c2b0dd7a1e99212474bf927af1be13076e77b530019c10720abd2c2503004ef930a3632d074037e4cd19669a324d6ac00a24011fb47704ae4d5183993e8005449594b7bd75b3c0976a101c705a5f47b60631e146ee510008bf8fc082620d255ea015e836f285ee66b03617ac0408769aee6af084f33dbe92cc2537af6445d1b4c456bd0ac59d60d98f43fd5bcf5848407d70dc0b03b508304d82bf37e8466ccec1d4c944144f7f623b265d92af0cedcbf5ba05f9683e70c263ff55d0787b15769d49f090d22ef5042c845db297e35dab461a243001b7c5dde191607057b8a380bb092bbfb4e2700a19266e7689c2c90d0a9cfa55648e899dbf83f5009e82a216fe820a4cfd75b4fc6f1a9f2e1e0556c4d0d597d2f2db90000e748cd7ca0bc8ff80287d38107d8ce9c498906658cfcf71a9bf311a1a8394b3c56a73707013b1d25708d2c8b6cee63034b3a2b202e5d2e792cf41ec4a5ec7bfd502b82330fc626532e21af780819f70b72c2175790a84f0f9265121686bc120c4c11f9145450fbea56ad05b4e0a243356d5ba93843ff7055831fc1a20c3a9a759c9b497f2ed38307a777f3a58fc2e9160026e390180f2880c48b663400bb711306bc0048410bb1e601f06d0bd7c596a6979991365f0f48ee3aa14942b561f60d003a7aa615c344911aa99114b21ef36331c655818f614c2c92e925b91042a1ceda52f7767d8d2afcffeed6a15771441507d60f4b4c4429e9122c9fd852d03e3b8b70753e2fbce909cee71d506b7aa7195b221f4d13a63e34649fff5e8e526c331500bd7436834ea206aa3837a65b0b75f9ac9f881e21ec81562cf51a0292dab698ea7322cb95eb7ce1487b9b081bfee11b1955c385f2e5c27e36546ec2dae546d1fb13afa16f3bf93671c9da0320980899b175c9d0069f3411f564e9f3c2ac2d120bd1b5d90f8e58707d5583c9898dd8e5b31dfdf9d143ddc69987ee760ae06ea6377dde6b1379788948da6b2397044be609187c06757fddeaaf190806699654119e5b411ac53203a7627e6cb3d86a64ac584dc0b6f55c651acbec784bc47ef9ad030004000000000000000087181f4425793626a433ac393cde1f8030608fd1257bcfc2393f31a4b023d6b3e68e10195ced8c4638ec2ab9b5890e42a0a836ea9c52e0d205a37e009cd0753afcc944d943265745eaafac24add07d243915b5fe21d2e9bc93e440adad560100fa8089ecd1f826cfb7c097614ae6360d9cd7a6afb77b2da18969fe3e62fd23b90798b41e5231e5c92ab532d710805001c96f1018b02c26579906950cb6e38364f2be3cf00da20001070b0884e69cad38f55fc259422dc0e5c60174b6fd8c460d0081977fb90d7a2478c138a534ae2f9212ad31be8066b87928ba6b4fb17943e6b0a5b50c0744fa7660d8f94a61b00a6a1217620a99e77e941986e1f8f6fa3906bafd844a267b4ef49c64052cb06d3af7670c4beb3885e37b853d3813c4ab7f76030097037c238deb213c1a17737e17784c8bcf74fc53e5a3ecda80d21326869e28cfb61c7b64d7277ec5cba2094f56e7f40d03cab73d4dc30d861e08c859b5feb3f204b9fa68814060b6c465be639961909603d8d6eb52fe2cb7cab11cd00dfa940200").unwrap()).unwrap();
     pub static ref MPN_DEPOSIT_WITHDRAW_VK: zk::groth16::Groth16VerifyingKey =
@@ -45,7 +46,7 @@ fn get_mpn_contract() -> TransactionAndDelta {
     let mpn_contract_create_tx = Transaction {
         src: Address::Treasury,
         data: TransactionData::CreateContract {
-            contract: mpn_contract,
+            contract: Box::new(mpn_contract),
         },
         nonce: 2,
         fee: 0,
@@ -94,6 +95,7 @@ pub fn get_blockchain_config() -> BlockchainConfig {
 
     let blk = Block {
         header: Header {
+            version: 0,
             parent_hash: Default::default(),
             number: 0,
             block_root: Default::default(),
@@ -132,10 +134,17 @@ pub fn get_blockchain_config() -> BlockchainConfig {
                 .collect(),
             },
         },
+        network_magic: 0x7a696b30,                   // "zik0"
         total_supply: 2_000_000_000_000_000_000_u64, // 2 Billion ZIK
         reward_ratio: 100_000, // 1/100_000 -> 0.01% of Treasury Supply per block
-        max_delta_size: 1024 * 1024, // Bytes
-        block_time: 60,        // Seconds
+        max_block_size: 1024 * 1024, // Bytes
+        max_delta_size: 1024 * 1024, // Scalars
+        max_state_patch_size: 1 << 23, // Scalars
+        max_contracts_per_block: 16,
+        max_proof_verifications_per_block: 256,
+        max_txs_per_block: 8192,
+        enforce_nonce_order: false,
+        block_time: 60,                // Seconds
         difficulty_calc_interval: 128, // Blocks
 
         // 0 63 -> BAZUKA BASE KEY
@@ -150,14 +159,33 @@ pub fn get_blockchain_config() -> BlockchainConfig {
         // New block's timestamp should be higher than median
         // timestamp of 10 previous blocks
         median_timestamp_count: 10,
+        // Timestamps are only bounded by the median
+        max_timestamp_regression: None,
+        max_ram_forks: 16,
+        pow_enabled: true,
 
         // We expect a minimum number of MPN contract updates
         // in a block to consider it valid
         mpn_num_function_calls: 0,
         mpn_num_deposit_withdraws: 1,
+
+        // No trusted checkpoint, the PoW of every header is verified
+        checkpoint: None,
+        // All of the fees are burnt
+        fee_split: FeeSplit {
+            miner_bps: 0,
+            treasury_bps: 0,
+            burn_bps: 10000,
+        },
+        allow_empty_blocks: true,
     }
 }
 
+#[cfg(test)]
+pub fn get_test_mpn_contract_id() -> ContractId {
+    ContractId::new(&get_test_mpn_contract().tx)
+}
+
 #[cfg(test)]
 pub fn get_test_blockchain_config() -> BlockchainConfig {
     let mpn_tx_delta = get_test_mpn_contract();
@@ -1,4 +1,6 @@
+use crate::blockchain::FifoStrategy;
 use crate::node::NodeOptions;
+use std::sync::Arc;
 use std::time::Duration;
 
 pub fn get_node_options() -> NodeOptions {
@@ -10,7 +12,38 @@ pub fn get_node_options() -> NodeOptions {
         incorrect_power_punish: 12,
         max_punish: 15,
         outdated_heights_threshold: 10,
+        state_failure_threshold: 10,
         state_unavailable_ban_time: 20,
+        max_txs_per_sender: 64,
+        max_banned_headers: 1024,
+        max_punishment_events: 256,
+        http_keep_alive: true,
+        http_header_read_timeout: Duration::from_secs(10),
+        http_tcp_keep_alive: Some(Duration::from_secs(60)),
+        http_body_read_timeout: Duration::from_secs(10),
+        http_idle_timeout: Duration::from_secs(60),
+        max_connections: 1024,
+        solo_mine: false,
+        mempool_strategy: Arc::new(FifoStrategy),
+        max_concurrent_state_requests: 4,
+        state_chunk_size: 8192,
+        run_self_test: true,
+        sync_failure_threshold: 5,
+        sync_cooldown: 60,
+        startup_grace_period: 30,
+        mempool_path: None,
+        mempool_persist_interval: 60,
+        max_state_patch_contracts: 32,
+        max_state_patch_bytes: 16 * 1024 * 1024,
+        header_stream_chunk_size: 1024,
+        hashrate_warning_window: 16,
+        hashrate_warning_factor: 4.0,
+        peer_info_refresh_interval: 30,
+        peer_info_ttl: 120,
+        reject_non_canonical_encodings: true,
+        mempool_max_age: 24 * 60 * 60,
+        mempool_capacity: 10000,
+        events: None,
     }
 }
 
@@ -23,6 +56,37 @@ pub fn get_test_node_options() -> NodeOptions {
         incorrect_power_punish: 0,
         max_punish: 0,
         outdated_heights_threshold: 5,
+        state_failure_threshold: 5,
         state_unavailable_ban_time: 10,
+        max_txs_per_sender: 4,
+        max_banned_headers: 16,
+        max_punishment_events: 8,
+        http_keep_alive: true,
+        http_header_read_timeout: Duration::from_secs(1),
+        http_tcp_keep_alive: None,
+        http_body_read_timeout: Duration::from_secs(1),
+        http_idle_timeout: Duration::from_secs(5),
+        max_connections: 16,
+        solo_mine: false,
+        mempool_strategy: Arc::new(FifoStrategy),
+        max_concurrent_state_requests: 2,
+        state_chunk_size: 64,
+        run_self_test: false,
+        sync_failure_threshold: 3,
+        sync_cooldown: 5,
+        startup_grace_period: 0,
+        mempool_path: None,
+        mempool_persist_interval: 1,
+        max_state_patch_contracts: 16,
+        max_state_patch_bytes: 1024 * 1024,
+        header_stream_chunk_size: 16,
+        hashrate_warning_window: 5,
+        hashrate_warning_factor: 4.0,
+        peer_info_refresh_interval: 2,
+        peer_info_ttl: 10,
+        reject_non_canonical_encodings: true,
+        mempool_max_age: 60,
+        mempool_capacity: 100,
+        events: None,
     }
 }
@@ -6,6 +6,18 @@ pub mod pow {
     lazy_static! {
         pub static ref HASHER: Arc<Mutex<HashMap<Vec<u8>, Hasher>>> =
             Arc::new(Mutex::new(HashMap::new()));
+        pub static ref CONTEXTS: Arc<Mutex<HashMap<Vec<u8>, Arc<Context>>>> =
+            Arc::new(Mutex::new(HashMap::new()));
+    }
+
+    // A standalone hasher, so that multiple threads can hash in parallel. The
+    // (expensive) context of the key is shared between them.
+    pub fn new_hasher(key: &[u8]) -> Hasher {
+        let mut contexts = CONTEXTS.lock().unwrap();
+        let context = contexts
+            .entry(key.to_vec())
+            .or_insert_with(|| Arc::new(Context::new(key, false)));
+        Hasher::new(Arc::clone(context))
     }
 
     pub fn hash(key: &[u8], input: &[u8]) -> Output {
@@ -1,4 +1,5 @@
-use super::Money;
+use super::hash::Hash;
+use super::{Hasher, Money};
 use crate::crypto::SignatureScheme;
 use std::str::FromStr;
 use thiserror::Error;
@@ -12,6 +13,16 @@ pub enum Address<S: SignatureScheme> {
     PublicKey(S::Pub),
 }
 
+impl<S: SignatureScheme + PartialEq> Eq for Address<S> {}
+impl<S: SignatureScheme> std::hash::Hash for Address<S> {
+    fn hash<Hasher>(&self, state: &mut Hasher)
+    where
+        Hasher: std::hash::Hasher,
+    {
+        state.write(&bincode::serialize(self).unwrap());
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum ParseAddressError {
     #[error("address invalid")]
@@ -27,6 +38,29 @@ impl<S: SignatureScheme> std::fmt::Display for Address<S> {
     }
 }
 
+// Characters kept from each end of an address in its short form
+const SHORT_ADDRESS_CHARS: usize = 6;
+
+impl<S: SignatureScheme> Address<S> {
+    // Truncated form of the address for logs and UIs, e.g. `0x3a91f0..c4e7d2#5b0e`.
+    // The trailing checksum covers the whole address, so that addresses sharing
+    // both ends are still told apart. It cannot be parsed back into an address.
+    pub fn short(&self) -> String {
+        let full = self.to_string();
+        if full.len() <= 2 * SHORT_ADDRESS_CHARS + 2 {
+            return full;
+        }
+        let checksum = Hasher::hash(full.as_bytes());
+        format!(
+            "{}..{}#{:02x}{:02x}",
+            &full[..SHORT_ADDRESS_CHARS + 2],
+            &full[full.len() - SHORT_ADDRESS_CHARS..],
+            checksum[0],
+            checksum[1]
+        )
+    }
+}
+
 impl<S: SignatureScheme> FromStr for Address<S>
 where
     <S::Pub as FromStr>::Err: std::fmt::Debug,
@@ -45,8 +79,30 @@ pub enum Signature<S: SignatureScheme> {
     Signed(S::Sig),
 }
 
-#[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug, Clone)]
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug, Clone, schemars::JsonSchema)]
 pub struct Account {
     pub balance: Money,
     pub nonce: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::wallet::Wallet;
+
+    #[test]
+    fn test_short_address_is_stable() {
+        let addr = Wallet::new(Vec::from("ABC")).get_address();
+        let full = addr.to_string();
+        let short = addr.short();
+
+        assert_eq!(short, addr.short());
+        assert_eq!(short, full.parse::<crate::core::Address>().unwrap().short());
+        assert!(short.starts_with(&full[..8]));
+        assert!(short.contains(&format!("{}#", &full[full.len() - 6..])));
+        assert!(short.len() < full.len());
+
+        let other = Wallet::new(Vec::from("CBA")).get_address();
+        assert_ne!(short, other.short());
+        assert_eq!(crate::core::Address::Treasury.short(), "Treasury");
+    }
+}
@@ -2,6 +2,14 @@ use rust_randomx::Difficulty;
 
 use super::hash::Hash;
 
+/// Header version of the blocks produced by this software. Miners signal
+/// readiness for a consensus change by bumping it.
+pub const HEADER_VERSION: u32 = 1;
+
+/// Oldest header version accepted after the genesis block. Newer versions are
+/// accepted too, as they are how the miners of upgraded nodes signal.
+pub const MIN_HEADER_VERSION: u32 = 1;
+
 #[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize, Hash)]
 pub struct ProofOfWork {
     /// when the miner started mining this block
@@ -14,6 +22,8 @@ pub struct ProofOfWork {
 
 #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, Hash)]
 pub struct Header<H: Hash> {
+    /// version signaled by the miner
+    pub version: u32,
     /// the parent hash
     pub parent_hash: H::Output,
     /// block number or block height
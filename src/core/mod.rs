@@ -28,6 +28,9 @@ pub type Header = header::Header<Hasher>;
 pub type Block = blocks::Block<Hasher, Signer, ZkSigner>;
 
 pub type ProofOfWork = header::ProofOfWork;
+pub use header::{HEADER_VERSION, MIN_HEADER_VERSION};
+pub use transaction::fee_rate;
 pub type ContractId = transaction::ContractId<Hasher>;
+pub type ParseContractIdError = transaction::ParseContractIdError;
 
 pub type TransactionAndDelta = transaction::TransactionAndDelta<Hasher, Signer, ZkSigner>;
@@ -80,6 +80,9 @@ impl<H: Hash, S: SignatureScheme, ZS: ZkSignatureScheme> std::hash::Hash
 }
 
 impl<H: Hash, S: SignatureScheme, ZS: ZkSignatureScheme> ContractPayment<H, S, ZS> {
+    pub fn hash(&self) -> H::Output {
+        H::hash(&bincode::serialize(self).unwrap())
+    }
     pub fn verify_signature(&self) -> bool {
         let mut unsigned = self.clone();
         unsigned.direction = match &unsigned.direction {
@@ -106,6 +109,7 @@ pub struct ContractAccount {
     pub nonce: u32,
     pub balance: Money,
     pub compressed_state: ZkCompressedState,
+    pub byte_size: u64,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug, Clone)]
@@ -135,13 +139,25 @@ pub enum TransactionData<H: Hash, S: SignatureScheme, ZS: ZkSignatureScheme> {
     // Create a Zero-Contract. The creator can consider multiple ways (Circuits) of updating
     // the state. But there should be only one circuit for entering and exiting the contract.
     CreateContract {
-        contract: ZkContract,
+        contract: Box<ZkContract>,
     },
     // Collection of contract updates
     UpdateContract {
         contract_id: ContractId<H>,
         updates: Vec<ContractUpdate<H, S, ZS>>,
     },
+    // Emergency stop of a (Probably buggy) contract, only its owner can (un)pause it
+    PauseContract {
+        contract_id: ContractId<H>,
+        paused: bool,
+    },
+    // Same as `CreateContract`, but only the owner can update (Or pause) the contract.
+    // The owner is not part of the contract itself, so that the encoding (And ids) of
+    // ownerless contracts are not affected.
+    CreateOwnedContract {
+        contract: Box<ZkContract>,
+        owner: Address<S>,
+    },
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
@@ -167,10 +183,33 @@ impl<H: Hash, S: SignatureScheme, ZS: ZkSignatureScheme> PartialEq<TransactionAn
     }
 }
 
+// Fee paid per 1000 bytes. Empty transactions are considered to have a single byte.
+pub fn fee_rate(fee: Money, size: usize) -> Money {
+    (fee as u128 * 1000 / size.max(1) as u128).min(Money::MAX as u128) as Money
+}
+
+impl<H: Hash, S: SignatureScheme, ZS: ZkSignatureScheme> TransactionAndDelta<H, S, ZS> {
+    // Size of the transaction along with its state delta
+    pub fn size(&self) -> usize {
+        self.tx.size()
+            + self
+                .state_delta
+                .as_ref()
+                .map(|d| bincode::serialize(d).unwrap().len())
+                .unwrap_or(0)
+    }
+    pub fn fee_rate(&self) -> Money {
+        fee_rate(self.tx.fee, self.size())
+    }
+}
+
 impl<H: Hash, S: SignatureScheme, ZS: ZkSignatureScheme> Transaction<H, S, ZS> {
     pub fn size(&self) -> usize {
         bincode::serialize(self).unwrap().len()
     }
+    pub fn fee_rate(&self) -> Money {
+        fee_rate(self.fee, self.size())
+    }
     pub fn hash(&self) -> H::Output {
         H::hash(&bincode::serialize(self).unwrap())
     }
@@ -205,3 +244,55 @@ impl<H: Hash, S: SignatureScheme, ZS: ZkSignatureScheme> std::hash::Hash
         state.finish();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Address, Signature, Transaction, TransactionAndDelta, TransactionData};
+    use crate::zk::ZkDataLocator;
+
+    #[test]
+    fn test_fee_rate_accounts_for_size() {
+        let small = Transaction {
+            src: Address::Treasury,
+            nonce: 1,
+            data: TransactionData::RegularSend {
+                dst: Address::Treasury,
+                amount: 100,
+            },
+            fee: 500,
+            sig: Signature::Unsigned,
+        };
+        let mut big = small.clone();
+        big.data = TransactionData::UpdateContract {
+            contract_id: ContractId::from_str(
+                "0000000000000000000000000000000000000000000000000000000000000000",
+            )
+            .unwrap(),
+            updates: vec![],
+        };
+        assert!(big.size() > small.size());
+        assert!(big.fee_rate() < small.fee_rate());
+        assert_eq!(small.fee_rate(), 500 * 1000 / small.size() as Money);
+
+        // The state delta counts towards the size
+        let without_delta = TransactionAndDelta {
+            tx: small.clone(),
+            state_delta: None,
+        };
+        let with_delta = TransactionAndDelta {
+            tx: small.clone(),
+            state_delta: Some(ZkDeltaPairs(
+                [(ZkDataLocator(vec![1]), Some(ZkScalar::from(2)))]
+                    .into_iter()
+                    .collect(),
+            )),
+        };
+        assert_eq!(without_delta.fee_rate(), small.fee_rate());
+        assert!(with_delta.fee_rate() < without_delta.fee_rate());
+
+        // Zero fees never divide by zero
+        assert_eq!(fee_rate(0, 0), 0);
+        assert_eq!(fee_rate(7, 0), 7000);
+    }
+}
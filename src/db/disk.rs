@@ -12,6 +12,7 @@ use leveldb::kv::KV;
 use leveldb::options::{Options, ReadOptions, WriteOptions};
 use std::fs;
 use std::path::Path;
+use std::time::Duration;
 use tempdir::TempDir;
 
 pub struct ReadOnlyLevelDbKvStore(Database<StringKey>);
@@ -37,14 +38,39 @@ impl ReadOnlyLevelDbKvStore {
     }
 }
 
+// A restarting node might still be holding the lock for a moment
+const LOCK_RETRIES: usize = 3;
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(200);
+
+fn is_lock_error(e: &leveldb::error::Error) -> bool {
+    e.to_string().to_lowercase().contains("lock")
+}
+
 pub struct LevelDbKvStore(Database<StringKey>);
 impl LevelDbKvStore {
     pub fn new(path: &Path, cache_size: usize) -> Result<LevelDbKvStore, KvStoreError> {
         fs::create_dir_all(&path)?;
-        let mut options = Options::new();
-        options.create_if_missing = true;
-        options.cache = Some(Cache::new(cache_size));
-        Ok(LevelDbKvStore(Database::open(path, options)?))
+        let mut retries = 0;
+        loop {
+            let mut options = Options::new();
+            options.create_if_missing = true;
+            options.cache = Some(Cache::new(cache_size));
+            match Database::open(path, options) {
+                Ok(db) => {
+                    return Ok(LevelDbKvStore(db));
+                }
+                Err(e) if is_lock_error(&e) => {
+                    if retries == LOCK_RETRIES {
+                        return Err(KvStoreError::Locked(path.display().to_string()));
+                    }
+                    retries += 1;
+                    std::thread::sleep(LOCK_RETRY_INTERVAL);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            }
+        }
     }
 }
 
@@ -1,12 +1,14 @@
 use crate::blockchain::{ZkBlockchainPatch, ZkCompressedStateChange};
-use crate::core::{hash::Hash, Account, Block, ContractAccount, ContractId, Hasher, Header};
+use crate::core::{
+    hash::Hash, Account, Address, Block, ContractAccount, ContractId, Hasher, Header,
+};
 use crate::crypto::merkle::MerkleTree;
 use crate::zk::{
     ZkCompressedState, ZkContract, ZkDataPairs, ZkDeltaPairs, ZkScalar, ZkState, ZkStateModel,
 };
 use db_key::Key;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -20,6 +22,8 @@ pub enum KvStoreError {
     #[cfg(feature = "db")]
     #[error("leveldb error: {0}")]
     LevelDb(#[from] leveldb::error::Error),
+    #[error("database at {0} is locked, is another node running on it?")]
+    Locked(String),
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, std::hash::Hash)]
@@ -46,6 +50,15 @@ impl StringKey {
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Blob(Vec<u8>);
 
+impl Blob {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
 macro_rules! gen_try_into {
     ( $( $x:ty ),* ) => {
         $(
@@ -72,10 +85,12 @@ macro_rules! gen_from {
 }
 
 gen_try_into!(
+    bool,
     u32,
     u64,
     u128,
     usize,
+    Address,
     Account,
     ContractAccount,
     Header,
@@ -92,13 +107,16 @@ gen_try_into!(
     ZkStateModel,
     ZkScalar,
     ZkDataPairs,
-    ZkDeltaPairs
+    ZkDeltaPairs,
+    (u64, u32)
 );
 gen_from!(
+    bool,
     u32,
     u64,
     u128,
     usize,
+    Address,
     Account,
     ContractAccount,
     Header,
@@ -115,7 +133,8 @@ gen_from!(
     ZkStateModel,
     ZkScalar,
     &ZkDataPairs,
-    &ZkDeltaPairs
+    &ZkDeltaPairs,
+    (u64, u32)
 );
 
 impl Key for StringKey {
@@ -162,15 +181,65 @@ pub trait KvStore {
     }
 }
 
+// Writes accumulated over several steps, committed in a single atomic update
+#[derive(Clone, Debug, Default)]
+pub struct KvStoreTransaction {
+    ops: Vec<WriteOp>,
+}
+
+impl KvStoreTransaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn put(&mut self, k: StringKey, v: Blob) {
+        self.ops.push(WriteOp::Put(k, v));
+    }
+    pub fn remove(&mut self, k: StringKey) {
+        self.ops.push(WriteOp::Remove(k));
+    }
+    pub fn extend<I: IntoIterator<Item = WriteOp>>(&mut self, ops: I) {
+        self.ops.extend(ops);
+    }
+    pub fn ops(&self) -> &[WriteOp] {
+        &self.ops
+    }
+    // Ops restoring the current values of the touched keys of the store, sorted
+    // by key. Should be computed before the transaction is committed.
+    pub fn rollback_of<K: KvStore>(&self, store: &K) -> Result<Vec<WriteOp>, KvStoreError> {
+        let keys = self
+            .ops
+            .iter()
+            .map(|op| match op {
+                WriteOp::Remove(k) | WriteOp::Put(k, _) => k.clone(),
+            })
+            .collect::<BTreeSet<_>>();
+        keys.into_iter()
+            .map(|k| {
+                store.get(k.clone()).map(|v| match v {
+                    Some(v) => WriteOp::Put(k, v),
+                    None => WriteOp::Remove(k),
+                })
+            })
+            .collect()
+    }
+    // Returns the ops which undo the transaction
+    pub fn commit<K: KvStore>(self, store: &mut K) -> Result<Vec<WriteOp>, KvStoreError> {
+        let rollback = self.rollback_of(store)?;
+        store.update(&self.ops)?;
+        Ok(rollback)
+    }
+}
+
+// Overwrites are kept sorted, so that the generated ops are deterministic
 pub struct RamMirrorKvStore<'a, K: KvStore> {
     store: &'a K,
-    overwrite: HashMap<StringKey, Option<Blob>>,
+    overwrite: BTreeMap<StringKey, Option<Blob>>,
 }
 impl<'a, K: KvStore> RamMirrorKvStore<'a, K> {
     pub fn new(store: &'a K) -> Self {
         Self {
             store,
-            overwrite: HashMap::new(),
+            overwrite: BTreeMap::new(),
         }
     }
     pub fn rollback(&self) -> Result<Vec<WriteOp>, KvStoreError> {
@@ -6,6 +6,14 @@ impl RamKvStore {
     pub fn new() -> RamKvStore {
         RamKvStore(HashMap::new())
     }
+    pub fn with_capacity(capacity: usize) -> RamKvStore {
+        RamKvStore(HashMap::with_capacity(capacity))
+    }
+    pub fn from_pairs(pairs: Vec<(StringKey, Blob)>) -> RamKvStore {
+        let mut store = Self::with_capacity(pairs.len());
+        store.0.extend(pairs.into_iter().map(|(k, v)| (k.0, v)));
+        store
+    }
 }
 
 impl Default for RamKvStore {
@@ -8,6 +8,18 @@ fn temp_disk_store() -> Result<LevelDbKvStore, KvStoreError> {
     LevelDbKvStore::new(TempDir::new("bazuka_test").unwrap().path(), 64)
 }
 
+#[test]
+#[cfg(feature = "db")]
+fn test_disk_store_locked_by_another_instance() -> Result<(), KvStoreError> {
+    let dir = TempDir::new("bazuka_test").unwrap();
+    let _first = LevelDbKvStore::new(dir.path(), 64)?;
+    assert!(matches!(
+        LevelDbKvStore::new(dir.path(), 64),
+        Err(KvStoreError::Locked(_))
+    ));
+    Ok(())
+}
+
 #[test]
 #[cfg(feature = "db")]
 fn test_ram_and_disk_pair_prefix() -> Result<(), KvStoreError> {
@@ -156,3 +168,72 @@ fn test_mirror_rollback() -> Result<(), KvStoreError> {
 
     Ok(())
 }
+
+#[test]
+fn test_ram_bulk_load() -> Result<(), KvStoreError> {
+    let pairs = (0..100000u32)
+        .map(|i| {
+            (
+                StringKey::new(&format!("key_{}", i)),
+                Blob(i.to_le_bytes().to_vec()),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut ram = RamKvStore::default();
+    for (k, v) in pairs.iter() {
+        ram.update(&[WriteOp::Put(k.clone(), v.clone())])?;
+    }
+
+    let bulk = RamKvStore::from_pairs(pairs);
+    assert_eq!(bulk.pairs("".into())?.len(), 100000);
+    assert_eq!(ram.checksum::<Hasher>()?, bulk.checksum::<Hasher>()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_transaction_matches_manual_ops() -> Result<(), KvStoreError> {
+    let mut manual = RamKvStore::default();
+    let mut transacted = RamKvStore::default();
+    let initial = &[
+        WriteOp::Put("a".into(), Blob(vec![1])),
+        WriteOp::Put("b".into(), Blob(vec![2])),
+    ];
+    manual.update(initial)?;
+    transacted.update(initial)?;
+    let initial_checksum = manual.checksum::<Hasher>()?;
+
+    manual.update(&[
+        WriteOp::Put("c".into(), Blob(vec![3])),
+        WriteOp::Remove("a".into()),
+        WriteOp::Put("b".into(), Blob(vec![4])),
+    ])?;
+
+    let mut txn = KvStoreTransaction::new();
+    txn.put("c".into(), Blob(vec![3]));
+    txn.remove("a".into());
+    txn.put("b".into(), Blob(vec![5]));
+    // Later writes of the same key win
+    txn.put("b".into(), Blob(vec![4]));
+    let rollback = txn.rollback_of(&transacted)?;
+    assert_eq!(
+        rollback,
+        vec![
+            WriteOp::Put("a".into(), Blob(vec![1])),
+            WriteOp::Put("b".into(), Blob(vec![2])),
+            WriteOp::Remove("c".into()),
+        ]
+    );
+    assert_eq!(txn.commit(&mut transacted)?, rollback);
+
+    assert_eq!(
+        manual.checksum::<Hasher>()?,
+        transacted.checksum::<Hasher>()?
+    );
+
+    transacted.update(&rollback)?;
+    assert_eq!(transacted.checksum::<Hasher>()?, initial_checksum);
+
+    Ok(())
+}
@@ -15,12 +15,14 @@ use {
     bazuka::client::{NodeRequest, PeerAddress},
     bazuka::config,
     bazuka::db::LevelDbKvStore,
-    bazuka::node::node_create,
+    bazuka::node::{node_create, ConnectionLimiter, IdleTimeoutStream, NodeOptions},
     colored::Colorize,
-    hyper::server::conn::AddrStream,
+    hyper::server::accept::Accept,
+    hyper::server::conn::{AddrIncoming, AddrStream},
     hyper::service::{make_service_fn, service_fn},
     hyper::{Body, Client, Request, Response, Server},
     std::path::{Path, PathBuf},
+    std::pin::Pin,
     std::sync::Arc,
     tokio::sync::mpsc,
     tokio::try_join,
@@ -62,6 +64,8 @@ enum CliOptions {
         db: Option<PathBuf>,
         #[structopt(long)]
         bootstrap: Vec<String>,
+        #[structopt(long)]
+        mine: bool,
     },
     Status {
         #[structopt(long)]
@@ -88,6 +92,7 @@ async fn run_node(
     external: Option<SocketAddr>,
     db: Option<PathBuf>,
     bootstrap: Vec<String>,
+    mine: bool,
 ) -> Result<(), NodeError> {
     let (pub_key, priv_key) = Signer::generate_keys(&bazuka_config.seed.as_bytes());
 
@@ -128,8 +133,14 @@ async fn run_node(
     let bazuka_dir = db.unwrap_or_else(|| home::home_dir().unwrap().join(Path::new(".bazuka")));
     // Async loop that is responsible for answering external requests and gathering
     // data from external world through a heartbeat loop.
+    let opts = NodeOptions {
+        solo_mine: mine,
+        mempool_path: Some(bazuka_dir.join("mempool.bin")),
+        ..config::node::get_node_options()
+    };
+    let connections = Arc::new(ConnectionLimiter::new(opts.max_connections));
     let node = node_create(
-        config::node::get_node_options(),
+        opts.clone(),
         address,
         priv_key,
         bootstrap_nodes,
@@ -140,6 +151,7 @@ async fn run_node(
         .unwrap(),
         0,
         Some(Wallet::new(bazuka_config.seed.as_bytes().to_vec())),
+        Arc::clone(&connections),
         inc_recv,
         out_send,
     );
@@ -148,12 +160,28 @@ async fn run_node(
     // socket and redirecting it to the node channels.
     let server_loop = async {
         let arc_inc_send = Arc::new(inc_send);
-        Server::bind(&listen)
-            .serve(make_service_fn(|conn: &AddrStream| {
-                let client = conn.remote_addr();
+        let mut incoming = AddrIncoming::bind(&listen)?;
+        incoming.set_keepalive(opts.http_tcp_keep_alive);
+        // Every accepted connection gets its own idle deadline
+        let idle_timeout = opts.http_idle_timeout;
+        let incoming = hyper::server::accept::from_stream(futures::stream::poll_fn(move |cx| {
+            Pin::new(&mut incoming).poll_accept(cx).map(|conn| {
+                conn.map(|conn| conn.map(|conn| IdleTimeoutStream::new(conn, idle_timeout)))
+            })
+        }));
+        Server::builder(incoming)
+            .http1_keepalive(opts.http_keep_alive)
+            .http1_header_read_timeout(opts.http_header_read_timeout)
+            .serve(make_service_fn(|conn: &IdleTimeoutStream<AddrStream>| {
+                let client = conn.get_ref().remote_addr();
                 let arc_inc_send = Arc::clone(&arc_inc_send);
+                // Refuse the connection when saturated, the slot is released
+                // once the connection (And its service) is dropped.
+                let guard = connections.try_acquire();
                 async move {
+                    let guard = guard.ok_or(NodeError::TooManyConnections)?;
                     Ok::<_, NodeError>(service_fn(move |req: Request<Body>| {
+                        let _guard = &guard;
                         let arc_inc_send = Arc::clone(&arc_inc_send);
                         async move {
                             let (resp_snd, mut resp_rcv) =
@@ -201,6 +229,9 @@ async fn run_node(
 #[cfg(feature = "client")]
 #[tokio::main]
 async fn main() -> Result<(), NodeError> {
+    #[cfg(feature = "node")]
+    bazuka::node::logger_builder().init();
+    #[cfg(not(feature = "node"))]
     env_logger::init();
 
     let opts = CliOptions::from_args();
@@ -217,9 +248,10 @@ async fn main() -> Result<(), NodeError> {
             external,
             db,
             bootstrap,
+            mine,
         } => {
             let conf = conf.expect("Bazuka is not initialized!");
-            run_node(conf.clone(), listen, external, db, bootstrap).await?;
+            run_node(conf.clone(), listen, external, db, bootstrap, mine).await?;
         }
         #[cfg(not(feature = "node"))]
         CliOptions::Node { .. } => {
@@ -267,7 +299,10 @@ async fn main() -> Result<(), NodeError> {
             let (req_loop, client) = BazukaClient::connect(sk, PeerAddress(node));
             try_join!(
                 async move {
-                    let acc = client.get_account(wallet.get_address()).await?.account;
+                    let acc = client
+                        .get_account(wallet.get_address(), false)
+                        .await?
+                        .account;
                     let pay = wallet.contract_deposit_withdraw(
                         contract.parse().unwrap(),
                         index,
@@ -0,0 +1,21 @@
+use super::messages::{DecodeTxRequest, DecodeTxResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use crate::core::Transaction;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+// Purely informational, the transaction is not checked against the chain state
+pub async fn decode_tx<B: Blockchain>(
+    _context: Arc<RwLock<NodeContext<B>>>,
+    req: DecodeTxRequest,
+) -> Result<DecodeTxResponse, NodeError> {
+    let bytes = hex::decode(req.tx.trim()).map_err(|_| NodeError::InputError)?;
+    let tx: Transaction = bincode::deserialize(&bytes)?;
+    Ok(DecodeTxResponse {
+        tx: (&tx).into(),
+        valid_signature: tx.verify_signature(),
+        size: tx.size(),
+        fee_rate: tx.fee_rate(),
+    })
+}
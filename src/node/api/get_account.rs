@@ -1,6 +1,7 @@
 use super::messages::{GetAccountRequest, GetAccountResponse};
 use super::{NodeContext, NodeError};
 use crate::blockchain::Blockchain;
+use crate::core::Address;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -9,7 +10,14 @@ pub async fn get_account<B: Blockchain>(
     req: GetAccountRequest,
 ) -> Result<GetAccountResponse, NodeError> {
     let context = context.read().await;
+    let addr: Address = req.address.parse()?;
     Ok(GetAccountResponse {
-        account: context.blockchain.get_account(req.address.parse()?)?,
+        account: context.blockchain.get_account(addr.clone())?.into(),
+        exists: context.blockchain.account_exists(addr.clone())?,
+        pending: if req.include_pending {
+            Some(context.pending_account(&addr)?.into())
+        } else {
+            None
+        },
     })
 }
@@ -0,0 +1,28 @@
+use super::messages::{AccountEntry, GetAccountsRequest, GetAccountsResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+const MAX_ACCOUNTS_PER_PAGE: usize = 1000;
+
+pub async fn get_accounts<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: GetAccountsRequest,
+) -> Result<GetAccountsResponse, NodeError> {
+    let context = context.read().await;
+    Ok(GetAccountsResponse {
+        accounts: context
+            .blockchain
+            .iter_accounts()?
+            .skip(req.offset)
+            .take(req.limit.min(MAX_ACCOUNTS_PER_PAGE))
+            .map(|entry| {
+                entry.map(|(addr, acc)| AccountEntry {
+                    address: addr.to_string(),
+                    account: acc.into(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    })
+}
@@ -0,0 +1,15 @@
+use super::messages::{GetAdminOptionsRequest, GetAdminOptionsResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_admin_options<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    _req: GetAdminOptionsRequest,
+) -> Result<GetAdminOptionsResponse, NodeError> {
+    let context = context.read().await;
+    Ok(GetAdminOptionsResponse {
+        options: context.opts.admin_options(),
+    })
+}
@@ -0,0 +1,19 @@
+use super::{NodeContext, NodeError};
+use crate::blockchain::{Blockchain, BlockchainError};
+use futures::stream::{self, StreamExt};
+use hyper::Body;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+// Accounts are decoded and formatted while the response is being sent, instead of
+// building the whole CSV in memory first.
+pub async fn get_balances_csv<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+) -> Result<Body, NodeError> {
+    let accounts = context.read().await.blockchain.iter_accounts()?;
+    let header =
+        stream::once(async { Ok::<_, BlockchainError>("address,balance,nonce\n".to_string()) });
+    let rows = stream::iter(accounts)
+        .map(|entry| entry.map(|(addr, acc)| format!("{},{},{}\n", addr, acc.balance, acc.nonce)));
+    Ok(Body::wrap_stream(header.chain(rows)))
+}
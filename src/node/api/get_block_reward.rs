@@ -0,0 +1,26 @@
+use super::messages::{GetBlockRewardRequest, GetBlockRewardResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::{Blockchain, BlockchainError};
+use crate::client::explorer::BlockSummaryResponse;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_block_reward<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: GetBlockRewardRequest,
+) -> Result<GetBlockRewardResponse, NodeError> {
+    let context = context.read().await;
+    let block = context
+        .blockchain
+        .get_blocks(req.index, Some(req.index.saturating_add(1)))?
+        .into_iter()
+        .next()
+        .ok_or(BlockchainError::BlockNotFound)?;
+    // Genesis block has no reward, its summary reports zero
+    let summary = BlockSummaryResponse::new(&block, &context.blockchain.config().fee_split);
+    Ok(GetBlockRewardResponse {
+        reward: summary.reward,
+        fees: summary.total_fees,
+        total: summary.reward + summary.total_fees,
+    })
+}
@@ -0,0 +1,25 @@
+use super::messages::{GetBlockSummariesRequest, GetBlockSummariesResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use crate::client::explorer::BlockSummaryResponse;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+const MAX_SUMMARIES_PER_PAGE: u64 = 1000;
+
+pub async fn get_block_summaries<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: GetBlockSummariesRequest,
+) -> Result<GetBlockSummariesResponse, NodeError> {
+    let context = context.read().await;
+    let limit = req.limit.min(MAX_SUMMARIES_PER_PAGE);
+    let fee_split = &context.blockchain.config().fee_split;
+    Ok(GetBlockSummariesResponse {
+        summaries: context
+            .blockchain
+            .get_blocks(req.since, Some(req.since.saturating_add(limit)))?
+            .iter()
+            .map(|b| BlockSummaryResponse::new(b, fee_split))
+            .collect(),
+    })
+}
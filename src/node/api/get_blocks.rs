@@ -1,18 +1,52 @@
-use super::messages::{GetBlocksRequest, GetBlocksResponse};
+use super::messages::GetBlocksRequest;
 use super::{NodeContext, NodeError};
-use crate::blockchain::Blockchain;
+use crate::blockchain::{Blockchain, BlockchainError};
 use crate::config::MAX_BLOCK_FETCH;
+use futures::stream::{self, StreamExt};
+use hyper::Body;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-pub async fn get_blocks<B: Blockchain>(
+// Bincode encoding of a `GetBlocksResponse`, sent block by block so that the
+// blocks are never all kept in memory at once. The chain is only locked while
+// each of the blocks is being read.
+//
+// The headers of the range are read before the block count is written, so a
+// range that can't be read fails the request instead of truncating its body.
+// If the chain is rolled back while the blocks are being sent, the body ends
+// with an error and the client sees an incomplete response.
+pub async fn get_blocks<B: Blockchain + Send + Sync + 'static>(
     context: Arc<RwLock<NodeContext<B>>>,
     req: GetBlocksRequest,
-) -> Result<GetBlocksResponse, NodeError> {
-    let context = context.read().await;
-    let height = context.blockchain.get_height()?;
-    let until = std::cmp::min(height, req.since + MAX_BLOCK_FETCH);
-    Ok(GetBlocksResponse {
-        blocks: context.blockchain.get_blocks(req.since, Some(until))?,
-    })
+) -> Result<Body, NodeError> {
+    let headers = context
+        .read()
+        .await
+        .blockchain
+        .get_headers(req.since, Some(req.since.saturating_add(MAX_BLOCK_FETCH)))?;
+    let count = bincode::serialize(&(headers.len() as u64))?;
+    let blocks = stream::iter(headers).then(move |header| {
+        let context = Arc::clone(&context);
+        async move {
+            let index = header.number;
+            let block = context
+                .read()
+                .await
+                .blockchain
+                .get_blocks(index, Some(index + 1))?
+                .pop()
+                .filter(|b| b.header.hash() == header.hash())
+                .ok_or(BlockchainError::BlockNotFound);
+            match block {
+                Ok(block) => Ok(bincode::serialize(&block)?),
+                Err(e) => {
+                    log::warn!("Block #{} changed while being streamed!", index);
+                    Err(NodeError::from(e))
+                }
+            }
+        }
+    });
+    Ok(Body::wrap_stream(
+        stream::once(async { Ok(count) }).chain(blocks),
+    ))
 }
@@ -0,0 +1,30 @@
+use super::messages::{GetContractInfoRequest, GetContractInfoResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use crate::core::{hash::Hash, Hasher};
+use crate::zk::ZkVerifierKey;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+fn verifier_key_hash(vk: &ZkVerifierKey) -> String {
+    hex::encode(Hasher::hash(&bincode::serialize(vk).unwrap()))
+}
+
+pub async fn get_contract_info<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: GetContractInfoRequest,
+) -> Result<GetContractInfoResponse, NodeError> {
+    let context = context.read().await;
+    let contract_id = req.contract_id.parse()?;
+    let contract = context.blockchain.get_contract(contract_id)?;
+    let contract_account = context.blockchain.get_contract_account(contract_id)?;
+    Ok(GetContractInfoResponse {
+        state_model: contract.state_model,
+        log4_deposit_withdraw_capacity: contract.log4_deposit_withdraw_capacity,
+        num_functions: contract.functions.len(),
+        byte_size: contract_account.byte_size,
+        storage_size: context.blockchain.contract_storage_size(contract_id)?,
+        deposit_withdraw_function: verifier_key_hash(&contract.deposit_withdraw_function),
+        functions: contract.functions.iter().map(verifier_key_hash).collect(),
+    })
+}
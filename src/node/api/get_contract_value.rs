@@ -0,0 +1,20 @@
+use super::messages::{GetContractValueRequest, GetContractValueResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use ff::PrimeField;
+use num_bigint::BigUint;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_contract_value<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: GetContractValueRequest,
+) -> Result<GetContractValueResponse, NodeError> {
+    let context = context.read().await;
+    let value = context
+        .blockchain
+        .get_contract_value(req.id.parse()?, &req.locator.parse()?)?;
+    Ok(GetContractValueResponse {
+        value: BigUint::from_bytes_le(value.to_repr().as_ref()).to_string(),
+    })
+}
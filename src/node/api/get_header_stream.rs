@@ -0,0 +1,21 @@
+use super::messages::{GetHeaderStreamRequest, GetHeaderStreamResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_header_stream<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: GetHeaderStreamRequest,
+) -> Result<GetHeaderStreamResponse, NodeError> {
+    let context = context.read().await;
+    let height = context.blockchain.get_height()?;
+    let until = req
+        .since
+        .saturating_add(context.opts.header_stream_chunk_size as u64)
+        .min(height);
+    Ok(GetHeaderStreamResponse {
+        headers: context.blockchain.get_headers(req.since, Some(until))?,
+        next: if until < height { Some(until) } else { None },
+    })
+}
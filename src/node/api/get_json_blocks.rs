@@ -0,0 +1,20 @@
+use super::messages::{GetBlocksRequest, GetJsonBlocksResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_json_blocks<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: GetBlocksRequest,
+) -> Result<GetJsonBlocksResponse, NodeError> {
+    let context = context.read().await;
+    Ok(GetJsonBlocksResponse {
+        blocks: context
+            .blockchain
+            .get_blocks(req.since, req.until)?
+            .iter()
+            .map(|b| b.into())
+            .collect(),
+    })
+}
@@ -0,0 +1,20 @@
+use super::messages::{GetHeadersRequest, GetJsonHeadersResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_json_headers<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: GetHeadersRequest,
+) -> Result<GetJsonHeadersResponse, NodeError> {
+    let context = context.read().await;
+    Ok(GetJsonHeadersResponse {
+        headers: context
+            .blockchain
+            .get_headers(req.since, req.until)?
+            .iter()
+            .map(|h| h.into())
+            .collect(),
+    })
+}
@@ -0,0 +1,28 @@
+use super::messages::{GetMetricsRequest, GetMetricsResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_metrics<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    _req: GetMetricsRequest,
+) -> Result<GetMetricsResponse, NodeError> {
+    let context = context.read().await;
+    Ok(GetMetricsResponse {
+        open_connections: context.connections.open_connections(),
+        max_connections: context.connections.max_connections(),
+        num_active_peers: context.active_peers().len(),
+        mempool_size: context.mempool.len(),
+        block_propagation: context
+            .block_propagation
+            .iter()
+            .map(|(peer, hist)| (peer.to_string(), hist.clone()))
+            .collect(),
+        request_latency: context
+            .request_latency
+            .iter()
+            .map(|(peer, hist)| (peer.to_string(), hist.clone()))
+            .collect(),
+    })
+}
@@ -9,6 +9,7 @@ pub async fn get_miner_puzzle<B: Blockchain>(
     _req: GetMinerPuzzleRequest,
 ) -> Result<GetMinerPuzzleResponse, NodeError> {
     let mut context = context.write().await;
+    context.expire_stale_puzzle()?;
     if let Some((_, puzzle)) = context.miner_puzzle.as_ref() {
         Ok(GetMinerPuzzleResponse {
             puzzle: Some(puzzle.clone()),
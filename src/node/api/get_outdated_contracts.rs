@@ -0,0 +1,19 @@
+use super::messages::{GetOutdatedContractsRequest, GetOutdatedContractsResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_outdated_contracts<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    _req: GetOutdatedContractsRequest,
+) -> Result<GetOutdatedContractsResponse, NodeError> {
+    let context = context.read().await;
+    let mut outdated_contracts = HashMap::new();
+    for cid in context.blockchain.get_outdated_contracts()? {
+        let account = context.blockchain.get_contract_account(cid)?;
+        outdated_contracts.insert(cid, account.compressed_state);
+    }
+    Ok(GetOutdatedContractsResponse { outdated_contracts })
+}
@@ -0,0 +1,17 @@
+use super::messages::{GetPendingBalanceRequest, GetPendingBalanceResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use crate::core::Address;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_pending_balance<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: GetPendingBalanceRequest,
+) -> Result<GetPendingBalanceResponse, NodeError> {
+    let context = context.read().await;
+    let addr: Address = req.address.parse()?;
+    Ok(GetPendingBalanceResponse {
+        pending_delta: context.pending_balance_delta(&addr),
+    })
+}
@@ -0,0 +1,15 @@
+use super::messages::{GetPunishmentsRequest, GetPunishmentsResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_punishments<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    _req: GetPunishmentsRequest,
+) -> Result<GetPunishmentsResponse, NodeError> {
+    let context = context.read().await;
+    Ok(GetPunishmentsResponse {
+        punishments: context.punishments.iter().cloned().collect(),
+    })
+}
@@ -0,0 +1,15 @@
+use super::messages::{GetRollbackOpsRequest, GetRollbackOpsResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_rollback_ops<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: GetRollbackOpsRequest,
+) -> Result<GetRollbackOpsResponse, NodeError> {
+    let context = context.read().await;
+    Ok(GetRollbackOpsResponse {
+        ops: context.blockchain.get_rollback_ops(req.height)?,
+    })
+}
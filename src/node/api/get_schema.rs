@@ -0,0 +1,14 @@
+use super::messages::{json_schema_definitions, GetSchemaRequest, GetSchemaResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_schema<B: Blockchain>(
+    _context: Arc<RwLock<NodeContext<B>>>,
+    _req: GetSchemaRequest,
+) -> Result<GetSchemaResponse, NodeError> {
+    Ok(GetSchemaResponse {
+        definitions: json_schema_definitions(),
+    })
+}
@@ -0,0 +1,101 @@
+use super::get_states::estimated_patch_bytes;
+use super::messages::{GetStateChunksRequest, GetStateChunksResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::{Blockchain, ZkBlockchainPatch};
+use crate::core::{hash::Hash, Hasher};
+use crate::zk::{ZkScalar, ZkStatePatch};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_state_chunks<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: GetStateChunksRequest,
+) -> Result<GetStateChunksResponse, NodeError> {
+    let mut context = context.write().await;
+    let to =
+        <Hasher as Hash>::Output::try_from(hex::decode(req.to).map_err(|_| NodeError::InputError)?)
+            .map_err(|_| NodeError::InputError)?;
+
+    // Limit the work done for a single request, the requester may ask for the rest
+    // of the contracts later
+    let max_contracts = context.opts.max_state_patch_contracts;
+    let mut requested = req.outdated_heights.into_iter().collect::<Vec<_>>();
+    requested.sort_by_key(|(cid, _)| cid.to_string());
+    let mut more_available = requested.len() > max_contracts;
+    requested.truncate(max_contracts);
+
+    // The rest of the chunks of a full-state are served from the chunks generated
+    // for its first one, instead of regenerating the whole state on each request
+    let mut patch = ZkBlockchainPatch {
+        patches: Default::default(),
+    };
+    // Only as many contracts as would fit in the response get their patches built
+    let max_chunk_bytes = context.opts.state_chunk_size * std::mem::size_of::<ZkScalar>();
+    let mut estimated_bytes = 0;
+    let mut uncached = HashMap::new();
+    for (cid, height) in requested {
+        match context.state_chunks.get(&cid) {
+            Some((chunks_to, chunks)) if req.chunk > 0 && *chunks_to == to => {
+                let chunk = chunks.get(req.chunk).ok_or(NodeError::InputError)?;
+                patch
+                    .patches
+                    .insert(cid, ZkStatePatch::Chunks(vec![chunk.clone()]));
+            }
+            _ => {
+                let bytes = estimated_patch_bytes(&context.blockchain, cid)?.min(max_chunk_bytes);
+                if !uncached.is_empty()
+                    && estimated_bytes + bytes > context.opts.max_state_patch_bytes
+                {
+                    more_available = true;
+                    continue;
+                }
+                estimated_bytes += bytes;
+                uncached.insert(cid, height);
+            }
+        }
+    }
+    if !uncached.is_empty() {
+        let generated = context.blockchain.generate_state_patch(uncached, to)?;
+        patch.patches.extend(generated.patches);
+    }
+
+    // Huge full-states won't fit in a single response, only the requested chunk
+    // of them is sent
+    let chunk_size = context.opts.state_chunk_size;
+    for (cid, state_patch) in patch.patches.iter_mut() {
+        if let ZkStatePatch::Full(full) = state_patch {
+            if full.data.size() > chunk_size {
+                let chunks = full.chunks(chunk_size);
+                let chunk = chunks
+                    .get(req.chunk)
+                    .cloned()
+                    .ok_or(NodeError::InputError)?;
+                context
+                    .state_chunks
+                    .retain(|_, (chunks_to, _)| *chunks_to == to);
+                context.state_chunks.insert(*cid, (to, chunks));
+                *state_patch = ZkStatePatch::Chunks(vec![chunk]);
+            }
+        }
+    }
+
+    // At least one contract is always served, so that the requester makes progress
+    let mut cids = patch.patches.keys().cloned().collect::<Vec<_>>();
+    cids.sort_by_key(|cid| cid.to_string());
+    let mut total_bytes = 0;
+    for cid in cids {
+        let bytes = bincode::serialized_size(&patch.patches[&cid])? as usize;
+        if total_bytes > 0 && total_bytes + bytes > context.opts.max_state_patch_bytes {
+            patch.patches.remove(&cid);
+            more_available = true;
+        } else {
+            total_bytes += bytes;
+        }
+    }
+
+    Ok(GetStateChunksResponse {
+        patch,
+        more_available,
+    })
+}
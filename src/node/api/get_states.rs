@@ -1,10 +1,20 @@
 use super::messages::{GetStatesRequest, GetStatesResponse};
 use super::{NodeContext, NodeError};
 use crate::blockchain::Blockchain;
-use crate::core::{hash::Hash, Hasher};
+use crate::core::{hash::Hash, ContractId, Hasher};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+// Rough size of the patch of a contract, judging by the size of its current state
+// (Deltas are usually way smaller), so that the work can be limited before any
+// full-state is built
+pub(super) fn estimated_patch_bytes<B: Blockchain>(
+    blockchain: &B,
+    cid: ContractId,
+) -> Result<usize, NodeError> {
+    Ok(blockchain.get_contract_account(cid)?.byte_size as usize)
+}
+
 pub async fn get_states<B: Blockchain>(
     context: Arc<RwLock<NodeContext<B>>>,
     req: GetStatesRequest,
@@ -13,6 +23,20 @@ pub async fn get_states<B: Blockchain>(
     let to =
         <Hasher as Hash>::Output::try_from(hex::decode(req.to).map_err(|_| NodeError::InputError)?)
             .map_err(|_| NodeError::InputError)?;
+
+    // Peers requesting through this endpoint can't handle partial patches, requests
+    // too expensive to serve are refused instead
+    if req.outdated_heights.len() > context.opts.max_state_patch_contracts {
+        return Err(NodeError::InputError);
+    }
+    let mut estimated_bytes = 0;
+    for cid in req.outdated_heights.keys() {
+        estimated_bytes += estimated_patch_bytes(&context.blockchain, *cid)?;
+    }
+    if estimated_bytes > context.opts.max_state_patch_bytes {
+        return Err(NodeError::InputError);
+    }
+
     let patch = context
         .blockchain
         .generate_state_patch(req.outdated_heights, to)?;
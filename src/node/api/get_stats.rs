@@ -1,18 +1,27 @@
 use super::messages::{GetStatsRequest, GetStatsResponse};
 use super::{NodeContext, NodeError};
 use crate::blockchain::Blockchain;
+use crate::core::HEADER_VERSION;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+const VERSION_SUPPORT_WINDOW: u64 = 1000;
+
 pub async fn get_stats<B: Blockchain>(
     context: Arc<RwLock<NodeContext<B>>>,
     _req: GetStatsRequest,
 ) -> Result<GetStatsResponse, NodeError> {
     let context = context.read().await;
+    let ts = context.network_timestamp();
     Ok(GetStatsResponse {
         height: context.blockchain.get_height()?,
         power: context.blockchain.get_power()?,
         next_reward: context.blockchain.next_reward()?,
-        timestamp: context.network_timestamp(),
+        timestamp: ts,
+        state_unavailable_peers: context.num_state_unavailable_peers(ts),
+        version_support: context
+            .blockchain
+            .version_bits_support(HEADER_VERSION, VERSION_SUPPORT_WINDOW)?,
+        hashrate_warning: context.hashrate_warning()?,
     })
 }
@@ -0,0 +1,15 @@
+use super::messages::{GetSyncStatusRequest, GetSyncStatusResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_sync_status<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    _req: GetSyncStatusRequest,
+) -> Result<GetSyncStatusResponse, NodeError> {
+    let context = context.read().await;
+    Ok(GetSyncStatusResponse {
+        status: context.sync_status.clone(),
+    })
+}
@@ -0,0 +1,32 @@
+use super::messages::{GetTransactionStatusRequest, GetTransactionStatusResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use crate::core::{hash::Hash, Hasher};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn get_transaction_status<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: GetTransactionStatusRequest,
+) -> Result<GetTransactionStatusResponse, NodeError> {
+    let context = context.read().await;
+    let hash = <Hasher as Hash>::Output::try_from(
+        hex::decode(req.hash).map_err(|_| NodeError::InputError)?,
+    )
+    .map_err(|_| NodeError::InputError)?;
+
+    // Zero transactions are identified by the hash of their encoding
+    let mut pending = context.mempool.keys().any(|t| t.tx.hash() == hash)
+        || context.dw_mempool.keys().any(|t| t.hash() == hash);
+    for tx in context.zero_mempool.keys() {
+        pending = pending || Hasher::hash(&bincode::serialize(tx)?) == hash;
+    }
+    if pending {
+        return Ok(GetTransactionStatusResponse::Pending);
+    }
+
+    Ok(match context.blockchain.get_transaction(hash)? {
+        Some((height, _)) => GetTransactionStatusResponse::Confirmed { height },
+        None => GetTransactionStatusResponse::Unknown,
+    })
+}
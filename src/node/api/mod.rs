@@ -14,10 +14,28 @@ mod get_blocks;
 pub use get_blocks::*;
 mod get_states;
 pub use get_states::*;
+mod get_state_chunks;
+pub use get_state_chunks::*;
 mod get_outdated_heights;
 pub use get_outdated_heights::*;
+mod get_outdated_contracts;
+pub use get_outdated_contracts::*;
 mod get_headers;
 pub use get_headers::*;
+mod get_header_stream;
+pub use get_header_stream::*;
+mod get_json_headers;
+pub use get_json_headers::*;
+mod get_json_blocks;
+pub use get_json_blocks::*;
+mod get_block_summaries;
+pub use get_block_summaries::*;
+mod get_block_reward;
+pub use get_block_reward::*;
+mod decode_tx;
+pub use decode_tx::*;
+mod get_transaction_status;
+pub use get_transaction_status::*;
 mod transact;
 pub use transact::*;
 mod transact_zero;
@@ -34,3 +52,29 @@ mod post_miner_solution;
 pub use post_miner_solution::*;
 mod get_account;
 pub use get_account::*;
+mod get_accounts;
+pub use get_accounts::*;
+mod get_balances_csv;
+pub use get_balances_csv::*;
+mod get_pending_balance;
+pub use get_pending_balance::*;
+mod get_contract_info;
+pub use get_contract_info::*;
+mod get_contract_value;
+pub use get_contract_value::*;
+mod get_schema;
+pub use get_schema::*;
+mod get_metrics;
+pub use get_metrics::*;
+mod get_sync_status;
+pub use get_sync_status::*;
+mod get_punishments;
+pub use get_punishments::*;
+mod trace_tx;
+pub use trace_tx::*;
+mod get_rollback_ops;
+pub use get_rollback_ops::*;
+mod get_admin_options;
+pub use get_admin_options::*;
+mod post_admin_options;
+pub use post_admin_options::*;
@@ -0,0 +1,71 @@
+use super::messages::{PostAdminOptionsRequest, PostAdminOptionsResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+// The new values are picked up by the next heartbeat, as the options are read
+// again on every run.
+pub async fn post_admin_options<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: PostAdminOptionsRequest,
+) -> Result<PostAdminOptionsResponse, NodeError> {
+    let mut context = context.write().await;
+    let mut opts = context.opts.clone();
+
+    if let Some(v) = req.num_peers {
+        opts.num_peers = v;
+    }
+    if let Some(v) = req.outdated_heights_threshold {
+        opts.outdated_heights_threshold = v;
+    }
+    if let Some(v) = req.no_response_punish {
+        opts.no_response_punish = v;
+    }
+    if let Some(v) = req.invalid_data_punish {
+        opts.invalid_data_punish = v;
+    }
+    if let Some(v) = req.incorrect_power_punish {
+        opts.incorrect_power_punish = v;
+    }
+    if let Some(v) = req.max_punish {
+        opts.max_punish = v;
+    }
+    if let Some(v) = req.state_unavailable_ban_time {
+        opts.state_unavailable_ban_time = v;
+    }
+    if let Some(v) = req.max_txs_per_sender {
+        opts.max_txs_per_sender = v;
+    }
+    if let Some(v) = req.max_concurrent_state_requests {
+        opts.max_concurrent_state_requests = v;
+    }
+
+    if opts.max_txs_per_sender == 0 {
+        return Err(NodeError::InvalidOptions(
+            "max_txs_per_sender should be positive".into(),
+        ));
+    }
+    if opts.max_concurrent_state_requests == 0 {
+        return Err(NodeError::InvalidOptions(
+            "max_concurrent_state_requests should be positive".into(),
+        ));
+    }
+    if [
+        opts.no_response_punish,
+        opts.invalid_data_punish,
+        opts.incorrect_power_punish,
+    ]
+    .into_iter()
+    .any(|p| p > opts.max_punish)
+    {
+        return Err(NodeError::InvalidOptions(
+            "punishments cannot be bigger than max_punish".into(),
+        ));
+    }
+
+    context.opts = opts;
+    Ok(PostAdminOptionsResponse {
+        options: context.opts.admin_options(),
+    })
+}
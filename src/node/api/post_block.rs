@@ -9,9 +9,24 @@ pub async fn post_block<B: Blockchain>(
     req: PostBlockRequest,
 ) -> Result<PostBlockResponse, NodeError> {
     let mut context = context.write().await;
+
+    // Peers may re-broadcast blocks we already have, which is not an error
+    let number = req.block.header.number;
+    if number < context.blockchain.get_height()?
+        && context.blockchain.get_headers(number, Some(number + 1))?[0].hash()
+            == req.block.header.hash()
+    {
+        return Ok(PostBlockResponse {
+            already_known: true,
+        });
+    }
+
     context
         .blockchain
-        .extend(req.block.header.number, &[req.block])?;
+        .check_initial_states(&req.block, &req.patch)?;
+    context.extend_chain(number, &[req.block])?;
     context.blockchain.update_states(&req.patch)?;
-    Ok(PostBlockResponse {})
+    Ok(PostBlockResponse {
+        already_known: false,
+    })
 }
@@ -2,10 +2,17 @@ use super::messages::{
     PostBlockRequest, PostBlockResponse, PostMinerSolutionRequest, PostMinerSolutionResponse,
 };
 use super::{http, Limit, NodeContext, NodeError};
-use crate::blockchain::Blockchain;
+use crate::blockchain::{Blockchain, BlockchainError};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+fn rejected(reason: &str) -> PostMinerSolutionResponse {
+    PostMinerSolutionResponse {
+        accepted: false,
+        reason: Some(reason.into()),
+    }
+}
+
 pub async fn post_miner_solution<B: Blockchain>(
     context: Arc<RwLock<NodeContext<B>>>,
     req: PostMinerSolutionRequest,
@@ -13,35 +20,59 @@ pub async fn post_miner_solution<B: Blockchain>(
     let mut context = context.write().await;
     let net = context.outgoing.clone();
 
-    let mut nonce_bytes = [0u8; 8];
-    nonce_bytes.copy_from_slice(&hex::decode(req.nonce).unwrap());
+    if context.miner_puzzle.is_none() {
+        return Err(NodeError::NoCurrentlyMiningBlockError);
+    }
+
+    // Tip has changed since the puzzle was handed out
+    if context.expire_stale_puzzle()? {
+        return Ok(rejected("stale"));
+    }
+
+    let nonce_bytes: [u8; 8] = match hex::decode(req.nonce).ok().and_then(|b| b.try_into().ok()) {
+        Some(b) => b,
+        None => {
+            return Ok(rejected("invalid nonce"));
+        }
+    };
     let (mut draft, _) = context
         .miner_puzzle
         .as_ref()
         .ok_or(NodeError::NoCurrentlyMiningBlockError)?
         .clone();
     draft.block.header.proof_of_work.nonce = u64::from_le_bytes(nonce_bytes);
-    if context
-        .blockchain
-        .extend(draft.block.header.number, &[draft.block.clone()])
-        .is_ok()
-    {
-        let _ = context.blockchain.update_states(&draft.patch.clone());
-
-        let peer_addresses = context.random_peers(&mut rand::thread_rng(), context.opts.num_peers);
-        http::group_request(&peer_addresses, |peer| {
-            net.bincode_post::<PostBlockRequest, PostBlockResponse>(
-                format!("{}/bincode/blocks", peer.address),
-                PostBlockRequest {
-                    block: draft.block.clone(),
-                    patch: draft.patch.clone(),
-                },
-                Limit::default().size(1024 * 1024).time(1000),
-            )
-        })
-        .await;
-
-        context.miner_puzzle = None;
+    match context.extend_chain(draft.block.header.number, &[draft.block.clone()]) {
+        Ok(_) => {}
+        Err(BlockchainError::DifficultyTargetUnmet) => {
+            return Ok(rejected("invalid nonce"));
+        }
+        Err(e) => {
+            return Err(e.into());
+        }
+    }
+
+    // States that were already outdated before this block are not in the patch
+    if let Err(e) = context.blockchain.update_states_partial(&draft.patch) {
+        log::warn!("Could not update the states of the mined block: {}", e);
     }
-    Ok(PostMinerSolutionResponse {})
+
+    let peer_addresses = context.random_peers(&mut rand::thread_rng(), context.opts.num_peers);
+    http::group_request(&peer_addresses, |peer| {
+        net.bincode_post::<PostBlockRequest, PostBlockResponse>(
+            format!("{}/bincode/blocks", peer.address),
+            PostBlockRequest {
+                block: draft.block.clone(),
+                patch: draft.patch.clone(),
+            },
+            Limit::default().size(1024 * 1024).time(1000),
+        )
+    })
+    .await;
+
+    context.miner_puzzle = None;
+
+    Ok(PostMinerSolutionResponse {
+        accepted: true,
+        reason: None,
+    })
 }
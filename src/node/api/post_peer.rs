@@ -10,6 +10,10 @@ pub async fn post_peer<B: Blockchain>(
     req: PostPeerRequest,
 ) -> Result<PostPeerResponse, NodeError> {
     let mut context = context.write().await;
+    let network_magic = context.blockchain.config().network_magic;
+    if req.network_magic != network_magic {
+        return Err(NodeError::NetworkMagicMismatch);
+    }
     context
         .peers
         .entry(req.address)
@@ -22,8 +26,11 @@ pub async fn post_peer<B: Blockchain>(
             info: Some(req.info),
             punished_until: 0,
         });
+    let now = context.network_timestamp();
+    context.peer_info_updated.insert(req.address, now);
     Ok(PostPeerResponse {
         info: context.get_info()?,
         timestamp: context.network_timestamp(),
+        network_magic,
     })
 }
@@ -0,0 +1,14 @@
+use super::messages::{TraceTxRequest, TraceTxResponse};
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub async fn trace_tx<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: TraceTxRequest,
+) -> Result<TraceTxResponse, NodeError> {
+    let context = context.read().await;
+    let (ops, side_effect) = context.blockchain.trace_tx(&req.tx)?;
+    Ok(TraceTxResponse { ops, side_effect })
+}
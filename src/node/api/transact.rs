@@ -1,6 +1,6 @@
 use super::messages::{TransactRequest, TransactResponse};
 use super::{NodeContext, NodeError};
-use crate::blockchain::{Blockchain, TransactionStats};
+use crate::blockchain::Blockchain;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -12,9 +12,7 @@ pub async fn transact<B: Blockchain>(
     let now = context.network_timestamp();
     // Prevent spamming mempool
     if context.blockchain.validate_transaction(&req.tx_delta)? {
-        context
-            .mempool
-            .insert(req.tx_delta, TransactionStats { first_seen: now });
+        context.add_transaction(req.tx_delta, now)?;
     }
     Ok(TransactResponse {})
 }
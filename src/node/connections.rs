@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Keeps track of the open incoming connections, so that new connections can be
+// refused when the node is saturated instead of exhausting the file descriptors.
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    max_connections: usize,
+    open: AtomicUsize,
+}
+
+// Releases its connection slot when dropped
+#[derive(Debug)]
+pub struct ConnectionGuard(Arc<ConnectionLimiter>);
+
+impl ConnectionLimiter {
+    pub fn new(max_connections: usize) -> Self {
+        Self {
+            max_connections,
+            open: AtomicUsize::new(0),
+        }
+    }
+    pub fn max_connections(&self) -> usize {
+        self.max_connections
+    }
+    pub fn open_connections(&self) -> usize {
+        self.open.load(Ordering::SeqCst)
+    }
+    pub fn try_acquire(self: &Arc<Self>) -> Option<ConnectionGuard> {
+        self.open
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open| {
+                (open < self.max_connections).then(|| open + 1)
+            })
+            .ok()
+            .map(|_| ConnectionGuard(Arc::clone(self)))
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.open.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -1,19 +1,54 @@
-use super::{NodeOptions, OutgoingSender, Peer, PeerAddress, PeerInfo, Timestamp};
+use super::{
+    ConnectionLimiter, Histogram, NodeError, NodeEvent, NodeOptions, OutgoingSender, Peer,
+    PeerAddress, PeerInfo, PunishmentReason, SyncStatus, Timestamp,
+};
 use crate::blockchain::{BlockAndPatch, Blockchain, BlockchainError, TransactionStats};
-use crate::core::{ContractPayment, Header, Signer, TransactionAndDelta};
+use crate::client::PunishmentEvent;
+use crate::core::{
+    hash::Hash, Account, Address, Block, ContractId, ContractPayment, ContractUpdate, Hasher,
+    Header, Money, Signer, Transaction, TransactionAndDelta, TransactionData,
+};
 use crate::crypto::SignatureScheme;
 use crate::utils;
 use crate::wallet::Wallet;
 use crate::zk;
 use rand::seq::IteratorRandom;
 use rand::RngCore;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::client::messages::Puzzle;
 
 pub type BlockPuzzle = (BlockAndPatch, Puzzle);
 
+// Bucket bounds of the block propagation delays (Seconds)
+const BLOCK_PROPAGATION_BUCKETS: [u64; 7] = [1, 2, 5, 10, 30, 60, 300];
+// Blocks synced in longer batches are fetched while catching up, long after they
+// were mined, so they tell nothing about the propagation delays
+const MAX_PROPAGATION_BATCH: usize = 2;
+// Bucket bounds of the request latencies (Milliseconds)
+const REQUEST_LATENCY_BUCKETS: [u64; 7] = [10, 50, 100, 250, 500, 1000, 5000];
+
+// Circuit-breaker of a peer failing to serve valid blocks. It opens after too many
+// consecutive failures, and half-opens (Allowing a retry) once the cooldown is over.
+#[derive(Debug, Clone, Default)]
+pub struct SyncBreaker {
+    pub failures: u32,
+    pub opened_at: Option<Timestamp>,
+}
+
+// Mempools as saved on disk
+#[derive(Serialize, Deserialize)]
+struct PersistedMempools {
+    mempool: Vec<(TransactionAndDelta, TransactionStats)>,
+    zero_mempool: Vec<(zk::ZeroTransaction, TransactionStats)>,
+    dw_mempool: Vec<(ContractPayment, TransactionStats)>,
+}
+
 pub struct NodeContext<B: Blockchain> {
     pub opts: NodeOptions,
     pub pub_key: <Signer as SignatureScheme>::Pub,
@@ -24,6 +59,10 @@ pub struct NodeContext<B: Blockchain> {
     pub wallet: Option<Wallet>,
     pub peers: HashMap<PeerAddress, Peer>,
     pub timestamp_offset: i32,
+    // Local timestamp of the node startup
+    pub started_at: Timestamp,
+    // Local timestamp of the last time the mempools were saved
+    pub mempool_persisted_at: Timestamp,
     pub miner_puzzle: Option<BlockPuzzle>,
 
     pub mempool: HashMap<TransactionAndDelta, TransactionStats>,
@@ -32,16 +71,69 @@ pub struct NodeContext<B: Blockchain> {
 
     pub outdated_since: Option<Timestamp>,
     pub banned_headers: HashMap<Header, Timestamp>,
+
+    // Number of consecutive times a peer failed to provide valid states
+    pub state_failures: HashMap<PeerAddress, u32>,
+    pub state_unavailable_peers: HashMap<PeerAddress, Timestamp>,
+
+    pub sync_breakers: HashMap<PeerAddress, SyncBreaker>,
+    // Network timestamp of the last time the info of a peer was received
+    pub peer_info_updated: HashMap<PeerAddress, Timestamp>,
+
+    pub block_propagation: HashMap<PeerAddress, Histogram>,
+    pub request_latency: HashMap<PeerAddress, Histogram>,
+
+    // Chunks of the huge full-states being served, along with the tip they belong to
+    pub state_chunks: HashMap<ContractId, (<Hasher as Hash>::Output, Vec<zk::ZkStateChunk>)>,
+
+    // Most recent punishments, oldest first
+    pub punishments: VecDeque<PunishmentEvent>,
+
+    pub sync_status: SyncStatus,
+
+    pub connections: Arc<ConnectionLimiter>,
 }
 
 impl<B: Blockchain> NodeContext<B> {
     pub fn network_timestamp(&self) -> u32 {
         (utils::local_timestamp() as i32 + self.timestamp_offset) as u32
     }
-    pub fn punish(&mut self, bad_peer: PeerAddress, secs: u32) {
+    pub fn punish(&mut self, bad_peer: PeerAddress, reason: PunishmentReason) {
+        let secs = match reason {
+            PunishmentReason::NoResponse => self.opts.no_response_punish,
+            PunishmentReason::InvalidData => self.opts.invalid_data_punish,
+            PunishmentReason::IncorrectPower => self.opts.incorrect_power_punish,
+        };
+
+        // Peers might still be connecting right after the startup, they are still
+        // punished for serving invalid data though
+        if reason == PunishmentReason::NoResponse
+            && utils::local_timestamp() < self.started_at + self.opts.startup_grace_period
+        {
+            log::info!(
+                "Not punishing {} ({:?}) during the startup grace period",
+                bad_peer,
+                reason
+            );
+            return;
+        }
+
         self.peers
             .entry(bad_peer)
             .and_modify(|stats| stats.punish(secs, self.opts.max_punish));
+
+        if self.opts.max_punishment_events == 0 {
+            return;
+        }
+        while self.punishments.len() >= self.opts.max_punishment_events {
+            self.punishments.pop_front();
+        }
+        self.punishments.push_back(PunishmentEvent {
+            peer: bad_peer,
+            reason,
+            amount: secs,
+            timestamp: self.network_timestamp(),
+        });
     }
     pub fn get_info(&self) -> Result<PeerInfo, BlockchainError> {
         Ok(PeerInfo {
@@ -64,6 +156,223 @@ impl<B: Blockchain> NodeContext<B> {
             .collect()
     }
 
+    pub fn is_state_unavailable(&self, peer: PeerAddress, ts: Timestamp) -> bool {
+        self.state_unavailable_peers
+            .get(&peer)
+            .map(|since| ts < since + self.opts.state_unavailable_ban_time)
+            .unwrap_or(false)
+    }
+    pub fn num_state_unavailable_peers(&self, ts: Timestamp) -> usize {
+        self.state_unavailable_peers
+            .keys()
+            .filter(|p| self.is_state_unavailable(**p, ts))
+            .count()
+    }
+    // Peers with the same height as us, which are not known to lack the states
+    pub fn state_peers(&self, height: u64, ts: Timestamp) -> Vec<Peer> {
+        self.active_peers()
+            .into_iter()
+            .filter(|p| p.info.as_ref().map(|i| i.height == height).unwrap_or(false))
+            .filter(|p| !self.is_state_unavailable(p.address, ts))
+            .collect()
+    }
+    pub fn report_state_failure(&mut self, peer: PeerAddress, ts: Timestamp) {
+        let failures = self.state_failures.entry(peer).or_insert(0);
+        *failures += 1;
+        if *failures > self.opts.state_failure_threshold {
+            self.state_failures.remove(&peer);
+            self.state_unavailable_peers.insert(peer, ts);
+        }
+    }
+    // Patches failing validation are served by lying peers, other failures (E.g. the
+    // peer not having the states) only count towards the state-unavailable ban
+    pub fn report_state_error(&mut self, peer: PeerAddress, err: &BlockchainError, ts: Timestamp) {
+        match err {
+            BlockchainError::StatePatchTooBig
+            | BlockchainError::FullStateNotValid
+            | BlockchainError::DeltasInvalid => {
+                self.punish(peer, PunishmentReason::InvalidData);
+            }
+            _ => {
+                self.report_state_failure(peer, ts);
+            }
+        }
+    }
+    // Blocks are coming much slower than expected, probably the network hashrate
+    // has collapsed and blocks stay slow until the next difficulty retarget
+    pub fn hashrate_warning(&self) -> Result<bool, BlockchainError> {
+        Ok(self
+            .blockchain
+            .block_time_ratio(self.opts.hashrate_warning_window)?
+            .map(|ratio| ratio > self.opts.hashrate_warning_factor)
+            .unwrap_or(false))
+    }
+    pub fn report_state_success(&mut self, peer: PeerAddress) {
+        self.state_failures.remove(&peer);
+        self.state_unavailable_peers.remove(&peer);
+    }
+
+    pub fn is_sync_skipped(&self, peer: PeerAddress, ts: Timestamp) -> bool {
+        self.sync_breakers
+            .get(&peer)
+            .and_then(|b| b.opened_at)
+            .map(|since| ts < since + self.opts.sync_cooldown)
+            .unwrap_or(false)
+    }
+    pub fn report_sync_failure(&mut self, peer: PeerAddress, ts: Timestamp) {
+        let breaker = self.sync_breakers.entry(peer).or_default();
+        breaker.failures += 1;
+        // A single failure is enough for opening a half-open breaker again
+        if breaker.failures >= self.opts.sync_failure_threshold {
+            breaker.opened_at = Some(ts);
+        }
+    }
+    // Blocks that don't make the chain more powerful are served by lying peers
+    pub fn report_sync_error(&mut self, peer: PeerAddress, err: &NodeError, ts: Timestamp) {
+        if let NodeError::BlockchainError(BlockchainError::PowerNotIncreased) = err {
+            self.punish(peer, PunishmentReason::IncorrectPower);
+        }
+        self.report_sync_failure(peer, ts);
+    }
+    pub fn report_sync_success(&mut self, peer: PeerAddress) {
+        self.sync_breakers.remove(&peer);
+    }
+
+    pub fn update_peer_info(&mut self, peer: PeerAddress, info: PeerInfo, ts: Timestamp) {
+        if let Some(p) = self.peers.get_mut(&peer) {
+            p.info = Some(info);
+            self.peer_info_updated.insert(peer, ts);
+        }
+    }
+    pub fn is_peer_info_fresh(&self, peer: PeerAddress, ts: Timestamp) -> bool {
+        self.peer_info_updated
+            .get(&peer)
+            .map(|updated| ts < updated + self.opts.peer_info_ttl)
+            .unwrap_or(false)
+    }
+    // Find the peer that claims the highest power, ignoring stale claims and skipping
+    // the peers which have failed to serve valid blocks too many times in a row.
+    pub fn most_powerful_peer(&self, ts: Timestamp) -> Option<Peer> {
+        self.active_peers()
+            .into_iter()
+            .filter(|p| {
+                p.info.is_some()
+                    && self.is_peer_info_fresh(p.address, ts)
+                    && !self.is_sync_skipped(p.address, ts)
+            })
+            .max_by_key(|p| p.info.as_ref().map(|i| i.power).unwrap_or(0))
+    }
+
+    pub fn record_block_propagation(&mut self, peer: PeerAddress, header: &Header, now: Timestamp) {
+        self.block_propagation
+            .entry(peer)
+            .or_insert_with(|| Histogram::new(&BLOCK_PROPAGATION_BUCKETS))
+            .observe(now.saturating_sub(header.proof_of_work.timestamp) as u64);
+    }
+    pub fn record_synced_blocks(&mut self, peer: PeerAddress, blocks: &[Block], now: Timestamp) {
+        if blocks.len() <= MAX_PROPAGATION_BATCH {
+            for block in blocks {
+                self.record_block_propagation(peer, &block.header, now);
+            }
+        }
+    }
+    pub fn record_request_latency(&mut self, peer: PeerAddress, latency: Duration) {
+        self.request_latency
+            .entry(peer)
+            .or_insert_with(|| Histogram::new(&REQUEST_LATENCY_BUCKETS))
+            .observe(latency.as_millis() as u64);
+    }
+
+    // Drops the peer along with everything tracked about it
+    pub fn forget_peer(&mut self, peer: PeerAddress) {
+        self.peers.remove(&peer);
+        self.state_failures.remove(&peer);
+        self.state_unavailable_peers.remove(&peer);
+        self.sync_breakers.remove(&peer);
+        self.peer_info_updated.remove(&peer);
+        self.block_propagation.remove(&peer);
+        self.request_latency.remove(&peer);
+    }
+
+    // Oldest bans get evicted when the ban list is full
+    pub fn ban_header(&mut self, header: Header, ts: Timestamp) {
+        while self.banned_headers.len() >= self.opts.max_banned_headers {
+            let oldest = self
+                .banned_headers
+                .iter()
+                .min_by_key(|(_, banned_ts)| **banned_ts)
+                .map(|(h, _)| h.clone());
+            match oldest {
+                Some(oldest) => {
+                    self.banned_headers.remove(&oldest);
+                }
+                None => {
+                    return;
+                }
+            }
+        }
+        self.banned_headers.insert(header, ts);
+    }
+    pub fn sweep_banned_headers(&mut self, ts: Timestamp) {
+        let ban_time = self.opts.state_unavailable_ban_time;
+        self.banned_headers
+            .retain(|_, banned_ts| ts.saturating_sub(*banned_ts) < ban_time);
+    }
+
+    // Drops the expired transactions and the cheapest ones above the capacity,
+    // returns the number of dropped transactions
+    // Transactions are only evicted from the end of their sender's nonce chain,
+    // so that no transaction is left waiting for a nonce that was dropped
+    pub fn trim_mempool(&mut self, ts: Timestamp) -> usize {
+        let before = self.mempool.len();
+        let max_age = self.opts.mempool_max_age;
+        // Later transactions of the sender of an expired transaction expire with it
+        let mut expired_since: HashMap<Address, u32> = HashMap::new();
+        for (tx, stats) in self.mempool.iter() {
+            if ts.saturating_sub(stats.first_seen) >= max_age {
+                let nonce = expired_since
+                    .entry(tx.tx.src.clone())
+                    .or_insert(tx.tx.nonce);
+                *nonce = (*nonce).min(tx.tx.nonce);
+            }
+        }
+        self.mempool
+            .retain(|tx, _| match expired_since.get(&tx.tx.src) {
+                Some(nonce) => tx.tx.nonce < *nonce,
+                None => true,
+            });
+        if self.mempool.len() > self.opts.mempool_capacity {
+            let mut chains: HashMap<Address, Vec<TransactionAndDelta>> = HashMap::new();
+            for tx in self.mempool.keys() {
+                chains
+                    .entry(tx.tx.src.clone())
+                    .or_default()
+                    .push(tx.clone());
+            }
+            let mut chains = chains.into_values().collect::<Vec<_>>();
+            for chain in chains.iter_mut() {
+                chain.sort_by_key(|t| t.tx.nonce);
+            }
+            let mut tails = chains
+                .iter()
+                .enumerate()
+                .filter_map(|(i, chain)| chain.last().map(|t| Reverse((t.fee_rate(), i))))
+                .collect::<BinaryHeap<_>>();
+            let excess = self.mempool.len() - self.opts.mempool_capacity;
+            for _ in 0..excess {
+                if let Some(Reverse((_, i))) = tails.pop() {
+                    if let Some(tx) = chains[i].pop() {
+                        self.mempool.remove(&tx);
+                    }
+                    if let Some(tx) = chains[i].last() {
+                        tails.push(Reverse((tx.fee_rate(), i)));
+                    }
+                }
+            }
+        }
+        before - self.mempool.len()
+    }
+
     pub fn cleanup_mempools(&mut self) -> Result<(), BlockchainError> {
         self.blockchain
             .cleanup_contract_payment_mempool(&mut self.dw_mempool)?;
@@ -71,16 +380,288 @@ impl<B: Blockchain> NodeContext<B> {
         Ok(())
     }
 
+    pub fn save_mempools(&self, path: &Path) -> Result<(), NodeError> {
+        let persisted = PersistedMempools {
+            mempool: self.mempool.clone().into_iter().collect(),
+            zero_mempool: self.zero_mempool.clone().into_iter().collect(),
+            dw_mempool: self.dw_mempool.clone().into_iter().collect(),
+        };
+        // Write to a temporary file first, so that a crash never leaves a partial file
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, bincode::serialize(&persisted)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    // Transactions are re-validated against the current state, the ones which are
+    // no longer valid are dropped
+    // A corrupted mempool file shouldn't prevent the node from starting, the node
+    // starts with empty mempools instead
+    pub fn restore_mempools(&mut self, path: &Path) {
+        if let Err(e) = self.load_mempools(path) {
+            log::warn!("Could not load the saved mempools: {}", e);
+            self.mempool.clear();
+            self.zero_mempool.clear();
+            self.dw_mempool.clear();
+        }
+    }
+
+    pub fn load_mempools(&mut self, path: &Path) -> Result<(), NodeError> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let persisted: PersistedMempools = bincode::deserialize(&std::fs::read(path)?)?;
+
+        let mut txs = persisted.mempool;
+        txs.sort_by_key(|(t, _)| t.tx.nonce);
+        for (tx_delta, stats) in txs {
+            if !tx_delta.tx.verify_signature() {
+                continue;
+            }
+            if let Err(e) = self.add_transaction(tx_delta, stats.first_seen) {
+                log::warn!("Dropped a persisted transaction: {}", e);
+            }
+        }
+        for (tx, stats) in persisted.zero_mempool {
+            if self.blockchain.validate_zero_transaction(&tx)? {
+                self.zero_mempool.insert(tx, stats);
+            }
+        }
+        for (tx, stats) in persisted.dw_mempool {
+            if self.blockchain.validate_dw_transaction(&tx)? {
+                self.dw_mempool.insert(tx, stats);
+            }
+        }
+        self.cleanup_mempools()?;
+        Ok(())
+    }
+
+    pub fn add_transaction(
+        &mut self,
+        tx_delta: TransactionAndDelta,
+        now: Timestamp,
+    ) -> Result<(), NodeError> {
+        // Replacing a transaction may invalidate the later transactions of the same
+        // sender, the replacement should pay more than all of the evicted ones
+        let replaced = self
+            .mempool
+            .keys()
+            .find(|t| {
+                **t != tx_delta && t.tx.src == tx_delta.tx.src && t.tx.nonce == tx_delta.tx.nonce
+            })
+            .cloned();
+        let mut evicted = Vec::new();
+        if let Some(replaced) = replaced {
+            let descendants = self.evicted_descendants(&replaced, &tx_delta)?;
+            let evicted_fees =
+                replaced.tx.fee + descendants.iter().map(|t| t.tx.fee).sum::<Money>();
+            if tx_delta.tx.fee <= evicted_fees {
+                return Err(NodeError::ReplacementFeeTooLow);
+            }
+            evicted.push(replaced);
+            evicted.extend(descendants);
+        }
+
+        // Only one of the contract updates built on the same state can be
+        // confirmed, keep the one paying a higher fee rate
+        if let Some(base) = self.contract_update_base(&tx_delta.tx)? {
+            let mut conflicts = Vec::new();
+            for t in self.mempool.keys() {
+                if *t != tx_delta && self.contract_update_base(&t.tx)? == Some(base) {
+                    conflicts.push(t.clone());
+                }
+            }
+            if conflicts
+                .iter()
+                .any(|t| t.fee_rate() >= tx_delta.fee_rate())
+            {
+                return Err(NodeError::ConflictingContractUpdate);
+            }
+            for t in conflicts {
+                self.mempool.remove(&t);
+            }
+        }
+        for t in evicted {
+            self.mempool.remove(&t);
+        }
+
+        // Keep the lowest-nonce transactions of a sender when it hits the cap
+        let sender_txs = self
+            .mempool
+            .keys()
+            .filter(|t| t.tx.src == tx_delta.tx.src)
+            .cloned()
+            .collect::<Vec<_>>();
+        if sender_txs.len() >= self.opts.max_txs_per_sender {
+            match sender_txs.into_iter().max_by_key(|t| t.tx.nonce) {
+                Some(highest) if highest.tx.nonce > tx_delta.tx.nonce => {
+                    self.mempool.remove(&highest);
+                }
+                _ => {
+                    return Err(NodeError::SenderMempoolFull);
+                }
+            }
+        }
+        if !self.mempool.contains_key(&tx_delta) {
+            self.emit(NodeEvent::TransactionAccepted(Box::new(tx_delta.clone())));
+        }
+        self.mempool
+            .insert(tx_delta, TransactionStats { first_seen: now });
+        Ok(())
+    }
+
+    fn emit(&self, event: NodeEvent) {
+        if let Some(events) = &self.opts.events {
+            // The embedder may have stopped listening, which is not our concern
+            let _ = events.send(event);
+        }
+    }
+
+    // Extends the chain, reporting the applied blocks
+    pub fn extend_chain(&mut self, from: u64, blocks: &[Block]) -> Result<(), BlockchainError> {
+        self.blockchain.extend(from, blocks)?;
+        for block in blocks {
+            self.emit(NodeEvent::BlockApplied(block.clone()));
+        }
+        Ok(())
+    }
+
+    // Later transactions of the sender of `replaced` which are valid now, but won't be
+    // valid anymore if `replaced` gets replaced by `replacement`
+    fn evicted_descendants(
+        &self,
+        replaced: &TransactionAndDelta,
+        replacement: &TransactionAndDelta,
+    ) -> Result<Vec<TransactionAndDelta>, BlockchainError> {
+        let mut before = self
+            .mempool
+            .iter()
+            .filter(|(t, _)| t.tx.src == replaced.tx.src)
+            .map(|(t, stats)| (t.clone(), stats.clone()))
+            .collect::<HashMap<_, _>>();
+        let mut after = before.clone();
+        after.remove(replaced);
+        after.insert(replacement.clone(), TransactionStats { first_seen: 0 });
+        self.blockchain.cleanup_mempool(&mut before)?;
+        self.blockchain.cleanup_mempool(&mut after)?;
+        Ok(before
+            .into_keys()
+            .filter(|t| t.tx.nonce > replaced.tx.nonce && !after.contains_key(t))
+            .collect())
+    }
+
+    // The contract and the compressed-state a contract update is built upon. That is
+    // the result of the previous mempool update of the same sender, if any, or else
+    // the current state of the contract.
+    fn contract_update_base(
+        &self,
+        tx: &Transaction,
+    ) -> Result<Option<(ContractId, zk::ZkCompressedState)>, BlockchainError> {
+        let contract_id = match &tx.data {
+            TransactionData::UpdateContract { contract_id, .. } => *contract_id,
+            _ => {
+                return Ok(None);
+            }
+        };
+        let prev_state = self.mempool.keys().find_map(|t| match &t.tx.data {
+            TransactionData::UpdateContract {
+                contract_id: cid,
+                updates,
+            } if *cid == contract_id && t.tx.src == tx.src && t.tx.nonce + 1 == tx.nonce => {
+                updates.last().map(|update| match update {
+                    ContractUpdate::DepositWithdraw { next_state, .. }
+                    | ContractUpdate::FunctionCall { next_state, .. } => *next_state,
+                })
+            }
+            _ => None,
+        });
+        let base = match prev_state {
+            Some(state) => state,
+            None => {
+                self.blockchain
+                    .get_contract_account(contract_id)?
+                    .compressed_state
+            }
+        };
+        Ok(Some((contract_id, base)))
+    }
+
+    // Net change of the balance of `addr` if the mempool transactions get confirmed.
+    // Among the transactions with the same sender and nonce only one can be confirmed,
+    // the one paying the highest fee is considered.
+    pub fn pending_balance_delta(&self, addr: &Address) -> i128 {
+        // The conflicts are resolved over the whole mempool, the winner of a conflict
+        // may not involve `addr` at all
+        let mut candidates: HashMap<(&Address, u32), &Transaction> = HashMap::new();
+        for tx_delta in self.mempool.keys() {
+            let tx = &tx_delta.tx;
+            let entry = candidates.entry((&tx.src, tx.nonce)).or_insert(tx);
+            if tx.fee > entry.fee {
+                *entry = tx;
+            }
+        }
+
+        let mut delta = 0i128;
+        for tx in candidates.values() {
+            if tx.src == *addr {
+                delta -= tx.fee as i128;
+            }
+            if let TransactionData::RegularSend { dst, amount } = &tx.data {
+                if tx.src == *addr {
+                    delta -= *amount as i128;
+                }
+                if dst == addr {
+                    delta += *amount as i128;
+                }
+            }
+        }
+        delta
+    }
+
+    // The account with the mempool transactions applied on top of it. The nonce is
+    // only advanced through the consecutive nonces available in the mempool.
+    pub fn pending_account(&self, addr: &Address) -> Result<Account, BlockchainError> {
+        let mut account = self.blockchain.get_account(addr.clone())?;
+        let balance = account.balance as i128 + self.pending_balance_delta(addr);
+        account.balance = balance.max(0) as Money;
+        while self
+            .mempool
+            .keys()
+            .any(|t| t.tx.src == *addr && t.tx.nonce == account.nonce + 1)
+        {
+            account.nonce += 1;
+        }
+        Ok(account)
+    }
+
+    // A puzzle is only worth solving as long as it extends the current tip
+    pub fn expire_stale_puzzle(&mut self) -> Result<bool, BlockchainError> {
+        if let Some((draft, _)) = self.miner_puzzle.as_ref() {
+            if draft.block.header.parent_hash != self.blockchain.get_tip()?.hash() {
+                self.miner_puzzle = None;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
     pub fn get_puzzle(&mut self, wallet: Wallet) -> Result<Option<BlockPuzzle>, BlockchainError> {
         let ts = self.network_timestamp();
-        let draft = self
-            .blockchain
-            .draft_block(ts, &self.mempool, &wallet, true)?;
+        let draft = match self.blockchain.draft_block_with_strategy(
+            ts,
+            &self.mempool,
+            &wallet,
+            true,
+            self.opts.mempool_strategy.as_ref(),
+        ) {
+            Err(BlockchainError::NothingToMine) => None,
+            res => res?,
+        };
         if let Some(draft) = draft {
             let puzzle = Puzzle {
                 key: hex::encode(self.blockchain.pow_key(draft.block.header.number)?),
                 blob: hex::encode(bincode::serialize(&draft.block.header).unwrap()),
-                offset: 80,
+                offset: 84,
                 size: 8,
                 target: draft.block.header.proof_of_work.target,
             };
@@ -24,5 +24,9 @@ pub async fn log_info<B: Blockchain>(
     inf.push(("Power".to_string(), ctx.blockchain.get_power()?.to_string()));
     log::info!("Lub dub! {:?}", inf);
 
+    if ctx.hashrate_warning()? {
+        log::warn!("Blocks are much slower than expected, has the network hashrate collapsed?");
+    }
+
     Ok(())
 }
@@ -1,12 +1,17 @@
 mod log_info;
 
 mod cleanup_mempool;
-mod sync_blocks;
+pub(super) mod persist_mempool;
+mod refresh_peer_info;
+mod sweep_banned_headers;
+pub(super) mod sync_blocks;
 mod sync_clock;
-mod sync_peers;
-mod sync_state;
+pub(super) mod sync_peers;
+pub(super) mod sync_state;
+mod trim_mempool;
+mod update_sync_status;
 
-use super::{http, Limit, NodeContext, NodeError, Peer, PeerAddress};
+use super::{http, Limit, NodeContext, NodeError, Peer, PeerAddress, PunishmentReason, SyncStatus};
 use crate::blockchain::Blockchain;
 use crate::client::messages::*;
 use crate::utils;
@@ -18,10 +23,15 @@ use tokio::time::sleep;
 pub async fn heartbeat<B: Blockchain>(
     context: Arc<RwLock<NodeContext<B>>>,
 ) -> Result<(), NodeError> {
+    trim_mempool::trim_mempool(&context).await?;
     cleanup_mempool::cleanup_mempool(&context).await?;
+    persist_mempool::persist_mempool(&context).await?;
+    sweep_banned_headers::sweep_banned_headers(&context).await?;
     log_info::log_info(&context).await?;
     sync_clock::sync_clock(&context).await?;
     sync_peers::sync_peers(&context).await?;
+    refresh_peer_info::refresh_peer_info(&context).await?;
+    update_sync_status::update_sync_status(&context).await?;
     sync_blocks::sync_blocks(&context).await?;
     sync_state::sync_state(&context).await?;
     Ok(())
@@ -48,7 +58,6 @@ pub async fn heartbeater<B: Blockchain>(
 fn punish_non_responding<B: Blockchain, R: Clone, E>(
     ctx: &mut RwLockWriteGuard<'_, NodeContext<B>>,
     resps: &[(Peer, Result<R, E>)],
-    amount: u32,
 ) -> Vec<(PeerAddress, R)> {
     resps
         .iter()
@@ -56,7 +65,7 @@ fn punish_non_responding<B: Blockchain, R: Clone, E>(
             if let Ok(resp) = resp {
                 Some((peer.address, resp.clone()))
             } else {
-                ctx.punish(peer.address, amount);
+                ctx.punish(peer.address, PunishmentReason::NoResponse);
                 None
             }
         })
@@ -0,0 +1,19 @@
+use super::*;
+
+pub async fn persist_mempool<B: Blockchain>(
+    context: &Arc<RwLock<NodeContext<B>>>,
+) -> Result<(), NodeError> {
+    let mut ctx = context.write().await;
+    let now = utils::local_timestamp();
+    if let Some(path) = ctx.opts.mempool_path.clone() {
+        if now.saturating_sub(ctx.mempool_persisted_at) >= ctx.opts.mempool_persist_interval {
+            // Best-effort, a full or read-only disk shouldn't stop the rest of the
+            // heartbeat. Retried on the next interval.
+            if let Err(e) = ctx.save_mempools(&path) {
+                log::warn!("Could not save the mempools: {}", e);
+            }
+            ctx.mempool_persisted_at = now;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use super::*;
+use crate::client::PeerInfo;
+
+pub async fn refresh_peer_info<B: Blockchain>(
+    context: &Arc<RwLock<NodeContext<B>>>,
+) -> Result<(), NodeError> {
+    let ctx = context.read().await;
+
+    let net = ctx.outgoing.clone();
+    let opts = ctx.opts.clone();
+    let ts = ctx.network_timestamp();
+
+    let outdated_peers = ctx
+        .active_peers()
+        .into_iter()
+        .filter(|p| {
+            ctx.peer_info_updated
+                .get(&p.address)
+                .map(|updated| ts >= updated + opts.peer_info_refresh_interval)
+                .unwrap_or(true)
+        })
+        .collect::<Vec<_>>();
+    drop(ctx);
+
+    if outdated_peers.is_empty() {
+        return Ok(());
+    }
+
+    let peer_responses: Vec<(Peer, Result<GetStatsResponse, NodeError>)> =
+        http::group_request(&outdated_peers, |peer| {
+            net.json_get::<GetStatsRequest, GetStatsResponse>(
+                format!("{}/stats", peer.address),
+                GetStatsRequest {},
+                Limit::default().size(1024 * 1024).time(1000),
+            )
+        })
+        .await;
+
+    {
+        let mut ctx = context.write().await;
+        for (addr, resp) in punish_non_responding(&mut ctx, &peer_responses) {
+            ctx.update_peer_info(
+                addr,
+                PeerInfo {
+                    height: resp.height,
+                    power: resp.power,
+                },
+                ts,
+            );
+        }
+    }
+
+    Ok(())
+}
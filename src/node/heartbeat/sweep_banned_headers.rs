@@ -0,0 +1,10 @@
+use super::*;
+
+pub async fn sweep_banned_headers<B: Blockchain>(
+    context: &Arc<RwLock<NodeContext<B>>>,
+) -> Result<(), NodeError> {
+    let mut ctx = context.write().await;
+    let ts = ctx.network_timestamp();
+    ctx.sweep_banned_headers(ts);
+    Ok(())
+}
@@ -1,4 +1,7 @@
 use super::*;
+use crate::client::{OutgoingSender, PeerInfo};
+use crate::node::NodeOptions;
+use std::time::Instant;
 
 pub async fn sync_blocks<B: Blockchain>(
     context: &Arc<RwLock<NodeContext<B>>>,
@@ -9,24 +12,64 @@ pub async fn sync_blocks<B: Blockchain>(
     let opts = ctx.opts.clone();
 
     let height = ctx.blockchain.get_height()?;
+    let ts = ctx.network_timestamp();
 
-    // Find the peer that claims the highest power.
-    let most_powerful = ctx
-        .active_peers()
-        .into_iter()
-        .max_by_key(|p| p.info.as_ref().map(|i| i.power).unwrap_or(0))
-        .ok_or(NodeError::NoPeers)?;
+    // Having no peer to sync from (E.g. all of them are punished or failing) is
+    // not an error, the rest of the heartbeat should still run
+    let most_powerful = match ctx.most_powerful_peer(ts) {
+        Some(peer) => peer,
+        None => return Ok(()),
+    };
     drop(ctx);
 
-    let most_powerful_info = most_powerful.info.as_ref().ok_or(NodeError::NoPeers)?;
+    let most_powerful_info = match most_powerful.info.as_ref() {
+        Some(info) => info,
+        None => return Ok(()),
+    };
 
     if most_powerful_info.power <= power {
         return Ok(());
     }
 
+    let result = sync_from(
+        context,
+        &net,
+        &opts,
+        &most_powerful,
+        most_powerful_info,
+        height,
+    )
+    .await;
+
+    let mut ctx = context.write().await;
+    match &result {
+        Ok(true) => {
+            ctx.report_sync_success(most_powerful.address);
+        }
+        Ok(false) => {
+            ctx.punish(most_powerful.address, PunishmentReason::IncorrectPower);
+            ctx.report_sync_failure(most_powerful.address, ts);
+        }
+        Err(e) => {
+            ctx.report_sync_error(most_powerful.address, e, ts);
+        }
+    }
+    result.map(|_| ())
+}
+
+// Returns false when the blocks of the peer don't extend the local chain
+async fn sync_from<B: Blockchain>(
+    context: &Arc<RwLock<NodeContext<B>>>,
+    net: &OutgoingSender,
+    opts: &NodeOptions,
+    most_powerful: &Peer,
+    most_powerful_info: &PeerInfo,
+    height: u64,
+) -> Result<bool, NodeError> {
     let start_height = std::cmp::min(height, most_powerful_info.height);
 
     // Get all headers starting from the indices that we don't have.
+    let start = Instant::now();
     let mut headers = net
         .bincode_get::<GetHeadersRequest, GetHeadersResponse>(
             format!("{}/bincode/headers", most_powerful.address),
@@ -38,6 +81,10 @@ pub async fn sync_blocks<B: Blockchain>(
         )
         .await?
         .headers;
+    context
+        .write()
+        .await
+        .record_request_latency(most_powerful.address, start.elapsed());
 
     // The local blockchain and the peer blockchain both have all blocks
     // from 0 to height-1, though, the blocks might not be equal. Find
@@ -90,23 +137,24 @@ pub async fn sync_blocks<B: Blockchain>(
                 .unwrap_or(false)
     };
 
-    if will_extend {
-        let resp = net
-            .bincode_get::<GetBlocksRequest, GetBlocksResponse>(
-                format!("{}/bincode/blocks", most_powerful.address).to_string(),
-                GetBlocksRequest {
-                    since: headers[0].number,
-                    until: None,
-                },
-                Limit::default().size(1024 * 1024).time(1000),
-            )
-            .await?;
-        let mut ctx = context.write().await;
-        ctx.blockchain.extend(headers[0].number, &resp.blocks)?;
-    } else {
-        let mut ctx = context.write().await;
-        ctx.punish(most_powerful.address, opts.incorrect_power_punish);
+    if !will_extend {
+        return Ok(false);
     }
 
-    Ok(())
+    let resp = net
+        .bincode_get::<GetBlocksRequest, GetBlocksResponse>(
+            format!("{}/bincode/blocks", most_powerful.address).to_string(),
+            GetBlocksRequest {
+                since: headers[0].number,
+                until: None,
+            },
+            Limit::default().size(1024 * 1024).time(1000),
+        )
+        .await?;
+    let mut ctx = context.write().await;
+    ctx.extend_chain(headers[0].number, &resp.blocks)?;
+    let now = ctx.network_timestamp();
+    ctx.record_synced_blocks(most_powerful.address, &resp.blocks, now);
+
+    Ok(true)
 }
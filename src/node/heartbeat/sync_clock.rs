@@ -11,6 +11,7 @@ pub async fn sync_clock<B: Blockchain>(
 
     let timestamp = ctx.network_timestamp();
     let info = ctx.get_info()?;
+    let network_magic = ctx.blockchain.config().network_magic;
     let peer_addresses = ctx.random_peers(&mut rand::thread_rng(), opts.num_peers);
     drop(ctx);
 
@@ -22,6 +23,7 @@ pub async fn sync_clock<B: Blockchain>(
                     address,
                     timestamp,
                     info: info.clone(),
+                    network_magic,
                 },
                 Limit::default().size(1024 * 1024).time(1000),
             )
@@ -30,10 +32,16 @@ pub async fn sync_clock<B: Blockchain>(
 
     {
         let mut ctx = context.write().await;
-        let timestamps = punish_non_responding(&mut ctx, &peer_responses, opts.no_response_punish)
-            .into_iter()
-            .map(|(_, r)| r.timestamp)
-            .collect::<Vec<_>>();
+        let mut timestamps = Vec::new();
+        for (addr, resp) in punish_non_responding(&mut ctx, &peer_responses) {
+            // Forget peers of other networks
+            if resp.network_magic != network_magic {
+                ctx.forget_peer(addr);
+                continue;
+            }
+            timestamps.push(resp.timestamp);
+            ctx.update_peer_info(addr, resp.info, timestamp);
+        }
         if !timestamps.is_empty() {
             // Set timestamp_offset according to median timestamp of the network
             let median_timestamp = utils::median(&timestamps);
@@ -1,4 +1,19 @@
 use super::*;
+use crate::blockchain::BlockchainError;
+use crate::client::OutgoingSender;
+use crate::core::ContractId;
+use crate::zk;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::collections::HashMap;
+use std::time::Instant;
+
+// Responses of the peers are refused (Before being deserialized) once they get
+// bigger than this
+const MAX_RESPONSE_BYTES: u64 = 1024 * 1024;
+
+// Encoded size of a single scalar of a state patch, along with its locator (With
+// plenty of slack). Bounds the raw size of a patch by `max_state_patch_size`.
+const STATE_PATCH_ENTRY_BYTES: u64 = 128;
 
 pub async fn sync_state<B: Blockchain>(
     context: &Arc<RwLock<NodeContext<B>>>,
@@ -16,40 +31,172 @@ pub async fn sync_state<B: Blockchain>(
     } else if outdated_heights.is_empty() && ctx.outdated_since.is_some() {
         ctx.outdated_since = None;
     }
+    let ban_time = ctx.opts.state_unavailable_ban_time;
+    ctx.state_unavailable_peers
+        .retain(|_, since| ts < *since + ban_time);
+
     // Find clients which their height is equal with our height
-    let same_height_peers = ctx
-        .active_peers()
-        .into_iter()
-        .filter(|p| p.info.as_ref().map(|i| i.height == height).unwrap_or(false));
+    let same_height_peers = ctx.state_peers(height, ts);
 
     if !outdated_heights.is_empty() {
         if let Some(outdated_since) = ctx.outdated_since {
             if (ts as i64 - outdated_since as i64) > ctx.opts.outdated_heights_threshold as i64 {
-                ctx.banned_headers.insert(last_header, ts);
+                ctx.ban_header(last_header, ts);
                 ctx.blockchain.rollback()?;
                 ctx.outdated_since = None;
                 return Ok(());
             }
         }
 
+        let max_requests = ctx.opts.max_concurrent_state_requests;
+        let max_chunks =
+            ctx.blockchain.config().max_state_patch_size / ctx.opts.state_chunk_size.max(1) + 1;
+        let max_bytes =
+            ctx.blockchain.config().max_state_patch_size as u64 * STATE_PATCH_ENTRY_BYTES;
         drop(ctx);
-        for peer in same_height_peers {
-            let patch = net
-                .bincode_get::<GetStatesRequest, GetStatesResponse>(
-                    format!("{}/bincode/states", peer.address),
-                    GetStatesRequest {
-                        outdated_heights: outdated_heights.clone(),
-                        to: hex::encode(last_header.hash()),
-                    },
-                    Limit::default().size(1024 * 1024).time(1000),
-                )
-                .await?
-                .patch;
-            let mut ctx = context.write().await;
-            if ctx.blockchain.update_states(&patch).is_ok() {
-                break;
-            }
+
+        let to = hex::encode(last_header.hash());
+        let peers = same_height_peers;
+        if peers.is_empty() {
+            return Ok(());
         }
+
+        // Outdated contracts are synced concurrently, each of them raced over several
+        // peers (Starting from a different peer per contract, to spread the load).
+        // The first patch which brings the contract up to date wins, the remaining
+        // requests of that contract get cancelled once its `in_flight` is dropped.
+        let (net, to, outdated_heights) = (&net, &to, &outdated_heights);
+        let sync_contract = |i: usize, cid: ContractId| {
+            let fetch = move |peer: &Peer| {
+                let net = Arc::clone(net);
+                let peer = peer.clone();
+                let outdated_heights = [(cid, outdated_heights[&cid])].into_iter().collect();
+                let to = to.clone();
+                async move {
+                    let sent = Instant::now();
+                    let resp =
+                        fetch_patch(&net, &peer, outdated_heights, to, max_chunks, max_bytes).await;
+                    (peer, resp, sent.elapsed())
+                }
+            };
+            let mut candidates = peers.iter().cycle().skip(i).take(peers.len());
+            let mut in_flight = candidates
+                .by_ref()
+                .take(max_requests)
+                .map(fetch)
+                .collect::<FuturesUnordered<_>>();
+            async move {
+                while let Some((peer, resp, latency)) = in_flight.next().await {
+                    match resp {
+                        Ok(resp) => {
+                            let mut ctx = context.write().await;
+                            ctx.record_request_latency(peer.address, latency);
+                            // Each patch is validated on its own, the other contracts are
+                            // left outdated until their own patches arrive
+                            match ctx.blockchain.update_states_partial(&resp.patch) {
+                                Ok(_) => {
+                                    if !ctx.blockchain.get_outdated_contracts()?.contains(&cid) {
+                                        ctx.report_state_success(peer.address);
+                                        return Ok(());
+                                    }
+                                    ctx.report_state_failure(peer.address, ts);
+                                }
+                                Err(e) => {
+                                    ctx.report_state_error(peer.address, &e, ts);
+                                }
+                            }
+                        }
+                        Err(NodeError::BlockchainError(e)) => {
+                            context
+                                .write()
+                                .await
+                                .report_state_error(peer.address, &e, ts);
+                        }
+                        Err(_) => {}
+                    }
+                    if let Some(peer) = candidates.next() {
+                        in_flight.push(fetch(peer));
+                    }
+                }
+                Ok::<(), NodeError>(())
+            }
+        };
+
+        let mut contracts = outdated_heights.keys().cloned().collect::<Vec<_>>();
+        contracts.sort_by_key(|cid| cid.to_string());
+        futures::stream::iter(contracts.into_iter().enumerate())
+            .map(|(i, cid)| sync_contract(i, cid))
+            .buffer_unordered(max_requests)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
     }
     Ok(())
 }
+
+// Fetches a state patch, along with the rest of the chunks of the full-states which
+// are served in chunks. The responses of a patch may take `max_bytes` in total,
+// peers sending more than that are serving a patch bigger than the limit.
+async fn fetch_patch(
+    net: &OutgoingSender,
+    peer: &Peer,
+    outdated_heights: HashMap<ContractId, u64>,
+    to: String,
+    max_chunks: usize,
+    max_bytes: u64,
+) -> Result<GetStateChunksResponse, NodeError> {
+    let fetch = |outdated_heights, chunk, budget: u64| {
+        let fut = net.bincode_get::<GetStateChunksRequest, GetStateChunksResponse>(
+            format!("{}/bincode/states/chunks", peer.address),
+            GetStateChunksRequest {
+                outdated_heights,
+                to: to.clone(),
+                chunk,
+            },
+            Limit::default()
+                .size(budget.min(MAX_RESPONSE_BYTES))
+                .time(1000),
+        );
+        async move {
+            match fut.await {
+                Err(NodeError::SizeLimitError) if budget < MAX_RESPONSE_BYTES => {
+                    Err(BlockchainError::StatePatchTooBig.into())
+                }
+                resp => resp,
+            }
+        }
+    };
+    let mut resp = fetch(outdated_heights.clone(), 0, max_bytes).await?;
+    let mut remaining = max_bytes.saturating_sub(bincode::serialized_size(&resp)?);
+
+    let chunked = resp
+        .patch
+        .patches
+        .iter()
+        .filter_map(|(cid, patch)| match patch {
+            zk::ZkStatePatch::Chunks(chunks) => chunks.first().map(|c| (*cid, c.total)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for (cid, total) in chunked {
+        // Peers announcing more chunks than a valid patch could have are not followed
+        let height = match outdated_heights.get(&cid) {
+            Some(height) if total <= max_chunks => *height,
+            _ => {
+                continue;
+            }
+        };
+        for chunk in 1..total {
+            let part = fetch([(cid, height)].into_iter().collect(), chunk, remaining).await?;
+            remaining = remaining.saturating_sub(bincode::serialized_size(&part)?);
+            let mut part = part.patch;
+            if let (Some(zk::ZkStatePatch::Chunks(chunks)), Some(zk::ZkStatePatch::Chunks(part))) =
+                (resp.patch.patches.get_mut(&cid), part.patches.remove(&cid))
+            {
+                chunks.extend(part);
+            }
+        }
+    }
+    Ok(resp)
+}
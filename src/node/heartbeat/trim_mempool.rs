@@ -0,0 +1,13 @@
+use super::*;
+
+pub async fn trim_mempool<B: Blockchain>(
+    context: &Arc<RwLock<NodeContext<B>>>,
+) -> Result<(), NodeError> {
+    let mut ctx = context.write().await;
+    let ts = ctx.network_timestamp();
+    let dropped = ctx.trim_mempool(ts);
+    if dropped > 0 {
+        log::info!("Dropped {} transactions from the mempool", dropped);
+    }
+    Ok(())
+}
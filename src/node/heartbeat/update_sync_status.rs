@@ -0,0 +1,25 @@
+use super::*;
+
+pub async fn update_sync_status<B: Blockchain>(
+    context: &Arc<RwLock<NodeContext<B>>>,
+) -> Result<(), NodeError> {
+    let mut ctx = context.write().await;
+    let tip_height = ctx.blockchain.get_height()?;
+    let power = ctx.blockchain.get_power()?;
+    let states_outdated = !ctx.blockchain.get_outdated_contracts()?.is_empty();
+    let ts = ctx.network_timestamp();
+    // The same peer we would sync from, falling back to ourselves when there is none
+    let (best_peer_height, best_peer_power) = ctx
+        .most_powerful_peer(ts)
+        .and_then(|p| p.info)
+        .map(|i| (i.height, i.power))
+        .unwrap_or((tip_height, power));
+    ctx.sync_status = SyncStatus {
+        tip_height,
+        best_peer_height,
+        best_peer_power,
+        states_outdated,
+        is_syncing: best_peer_power > power || states_outdated,
+    };
+    Ok(())
+}
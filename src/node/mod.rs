@@ -2,28 +2,40 @@
 mod test;
 
 mod api;
+mod connections;
 mod context;
 mod heartbeat;
 mod http;
+mod idle_timeout;
 pub mod seeds;
+pub mod self_test;
+mod solo_miner;
 pub mod upnp;
+pub use connections::{ConnectionGuard, ConnectionLimiter};
 use context::NodeContext;
+pub use idle_timeout::IdleTimeoutStream;
 
-use crate::blockchain::Blockchain;
+use crate::blockchain::{Blockchain, BlockchainError, MempoolStrategy};
+use crate::client::messages::AdminOptions;
 use crate::client::{
-    Limit, NodeError, NodeRequest, OutgoingSender, Peer, PeerAddress, PeerInfo, Timestamp,
+    compression, Histogram, Limit, NodeError, NodeRequest, OutgoingSender, Peer, PeerAddress,
+    PeerInfo, PunishmentReason, SyncStatus, Timestamp,
 };
+use crate::core::{Block, TransactionAndDelta};
 use crate::crypto::ed25519;
 use crate::crypto::SignatureScheme;
+use crate::utils;
 use crate::wallet::Wallet;
 use hyper::body::HttpBody;
-use hyper::header::AUTHORIZATION;
+use hyper::header::{HeaderValue, ACCEPT_ENCODING, AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE};
 use hyper::{Body, Method, Request, Response, StatusCode};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
+use tokio::sync::mpsc;
 use tokio::sync::RwLock;
 use tokio::try_join;
 
@@ -32,11 +44,90 @@ pub struct NodeOptions {
     pub heartbeat_interval: Duration,
     pub num_peers: usize,
     pub outdated_heights_threshold: u32,
+    // Peers failing to serve states more than this many times in a row are marked
+    // state-unavailable for `state_unavailable_ban_time` seconds
+    pub state_failure_threshold: u32,
     pub no_response_punish: u32,
     pub invalid_data_punish: u32,
     pub incorrect_power_punish: u32,
     pub max_punish: u32,
     pub state_unavailable_ban_time: u32,
+    pub max_txs_per_sender: usize,
+    pub max_banned_headers: usize,
+    pub max_punishment_events: usize,
+    pub http_keep_alive: bool,
+    pub http_header_read_timeout: Duration,
+    pub http_tcp_keep_alive: Option<Duration>,
+    pub http_body_read_timeout: Duration,
+    // Connections making no progress (Reading or writing) for this long are closed
+    pub http_idle_timeout: Duration,
+    pub max_connections: usize,
+    // Mine blocks internally, when a wallet is available
+    pub solo_mine: bool,
+    // Order in which mempool transactions are picked for new blocks
+    pub mempool_strategy: Arc<dyn MempoolStrategy>,
+    // Number of state patch requests (One per outdated contract) in flight at the same time
+    pub max_concurrent_state_requests: usize,
+    // Full-states with more scalars than this are served in several requests
+    pub state_chunk_size: usize,
+    // Check PoW, signing, serialization and storage before starting
+    pub run_self_test: bool,
+    // Peers failing to serve valid blocks this many times in a row are skipped by
+    // the block sync for `sync_cooldown` seconds
+    pub sync_failure_threshold: u32,
+    pub sync_cooldown: u32,
+    // Seconds after the startup in which peers are not punished
+    pub startup_grace_period: u32,
+    // File the mempools are saved to (Every `mempool_persist_interval` seconds and on
+    // shutdown) and reloaded from on startup
+    pub mempool_path: Option<PathBuf>,
+    pub mempool_persist_interval: u32,
+    // Limits of a single state-patch response, contracts past them are left out
+    pub max_state_patch_contracts: usize,
+    pub max_state_patch_bytes: usize,
+    // Number of headers in each chunk of the header stream (Served to light clients)
+    pub header_stream_chunk_size: usize,
+    // Warn when the blocks of the last `hashrate_warning_window` blocks are on average
+    // `hashrate_warning_factor` times slower than the target block time
+    pub hashrate_warning_window: u64,
+    pub hashrate_warning_factor: f64,
+    // Peer infos older than `peer_info_refresh_interval` seconds are re-queried, and
+    // the ones older than `peer_info_ttl` seconds are not trusted by the block sync
+    pub peer_info_refresh_interval: u32,
+    pub peer_info_ttl: u32,
+    // Reject the inbound transactions which don't re-encode to the exact bytes
+    // they were received as
+    pub reject_non_canonical_encodings: bool,
+    // Mempool transactions older than `mempool_max_age` seconds are dropped, and the
+    // ones paying the lowest fee rates are evicted once there are more than
+    // `mempool_capacity` of them
+    pub mempool_max_age: u32,
+    pub mempool_capacity: usize,
+    // Receives the events of the node, for in-process embedders (E.g. indexers)
+    pub events: Option<mpsc::UnboundedSender<NodeEvent>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum NodeEvent {
+    // Admitted to the mempool
+    TransactionAccepted(Box<TransactionAndDelta>),
+    BlockApplied(Block),
+}
+
+impl NodeOptions {
+    pub fn admin_options(&self) -> AdminOptions {
+        AdminOptions {
+            num_peers: self.num_peers,
+            outdated_heights_threshold: self.outdated_heights_threshold,
+            no_response_punish: self.no_response_punish,
+            invalid_data_punish: self.invalid_data_punish,
+            incorrect_power_punish: self.incorrect_power_punish,
+            max_punish: self.max_punish,
+            state_unavailable_ban_time: self.state_unavailable_ban_time,
+            max_txs_per_sender: self.max_txs_per_sender,
+            max_concurrent_state_requests: self.max_concurrent_state_requests,
+        }
+    }
 }
 
 fn fetch_signature(
@@ -62,7 +153,121 @@ fn fetch_signature(
     Ok(None)
 }
 
-async fn node_service<B: Blockchain>(
+// Reads the request body, giving up once it gets bigger than `limit`
+async fn read_body(mut body: Body, limit: usize) -> Result<Option<Vec<u8>>, NodeError> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        bytes.extend_from_slice(&chunk?);
+        if bytes.len() > limit {
+            return Ok(None);
+        }
+    }
+    Ok(Some(bytes))
+}
+
+// Bincode accepts more than one encoding of the same value (E.g. with trailing
+// bytes), when `canonical`, only the bytes the value re-encodes to are accepted
+fn bincode_deserialize<T: serde::Serialize + serde::de::DeserializeOwned>(
+    bytes: &[u8],
+    canonical: bool,
+) -> Result<T, NodeError> {
+    let value: T = bincode::deserialize(bytes)?;
+    if canonical && bincode::serialize(&value)? != bytes {
+        return Err(NodeError::NonCanonicalEncoding);
+    }
+    Ok(value)
+}
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// Short random token for correlating the log lines of a single request
+fn new_request_id() -> String {
+    hex::encode(rand::random::<[u8; 4]>())
+}
+
+tokio::task_local! {
+    // Id of the request being served by the current task
+    static REQUEST_ID: String;
+}
+
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+// Lines logged while serving a request (Even deep inside the blockchain) are
+// tagged with the id of the request
+pub fn logger_builder() -> env_logger::Builder {
+    let mut builder = env_logger::Builder::from_default_env();
+    builder.format(|buf, record| {
+        use std::io::Write;
+        let tag = current_request_id()
+            .map(|id| format!("[{}] ", id))
+            .unwrap_or_default();
+        writeln!(
+            buf,
+            "[{} {} {}] {}{}",
+            buf.timestamp(),
+            record.level(),
+            record.target(),
+            tag,
+            record.args()
+        )
+    });
+    builder
+}
+
+// Status of the response a failed request is answered with
+fn error_status(err: &NodeError) -> StatusCode {
+    match err {
+        NodeError::InputError
+        | NodeError::InvalidOptions(_)
+        | NodeError::NonCanonicalEncoding
+        | NodeError::JsonError(_)
+        | NodeError::QueryStringError(_)
+        | NodeError::BincodeError(_) => StatusCode::BAD_REQUEST,
+        NodeError::InvalidSignatureHeader | NodeError::SignatureRequired => {
+            StatusCode::UNAUTHORIZED
+        }
+        NodeError::AdminRequired => StatusCode::FORBIDDEN,
+        NodeError::BlockchainError(
+            BlockchainError::ContractNotFound | BlockchainError::BlockNotFound,
+        ) => StatusCode::NOT_FOUND,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+async fn node_service<B: Blockchain + Send + Sync + 'static>(
+    client: Option<SocketAddr>,
+    context: Arc<RwLock<NodeContext<B>>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, NodeError> {
+    let request_id = new_request_id();
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+
+    REQUEST_ID
+        .scope(request_id.clone(), async move {
+            // Failures are answered too, so that the id reaches the client either way
+            let mut resp = match serve_request(client, context, req).await {
+                Ok(resp) => {
+                    log::debug!("{} {} -> {}", method, path, resp.status());
+                    resp
+                }
+                Err(e) => {
+                    log::error!("{} {} failed: {}", method, path, e);
+                    let mut resp = Response::new(Body::from(e.to_string()));
+                    *resp.status_mut() = error_status(&e);
+                    resp
+                }
+            };
+            resp.headers_mut()
+                .insert(REQUEST_ID_HEADER, HeaderValue::from_str(&request_id)?);
+            Ok(resp)
+        })
+        .await
+}
+
+async fn serve_request<B: Blockchain + Send + Sync + 'static>(
     _client: Option<SocketAddr>,
     context: Arc<RwLock<NodeContext<B>>>,
     req: Request<Body>,
@@ -73,32 +278,67 @@ async fn node_service<B: Blockchain>(
     let qs = req.uri().query().unwrap_or("").to_string();
 
     let creds = fetch_signature(&req)?;
+    let accepts_gzip = req
+        .headers()
+        .get(ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(compression::accepts_gzip)
+        .unwrap_or(false);
     let body = req.into_body();
+    let body_read_timeout = context.read().await.opts.http_body_read_timeout;
+    let canonical = context.read().await.opts.reject_non_canonical_encodings;
 
     // Disallow large requests
     if body
         .size_hint()
         .upper()
         .map(|u| u > 1024 * 1024)
-        .unwrap_or(true)
+        .unwrap_or(false)
     {
         *response.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
         return Ok(response);
     }
 
-    let body_bytes = hyper::body::to_bytes(body).await?;
+    // Requests are served one at a time, slow senders shouldn't block the node
+    let body_bytes =
+        match tokio::time::timeout(body_read_timeout, read_body(body, 1024 * 1024)).await {
+            Ok(Ok(Some(bytes))) => bytes,
+            Ok(Ok(None)) => {
+                *response.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+                return Ok(response);
+            }
+            Ok(Err(e)) => {
+                return Err(e);
+            }
+            Err(_) => {
+                *response.status_mut() = StatusCode::REQUEST_TIMEOUT;
+                return Ok(response);
+            }
+        };
 
-    let needs_signature = false;
+    // Admin requests should be signed by the node's own key
+    let needs_admin = matches!(
+        &path[..],
+        "/admin/trace-tx"
+            | "/admin/options"
+            | "/admin/rollback-ops"
+            | "/admin/balances.csv"
+            | "/admin/punishments"
+    );
+    let needs_signature = needs_admin;
 
     // TODO: This doesn't prevent replay attacks
-    let is_signed = creds
-        .map(|(pub_key, sig)| {
-            ed25519::Ed25519::<crate::core::Hasher>::verify(&pub_key, &body_bytes, &sig)
+    let signer = creds
+        .filter(|(pub_key, sig)| {
+            ed25519::Ed25519::<crate::core::Hasher>::verify(pub_key, &body_bytes, sig)
         })
-        .unwrap_or(false);
-    if needs_signature && !is_signed {
+        .map(|(pub_key, _)| pub_key);
+    if needs_signature && signer.is_none() {
         return Err(NodeError::SignatureRequired);
     }
+    if needs_admin && signer != Some(context.read().await.pub_key.clone()) {
+        return Err(NodeError::AdminRequired);
+    }
 
     match (method, &path[..]) {
         // Miner will call this to fetch new PoW work.
@@ -124,11 +364,82 @@ async fn node_service<B: Blockchain>(
                 &api::get_stats(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
             )?);
         }
+        (Method::GET, "/headers") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_json_headers(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
+            )?);
+        }
+        (Method::GET, "/blocks") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_json_blocks(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
+            )?);
+        }
         (Method::GET, "/account") => {
             *response.body_mut() = Body::from(serde_json::to_vec(
                 &api::get_account(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
             )?);
         }
+        (Method::GET, "/accounts") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_accounts(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
+            )?);
+        }
+        (Method::GET, "/account/pending") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_pending_balance(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
+            )?);
+        }
+        (Method::GET, "/contract/info") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_contract_info(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
+            )?);
+        }
+        (Method::GET, "/block-summaries") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_block_summaries(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
+            )?);
+        }
+        (Method::GET, "/block-reward") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_block_reward(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
+            )?);
+        }
+        (Method::GET, "/tx") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_transaction_status(Arc::clone(&context), serde_qs::from_str(&qs)?)
+                    .await?,
+            )?);
+        }
+        (Method::POST, "/tx/decode") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::decode_tx(Arc::clone(&context), serde_json::from_slice(&body_bytes)?).await?,
+            )?);
+        }
+        (Method::GET, "/contract-value") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_contract_value(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
+            )?);
+        }
+        (Method::GET, "/metrics") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_metrics(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
+            )?);
+        }
+        (Method::GET, "/sync-status") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_sync_status(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
+            )?);
+        }
+        (Method::GET, "/admin/punishments") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_punishments(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
+            )?);
+        }
+        (Method::GET, "/schema") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_schema(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
+            )?);
+        }
         (Method::GET, "/peers") => {
             *response.body_mut() = Body::from(serde_json::to_vec(
                 &api::get_peers(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
@@ -144,22 +455,59 @@ async fn node_service<B: Blockchain>(
                 &api::shutdown(Arc::clone(&context), serde_json::from_slice(&body_bytes)?).await?,
             )?);
         }
+        (Method::GET, "/admin/balances.csv") => {
+            *response.body_mut() = api::get_balances_csv(Arc::clone(&context)).await?;
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static("text/csv"));
+        }
+        (Method::GET, "/admin/rollback-ops") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_rollback_ops(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
+            )?);
+        }
+        (Method::GET, "/admin/options") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::get_admin_options(Arc::clone(&context), serde_qs::from_str(&qs)?).await?,
+            )?);
+        }
+        (Method::POST, "/admin/options") => {
+            *response.body_mut() = Body::from(serde_json::to_vec(
+                &api::post_admin_options(
+                    Arc::clone(&context),
+                    serde_json::from_slice(&body_bytes)?,
+                )
+                .await?,
+            )?);
+        }
+        (Method::POST, "/admin/trace-tx") => {
+            *response.body_mut() = Body::from(bincode::serialize(
+                &api::trace_tx(Arc::clone(&context), bincode::deserialize(&body_bytes)?).await?,
+            )?);
+        }
         (Method::POST, "/bincode/transact") => {
             *response.body_mut() = Body::from(bincode::serialize(
-                &api::transact(Arc::clone(&context), bincode::deserialize(&body_bytes)?).await?,
+                &api::transact(
+                    Arc::clone(&context),
+                    bincode_deserialize(&body_bytes, canonical)?,
+                )
+                .await?,
             )?);
         }
         (Method::POST, "/bincode/transact/zero") => {
             *response.body_mut() = Body::from(bincode::serialize(
-                &api::transact_zero(Arc::clone(&context), bincode::deserialize(&body_bytes)?)
-                    .await?,
+                &api::transact_zero(
+                    Arc::clone(&context),
+                    bincode_deserialize(&body_bytes, canonical)?,
+                )
+                .await?,
             )?);
         }
         (Method::POST, "/bincode/transact/dw") => {
             *response.body_mut() = Body::from(bincode::serialize(
                 &api::transact_deposit_withdraw(
                     Arc::clone(&context),
-                    bincode::deserialize(&body_bytes)?,
+                    bincode_deserialize(&body_bytes, canonical)?,
                 )
                 .await?,
             )?);
@@ -169,11 +517,16 @@ async fn node_service<B: Blockchain>(
                 &api::get_headers(Arc::clone(&context), bincode::deserialize(&body_bytes)?).await?,
             )?);
         }
-        (Method::GET, "/bincode/blocks") => {
+        (Method::GET, "/bincode/header-stream") => {
             *response.body_mut() = Body::from(bincode::serialize(
-                &api::get_blocks(Arc::clone(&context), bincode::deserialize(&body_bytes)?).await?,
+                &api::get_header_stream(Arc::clone(&context), bincode::deserialize(&body_bytes)?)
+                    .await?,
             )?);
         }
+        (Method::GET, "/bincode/blocks") => {
+            *response.body_mut() =
+                api::get_blocks(Arc::clone(&context), bincode::deserialize(&body_bytes)?).await?;
+        }
         (Method::POST, "/bincode/blocks") => {
             *response.body_mut() = Body::from(bincode::serialize(
                 &api::post_block(Arc::clone(&context), bincode::deserialize(&body_bytes)?).await?,
@@ -184,6 +537,21 @@ async fn node_service<B: Blockchain>(
                 &api::get_states(Arc::clone(&context), bincode::deserialize(&body_bytes)?).await?,
             )?);
         }
+        (Method::GET, "/bincode/states/chunks") => {
+            *response.body_mut() = Body::from(bincode::serialize(
+                &api::get_state_chunks(Arc::clone(&context), bincode::deserialize(&body_bytes)?)
+                    .await?,
+            )?);
+        }
+        (Method::GET, "/bincode/outdated-contracts") => {
+            *response.body_mut() = Body::from(bincode::serialize(
+                &api::get_outdated_contracts(
+                    Arc::clone(&context),
+                    bincode::deserialize(&body_bytes)?,
+                )
+                .await?,
+            )?);
+        }
         (Method::GET, "/bincode/states/outdated") => {
             *response.body_mut() = Body::from(bincode::serialize(
                 &api::get_outdated_heights(
@@ -204,12 +572,20 @@ async fn node_service<B: Blockchain>(
         }
     };
 
+    // Only the binary endpoints are worth compressing
+    if accepts_gzip && path.starts_with("/bincode/") && response.status() == StatusCode::OK {
+        *response.body_mut() = compression::gzip_compress_body(std::mem::take(response.body_mut()));
+        response.headers_mut().insert(
+            CONTENT_ENCODING,
+            HeaderValue::from_static(compression::GZIP),
+        );
+    }
+
     Ok(response)
 }
 
-use tokio::sync::mpsc;
-
-pub async fn node_create<B: Blockchain>(
+#[allow(clippy::too_many_arguments)]
+pub async fn node_create<B: Blockchain + Send + Sync + 'static>(
     opts: NodeOptions,
     address: PeerAddress,
     priv_key: ed25519::PrivateKey,
@@ -217,9 +593,18 @@ pub async fn node_create<B: Blockchain>(
     blockchain: B,
     timestamp_offset: i32,
     wallet: Option<Wallet>,
+    connections: Arc<ConnectionLimiter>,
     mut incoming: mpsc::UnboundedReceiver<NodeRequest>,
     outgoing: mpsc::UnboundedSender<NodeRequest>,
 ) -> Result<(), NodeError> {
+    if opts.run_self_test {
+        let pow_key = blockchain.pow_key(blockchain.get_height()?)?;
+        if let Err(e) = self_test::self_test(&pow_key) {
+            log::error!("Self-test failed: {}", e);
+            return Err(NodeError::SelfTestFailed(e.to_string()));
+        }
+    }
+
     let context = Arc::new(RwLock::new(NodeContext {
         opts,
         address,
@@ -249,12 +634,28 @@ pub async fn node_create<B: Blockchain>(
             })
             .collect(),
         timestamp_offset,
+        started_at: utils::local_timestamp(),
+        mempool_persisted_at: 0,
         banned_headers: HashMap::new(),
         outdated_since: None,
+        state_failures: HashMap::new(),
+        state_unavailable_peers: HashMap::new(),
+        sync_breakers: HashMap::new(),
+        peer_info_updated: HashMap::new(),
+        block_propagation: HashMap::new(),
+        request_latency: HashMap::new(),
+        state_chunks: HashMap::new(),
+        punishments: VecDeque::new(),
+        sync_status: SyncStatus::default(),
+        connections,
 
         miner_puzzle: None,
     }));
 
+    if let Some(path) = context.read().await.opts.mempool_path.clone() {
+        context.write().await.restore_mempools(&path);
+    }
+
     let server_future = async {
         loop {
             if context.read().await.shutdown {
@@ -276,8 +677,13 @@ pub async fn node_create<B: Blockchain>(
     };
 
     let heartbeat_future = heartbeat::heartbeater(Arc::clone(&context));
+    let miner_future = solo_miner::solo_miner(Arc::clone(&context));
 
-    try_join!(server_future, heartbeat_future)?;
+    try_join!(server_future, heartbeat_future, miner_future)?;
+
+    if let Some(path) = context.read().await.opts.mempool_path.clone() {
+        context.read().await.save_mempools(&path)?;
+    }
 
     log::info!("Node stopped!");
 
@@ -0,0 +1,125 @@
+use crate::config;
+use crate::consensus::pow;
+use crate::core::{Block, Hasher, Header, ProofOfWork, Signer, Transaction, HEADER_VERSION};
+use crate::crypto::SignatureScheme;
+use crate::db::{KvStore, KvStoreError, LevelDbKvStore, RamKvStore, WriteOp};
+use crate::wallet::Wallet;
+use rust_randomx::Difficulty;
+use tempdir::TempDir;
+use thiserror::Error;
+
+// Target easy enough to be solved in a few attempts
+const SELF_TEST_TARGET: u32 = 0x00ffffff;
+const MAX_POW_ATTEMPTS: u64 = 1000;
+
+#[derive(Error, Debug)]
+pub enum SelfTestError {
+    #[error("proof-of-work hashing is broken")]
+    Pow,
+    #[error("signatures cannot be verified")]
+    Signature,
+    #[error("core types do not survive a bincode round-trip")]
+    Serialization,
+    #[error("storage does not read back what is written")]
+    Storage,
+    #[error("kvstore error happened: {0}")]
+    KvStoreError(#[from] KvStoreError),
+    #[error("bincode error happened: {0}")]
+    BincodeError(#[from] bincode::Error),
+    #[error("io error happened: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+// Quick checks of the functionality the node depends on, so that a broken build
+// or environment is detected before the node starts serving.
+pub fn self_test(pow_key: &[u8]) -> Result<(), SelfTestError> {
+    check_pow(pow_key)?;
+    check_signature()?;
+    check_serialization()?;
+    check_storage()?;
+    Ok(())
+}
+
+fn check_pow(pow_key: &[u8]) -> Result<(), SelfTestError> {
+    let mut header = Header {
+        version: HEADER_VERSION,
+        parent_hash: Default::default(),
+        number: 0,
+        block_root: Default::default(),
+        proof_of_work: ProofOfWork {
+            timestamp: 0,
+            target: SELF_TEST_TARGET,
+            nonce: 0,
+        },
+    };
+    while !header.meets_target(pow_key) {
+        if header.proof_of_work.nonce >= MAX_POW_ATTEMPTS {
+            return Err(SelfTestError::Pow);
+        }
+        header.proof_of_work.nonce += 1;
+    }
+
+    // A standalone hasher (As used by the miner) should agree on the solution
+    let bin = bincode::serialize(&header)?;
+    if !pow::new_hasher(pow_key)
+        .hash(&bin)
+        .meets_difficulty(Difficulty::new(SELF_TEST_TARGET))
+    {
+        return Err(SelfTestError::Pow);
+    }
+    Ok(())
+}
+
+fn check_signature() -> Result<(), SelfTestError> {
+    let (pk, sk) = Signer::generate_keys(b"self-test");
+    let msg = b"bazuka self-test";
+    let sig = Signer::sign(&sk, msg);
+    if !Signer::verify(&pk, msg, &sig) || Signer::verify(&pk, b"tampered", &sig) {
+        return Err(SelfTestError::Signature);
+    }
+    Ok(())
+}
+
+fn check_serialization() -> Result<(), SelfTestError> {
+    let wallet = Wallet::new(Vec::from("SELF-TEST"));
+    let tx = wallet.create_transaction(wallet.get_address(), 1, 1, 1).tx;
+    let decoded: Transaction = bincode::deserialize(&bincode::serialize(&tx)?)?;
+    if decoded != tx || !decoded.verify_signature() {
+        return Err(SelfTestError::Serialization);
+    }
+
+    let block = config::blockchain::get_blockchain_config().genesis.block;
+    let decoded: Block = bincode::deserialize(&bincode::serialize(&block)?)?;
+    if decoded.header != block.header
+        || decoded.header.hash() != block.header.hash()
+        || decoded.body != block.body
+    {
+        return Err(SelfTestError::Serialization);
+    }
+    Ok(())
+}
+
+fn check_storage() -> Result<(), SelfTestError> {
+    let dir = TempDir::new("bazuka_self_test")?;
+    let mut disk = LevelDbKvStore::new(dir.path(), 64)?;
+    let mut ram = RamKvStore::new();
+    let ops = [
+        WriteOp::Put("self_test_a".into(), 123u64.into()),
+        WriteOp::Put("self_test_b".into(), 234u64.into()),
+        WriteOp::Remove("self_test_b".into()),
+    ];
+    disk.update(&ops)?;
+    ram.update(&ops)?;
+
+    let value: Option<u64> = disk
+        .get("self_test_a".into())?
+        .map(|b| b.try_into())
+        .transpose()?;
+    if value != Some(123)
+        || disk.get("self_test_b".into())?.is_some()
+        || disk.checksum::<Hasher>()? != ram.checksum::<Hasher>()?
+    {
+        return Err(SelfTestError::Storage);
+    }
+    Ok(())
+}
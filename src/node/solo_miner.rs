@@ -0,0 +1,125 @@
+use super::api;
+use super::{NodeContext, NodeError};
+use crate::blockchain::Blockchain;
+use crate::client::messages::{GetMinerPuzzleRequest, PostMinerSolutionRequest, Puzzle};
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::sleep;
+
+// Searches for a nonce solving the puzzle on all cores, until found, aborted or
+// the nonce space of the puzzle is exhausted
+pub fn mine_puzzle(puzzle: &Puzzle, abort: &AtomicBool) -> Option<PostMinerSolutionRequest> {
+    if puzzle.size > 8 {
+        return None;
+    }
+    let max_nonce = if puzzle.size == 8 {
+        u64::MAX
+    } else {
+        (1u64 << (8 * puzzle.size)) - 1
+    };
+    let key = hex::decode(&puzzle.key).ok()?;
+    let blob = hex::decode(&puzzle.blob).ok()?;
+    let difficulty = rust_randomx::Difficulty::new(puzzle.target);
+    let num_threads = rayon::current_num_threads() as u64;
+    (0..num_threads)
+        .into_par_iter()
+        .find_map_any(|thread| {
+            let hasher = crate::consensus::pow::new_hasher(&key);
+            let mut blob = blob.clone();
+            let mut nonce = thread;
+            while nonce <= max_nonce && !abort.load(Ordering::Relaxed) {
+                blob[puzzle.offset..puzzle.offset + puzzle.size]
+                    .copy_from_slice(&nonce.to_le_bytes()[..puzzle.size]);
+                if hasher.hash(&blob).meets_difficulty(difficulty) {
+                    abort.store(true, Ordering::Relaxed);
+                    return Some(nonce);
+                }
+                nonce = match nonce.checked_add(num_threads) {
+                    Some(next) => next,
+                    None => break,
+                };
+            }
+            None
+        })
+        .map(|nonce| PostMinerSolutionRequest {
+            nonce: hex::encode(nonce.to_le_bytes()),
+        })
+}
+
+async fn mine_once<B: Blockchain>(context: &Arc<RwLock<NodeContext<B>>>) -> Result<(), NodeError> {
+    let heartbeat_interval = context.read().await.opts.heartbeat_interval;
+    let puzzle = match api::get_miner_puzzle(Arc::clone(context), GetMinerPuzzleRequest {})
+        .await?
+        .puzzle
+    {
+        Some(puzzle) => puzzle,
+        None => {
+            sleep(heartbeat_interval).await;
+            return Ok(());
+        }
+    };
+    let tip = context.read().await.blockchain.get_tip()?.hash();
+
+    let abort = Arc::new(AtomicBool::new(false));
+    let mut miner = tokio::task::spawn_blocking({
+        let abort = Arc::clone(&abort);
+        move || mine_puzzle(&puzzle, &abort)
+    });
+
+    // Give up once the chain is extended by someone else, the puzzle is stale then
+    let solution = loop {
+        tokio::select! {
+            solution = &mut miner => {
+                break solution.map_err(|_| NodeError::MinerError)?;
+            }
+            _ = sleep(heartbeat_interval) => {
+                let ctx = context.read().await;
+                if ctx.shutdown || ctx.blockchain.get_tip()?.hash() != tip {
+                    abort.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    };
+
+    match solution {
+        Some(solution) => {
+            let resp = api::post_miner_solution(Arc::clone(context), solution).await?;
+            if !resp.accepted {
+                log::info!("Mined block rejected: {:?}", resp.reason);
+            }
+        }
+        None if !abort.load(Ordering::Relaxed) => {
+            // The nonce space is exhausted, a puzzle drafted at a later timestamp
+            // has a different header to search through
+            log::info!("Nonce space exhausted, drafting a new puzzle...");
+            context.write().await.miner_puzzle = None;
+            sleep(Duration::from_secs(1)).await;
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+pub async fn solo_miner<B: Blockchain>(
+    context: Arc<RwLock<NodeContext<B>>>,
+) -> Result<(), NodeError> {
+    {
+        let ctx = context.read().await;
+        if !ctx.opts.solo_mine || ctx.wallet.is_none() {
+            return Ok(());
+        }
+    }
+    loop {
+        if context.read().await.shutdown {
+            break;
+        }
+        if let Err(e) = mine_once(&context).await {
+            log::error!("Error happened while mining: {}", e);
+            sleep(context.read().await.opts.heartbeat_interval).await;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use super::*;
+
+use crate::blockchain::KvStoreChain;
+use crate::client::{BazukaClient, OutgoingSender};
+use crate::config;
+use crate::db::RamKvStore;
+
+pub const BASE_PORT: u16 = 3030;
+
+// Spins up `n` in-memory nodes (Each with its own wallet) listening on consecutive
+// ports starting from BASE_PORT. Node i bootstraps from node i-1 and all of the
+// outgoing requests are routed between the nodes through the given rules.
+#[allow(clippy::type_complexity)]
+pub fn spawn_network(
+    rules: Arc<RwLock<Vec<Rule>>>,
+    n: usize,
+) -> (
+    impl futures::Future<Output = Result<Vec<()>, NodeError>>,
+    impl futures::Future<Output = Result<Vec<()>, NodeError>>,
+    Vec<BazukaClient>,
+) {
+    let conf = blockchain::get_test_blockchain_config();
+    test_network(
+        rules,
+        (0..n)
+            .map(|i| {
+                let port = BASE_PORT + i as u16;
+                NodeOpts {
+                    config: conf.clone(),
+                    priv_key: Signer::generate_keys(port.to_string().as_bytes()).1,
+                    wallet: Some(Wallet::new(format!("NODE{}", i).into_bytes())),
+                    addr: port,
+                    bootstrap: if i > 0 { vec![port - 1] } else { vec![] },
+                    timestamp_offset: 5 * (i as i32 + 1),
+                }
+            })
+            .collect(),
+    )
+}
+
+// A standalone node context (Not connected to any network) for testing the
+// node logic directly.
+pub fn test_context() -> NodeContext<KvStoreChain<RamKvStore>> {
+    let priv_key = Signer::generate_keys(b"3030").1;
+    let (out_send, _) = mpsc::unbounded_channel::<NodeRequest>();
+    let opts = config::node::get_test_node_options();
+    NodeContext {
+        connections: Arc::new(ConnectionLimiter::new(opts.max_connections)),
+        opts,
+        address: PeerAddress(SocketAddr::from(([127, 0, 0, 1], BASE_PORT))),
+        pub_key: ed25519::PublicKey::from(priv_key.clone()),
+        shutdown: false,
+        outgoing: Arc::new(OutgoingSender {
+            chan: out_send,
+            priv_key,
+        }),
+        blockchain: KvStoreChain::new(RamKvStore::new(), blockchain::get_test_blockchain_config())
+            .unwrap(),
+        wallet: None,
+        mempool: HashMap::new(),
+        zero_mempool: HashMap::new(),
+        dw_mempool: HashMap::new(),
+        peers: HashMap::new(),
+        timestamp_offset: 0,
+        started_at: 0,
+        mempool_persisted_at: 0,
+        banned_headers: HashMap::new(),
+        outdated_since: None,
+        state_failures: HashMap::new(),
+        state_unavailable_peers: HashMap::new(),
+        sync_breakers: HashMap::new(),
+        peer_info_updated: HashMap::new(),
+        block_propagation: HashMap::new(),
+        request_latency: HashMap::new(),
+        state_chunks: HashMap::new(),
+        punishments: VecDeque::new(),
+        sync_status: SyncStatus::default(),
+        miner_puzzle: None,
+    }
+}
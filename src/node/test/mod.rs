@@ -3,19 +3,57 @@ use super::*;
 mod simulation;
 use simulation::*;
 
-use crate::blockchain::BlockchainError;
+mod harness;
+
+use crate::blockchain::{BlockchainError, KvStoreChain, TransactionStats};
+use crate::client::messages::{
+    DecodeTxRequest, GetAccountRequest, GetAccountsRequest, GetBlockRewardRequest,
+    GetBlockSummariesRequest, GetBlocksRequest, GetBlocksResponse, GetHeaderStreamRequest,
+    GetHeadersRequest, GetMetricsRequest, GetMinerPuzzleRequest, GetOutdatedContractsRequest,
+    GetPunishmentsRequest, GetStateChunksRequest, GetStateChunksResponse, GetStatesRequest,
+    GetTransactionStatusRequest, GetTransactionStatusResponse, PostAdminOptionsRequest,
+    PostBlockRequest, PostMinerSolutionRequest, PostPeerRequest, TransactRequest,
+};
 use crate::config::blockchain;
-use crate::core::{ContractId, Signer, TransactionAndDelta, ZkHasher};
+use crate::core::{Address, ContractId, Money, Signer, TransactionAndDelta, ZkHasher};
 use crate::crypto::SignatureScheme;
+use crate::db::RamKvStore;
 use crate::zk;
-use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::RwLock;
+use tempdir::TempDir;
+use tokio::sync::{mpsc, RwLock};
 use tokio::time::sleep;
 
+// Everything logged by the node during the tests
+static CAPTURED_LOGS: std::sync::Mutex<Vec<u8>> = std::sync::Mutex::new(Vec::new());
+
+struct LogCapture;
+impl std::io::Write for LogCapture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        CAPTURED_LOGS.lock().unwrap().extend_from_slice(buf);
+        eprint!("{}", String::from_utf8_lossy(buf));
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn captured_log_lines() -> Vec<String> {
+    String::from_utf8_lossy(&CAPTURED_LOGS.lock().unwrap())
+        .lines()
+        .map(|l| l.to_string())
+        .collect()
+}
+
 fn init() {
-    let _ = env_logger::builder().is_test(true).try_init();
+    let _ = logger_builder()
+        .is_test(true)
+        .filter_module("bazuka::node", log::LevelFilter::Debug)
+        .target(env_logger::Target::Pipe(Box::new(LogCapture)))
+        .try_init();
 }
 
 const MAX_WAIT_FOR_CHANGE: usize = 20;
@@ -233,12 +271,10 @@ async fn test_blocks_get_synced() -> Result<(), NodeError> {
     Ok(())
 }
 
-fn sample_contract_call() -> TransactionAndDelta {
+fn sample_contract_call(nonce: u32) -> TransactionAndDelta {
     let updater = Wallet::new(Vec::from("ABC"));
 
-    let cid =
-        ContractId::from_str("764c9a719a203d34dae8d8538bf4667c7fcb84030fb2e476e4aeb6060c4419e0")
-            .unwrap();
+    let cid = blockchain::get_test_mpn_contract_id();
     let state_model = zk::ZkStateModel::List {
         item_type: Box::new(zk::ZkStateModel::Scalar),
         log4_size: 5,
@@ -264,7 +300,7 @@ fn sample_contract_call() -> TransactionAndDelta {
         state_model.compress::<ZkHasher>(&full_state.data).unwrap(),
         zk::ZkProof::Dummy(true),
         0,
-        1,
+        nonce,
     )
 }
 
@@ -297,7 +333,7 @@ async fn test_states_get_synced() -> Result<(), NodeError> {
         ],
     );
     let test_logic = async {
-        let tx_delta = sample_contract_call();
+        let tx_delta = sample_contract_call(1);
 
         chans[0].transact(tx_delta).await?;
 
@@ -343,6 +379,343 @@ async fn test_states_get_synced() -> Result<(), NodeError> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_states_are_fetched_from_fastest_peer() -> Result<(), NodeError> {
+    init();
+
+    let isolate_3032 = vec![
+        Rule {
+            from: Endpoint::Peer(3032),
+            to: Endpoint::Any,
+            url: "".into(),
+            action: Action::Drop,
+        },
+        Rule {
+            from: Endpoint::Any,
+            to: Endpoint::Peer(3032),
+            url: "".into(),
+            action: Action::Drop,
+        },
+    ];
+    let rules = Arc::new(RwLock::new(isolate_3032));
+    let conf = blockchain::get_test_blockchain_config();
+
+    let (node_futs, route_futs, chans) = simulation::test_network(
+        Arc::clone(&rules),
+        vec![
+            NodeOpts {
+                config: conf.clone(),
+                priv_key: Signer::generate_keys(b"3030").1,
+                wallet: Some(Wallet::new(Vec::from("ABC"))),
+                addr: 3030,
+                bootstrap: vec![],
+                timestamp_offset: 5,
+            },
+            NodeOpts {
+                config: conf.clone(),
+                priv_key: Signer::generate_keys(b"3031").1,
+                wallet: Some(Wallet::new(Vec::from("CBA"))),
+                addr: 3031,
+                bootstrap: vec![3030],
+                timestamp_offset: 10,
+            },
+            NodeOpts {
+                config: conf.clone(),
+                priv_key: Signer::generate_keys(b"3032").1,
+                wallet: Some(Wallet::new(Vec::from("DEF"))),
+                addr: 3032,
+                bootstrap: vec![3030, 3031],
+                timestamp_offset: 15,
+            },
+        ],
+    );
+    let test_logic = async {
+        chans[0].transact(sample_contract_call(1)).await?;
+        chans[0].mine().await?;
+
+        // Both 3030 and 3031 end up having the states
+        assert_eq!(
+            catch_change(|| async { Ok(chans[1].stats().await?.height) }).await?,
+            2
+        );
+        if !chans[1]
+            .outdated_heights()
+            .await?
+            .outdated_heights
+            .is_empty()
+        {
+            assert_eq!(
+                catch_change(|| async {
+                    Ok(chans[1].outdated_heights().await?.outdated_heights.len())
+                })
+                .await?,
+                0
+            );
+        }
+
+        // 3032 gets the block but not the states...
+        *rules.write().await = vec![Rule {
+            from: Endpoint::Peer(3032),
+            to: Endpoint::Any,
+            url: "state".into(),
+            action: Action::Drop,
+        }];
+        assert_eq!(
+            catch_change(|| async { Ok(chans[2].stats().await?.height) }).await?,
+            2
+        );
+        assert_eq!(chans[2].outdated_heights().await?.outdated_heights.len(), 1);
+
+        // ...until states are served, 3030 being much slower than 3031.
+        *rules.write().await = vec![Rule {
+            from: Endpoint::Peer(3032),
+            to: Endpoint::Peer(3030),
+            url: "state".into(),
+            action: Action::Delay(Duration::from_secs(60)),
+        }];
+        let start = std::time::Instant::now();
+        assert_eq!(
+            catch_change(|| async {
+                Ok(chans[2].outdated_heights().await?.outdated_heights.len())
+            })
+            .await?,
+            0
+        );
+        assert!(start.elapsed() < Duration::from_secs(60));
+
+        for chan in chans.iter() {
+            chan.shutdown().await?;
+        }
+
+        Ok::<(), NodeError>(())
+    };
+    tokio::try_join!(node_futs, route_futs, test_logic)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_states_of_contracts_are_fetched_concurrently() -> Result<(), NodeError> {
+    init();
+
+    let isolate_3032 = vec![
+        Rule {
+            from: Endpoint::Peer(3032),
+            to: Endpoint::Any,
+            url: "".into(),
+            action: Action::Drop,
+        },
+        Rule {
+            from: Endpoint::Any,
+            to: Endpoint::Peer(3032),
+            url: "".into(),
+            action: Action::Drop,
+        },
+    ];
+    let rules = Arc::new(RwLock::new(isolate_3032));
+    let conf = blockchain::get_test_blockchain_config();
+
+    let (node_futs, route_futs, chans) = simulation::test_network(
+        Arc::clone(&rules),
+        vec![
+            NodeOpts {
+                config: conf.clone(),
+                priv_key: Signer::generate_keys(b"3030").1,
+                wallet: Some(Wallet::new(Vec::from("ABC"))),
+                addr: 3030,
+                bootstrap: vec![],
+                timestamp_offset: 5,
+            },
+            NodeOpts {
+                config: conf.clone(),
+                priv_key: Signer::generate_keys(b"3031").1,
+                wallet: Some(Wallet::new(Vec::from("CBA"))),
+                addr: 3031,
+                bootstrap: vec![3030],
+                timestamp_offset: 10,
+            },
+            NodeOpts {
+                config: conf.clone(),
+                priv_key: Signer::generate_keys(b"3032").1,
+                wallet: Some(Wallet::new(Vec::from("DEF"))),
+                addr: 3032,
+                bootstrap: vec![3030, 3031],
+                timestamp_offset: 15,
+            },
+        ],
+    );
+    let test_logic = async {
+        let state_model = zk::ZkStateModel::List {
+            item_type: Box::new(zk::ZkStateModel::Scalar),
+            log4_size: 1,
+        };
+        let mut cids = Vec::new();
+        for i in 0..3 {
+            // One creator per contract, the mempool only accepts the next nonce
+            let creator = Wallet::new(format!("CREATOR{}", i).into_bytes());
+            let initial_data = zk::ZkDataPairs(
+                [(zk::ZkDataLocator(vec![0]), zk::ZkScalar::from(i as u64 + 1))]
+                    .into_iter()
+                    .collect(),
+            );
+            let tx_delta = creator.create_contract(
+                zk::ZkContract {
+                    state_model: state_model.clone(),
+                    initial_state: state_model.compress::<ZkHasher>(&initial_data).unwrap(),
+                    log4_deposit_withdraw_capacity: 0,
+                    deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+                    functions: vec![zk::ZkVerifierKey::Dummy],
+                },
+                initial_data,
+                0,
+                1,
+            );
+            cids.push(ContractId::new(&tx_delta.tx));
+            chans[0].transact(tx_delta).await?;
+        }
+        chans[0].mine().await?;
+
+        // 3031 ends up having the states
+        assert_eq!(
+            catch_change(|| async { Ok(chans[1].stats().await?.height) }).await?,
+            2
+        );
+        if !chans[1]
+            .outdated_heights()
+            .await?
+            .outdated_heights
+            .is_empty()
+        {
+            assert_eq!(
+                catch_change(|| async {
+                    Ok(chans[1].outdated_heights().await?.outdated_heights.len())
+                })
+                .await?,
+                0
+            );
+        }
+
+        // 3032 gets the block but not the states...
+        *rules.write().await = vec![Rule {
+            from: Endpoint::Peer(3032),
+            to: Endpoint::Any,
+            url: "state".into(),
+            action: Action::Drop,
+        }];
+        assert_eq!(
+            catch_change(|| async { Ok(chans[2].stats().await?.height) }).await?,
+            2
+        );
+        assert_eq!(chans[2].outdated_heights().await?.outdated_heights.len(), 3);
+
+        // ...until states are served, the contracts being spread over both peers
+        let to_3030 = Arc::new(AtomicUsize::new(0));
+        let to_3031 = Arc::new(AtomicUsize::new(0));
+        *rules.write().await = vec![
+            Rule {
+                from: Endpoint::Peer(3032),
+                to: Endpoint::Peer(3030),
+                url: "state".into(),
+                action: Action::Count(Arc::clone(&to_3030)),
+            },
+            Rule {
+                from: Endpoint::Peer(3032),
+                to: Endpoint::Peer(3031),
+                url: "state".into(),
+                action: Action::Count(Arc::clone(&to_3031)),
+            },
+        ];
+        for _ in 0..MAX_WAIT_FOR_CHANGE {
+            if chans[2]
+                .outdated_heights()
+                .await?
+                .outdated_heights
+                .is_empty()
+            {
+                break;
+            }
+            sleep(Duration::from_secs(1)).await;
+        }
+        assert_eq!(chans[2].outdated_heights().await?.outdated_heights.len(), 0);
+        assert!(to_3030.load(Ordering::SeqCst) > 0);
+        assert!(to_3031.load(Ordering::SeqCst) > 0);
+
+        for (i, cid) in cids.into_iter().enumerate() {
+            assert_eq!(
+                chans[2]
+                    .contract_value(cid, zk::ZkDataLocator(vec![0]))
+                    .await?
+                    .value,
+                (i + 1).to_string()
+            );
+        }
+
+        for chan in chans.iter() {
+            chan.shutdown().await?;
+        }
+
+        Ok::<(), NodeError>(())
+    };
+    tokio::try_join!(node_futs, route_futs, test_logic)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_options_can_be_changed_at_runtime() -> Result<(), NodeError> {
+    init();
+
+    let peers_requests = Arc::new(AtomicUsize::new(0));
+    let rules = Arc::new(RwLock::new(vec![Rule {
+        from: Endpoint::Peer(3031),
+        to: Endpoint::Peer(3030),
+        url: "/peers".into(),
+        action: Action::Count(Arc::clone(&peers_requests)),
+    }]));
+    let (node_futs, route_futs, chans) = harness::spawn_network(Arc::clone(&rules), 2);
+
+    let test_logic = async {
+        assert_eq!(chans[1].admin_options().await?.options.num_peers, 8);
+        sleep(Duration::from_secs(1)).await;
+        assert!(peers_requests.load(Ordering::SeqCst) > 0);
+
+        // Invalid values and options which cannot be changed are rejected
+        assert!(matches!(
+            chans[1]
+                .set_admin_options(PostAdminOptionsRequest {
+                    no_response_punish: Some(1),
+                    ..Default::default()
+                })
+                .await,
+            Err(NodeError::RequestFailed(StatusCode::BAD_REQUEST))
+        ));
+        assert!(
+            serde_json::from_str::<PostAdminOptionsRequest>(r#"{"heartbeat_interval":1}"#).is_err()
+        );
+
+        let resp = chans[1]
+            .set_admin_options(PostAdminOptionsRequest {
+                num_peers: Some(0),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(resp.options.num_peers, 0);
+        assert_eq!(chans[1].admin_options().await?.options, resp.options);
+
+        // The next heartbeats don't ask any peers
+        sleep(Duration::from_secs(1)).await;
+        let before = peers_requests.load(Ordering::SeqCst);
+        sleep(Duration::from_secs(2)).await;
+        assert_eq!(peers_requests.load(Ordering::SeqCst), before);
+
+        for chan in chans.iter() {
+            chan.shutdown().await?;
+        }
+
+        Ok::<(), NodeError>(())
+    };
+    tokio::try_join!(node_futs, route_futs, test_logic)?;
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_chain_rolls_back() -> Result<(), NodeError> {
     init();
@@ -372,7 +745,7 @@ async fn test_chain_rolls_back() -> Result<(), NodeError> {
         ],
     );
     let test_logic = async {
-        let tx_delta = sample_contract_call();
+        let tx_delta = sample_contract_call(1);
 
         chans[0].transact(tx_delta).await?;
 
@@ -387,11 +760,6 @@ async fn test_chain_rolls_back() -> Result<(), NodeError> {
         assert_eq!(chans[0].outdated_heights().await?.outdated_heights.len(), 0);
         assert_eq!(chans[1].outdated_heights().await?.outdated_heights.len(), 1);
 
-        assert!(matches!(
-            chans[1].mine().await,
-            Err(NodeError::BlockchainError(BlockchainError::StatesOutdated))
-        ));
-
         assert_eq!(
             catch_change(|| async { Ok(chans[1].stats().await?.height) }).await?,
             1
@@ -433,3 +801,2349 @@ async fn test_chain_rolls_back() -> Result<(), NodeError> {
     tokio::try_join!(node_futs, route_futs, test_logic)?;
     Ok(())
 }
+
+#[tokio::test]
+async fn test_node_syncs_chain_mined_by_another() -> Result<(), NodeError> {
+    init();
+
+    let rules = Arc::new(RwLock::new(vec![Rule::drop_all()]));
+    let (node_futs, route_futs, chans) = harness::spawn_network(Arc::clone(&rules), 2);
+
+    let test_logic = async {
+        for _ in 0..3 {
+            chans[0].mine().await?;
+        }
+        assert_eq!(chans[0].stats().await?.height, 4);
+        assert_eq!(chans[1].stats().await?.height, 1);
+
+        // Let the second node find the first one and download its chain
+        rules.write().await.clear();
+        assert_eq!(
+            catch_change(|| async { Ok(chans[1].stats().await?.height) }).await?,
+            4
+        );
+        assert_eq!(chans[0].stats().await?.height, 4);
+
+        for chan in chans.iter() {
+            chan.shutdown().await?;
+        }
+
+        Ok::<(), NodeError>(())
+    };
+    tokio::try_join!(node_futs, route_futs, test_logic)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_balances_csv_lists_accounts() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+
+    let mempool = [
+        alice.create_transaction(bob.get_address(), 100, 0, 1),
+        alice.create_transaction(bob.get_address(), 200, 0, 2),
+    ]
+    .into_iter()
+    .map(|tx| (tx, TransactionStats { first_seen: 0 }))
+    .collect::<HashMap<_, _>>();
+    let draft = ctx
+        .blockchain
+        .draft_block(1, &mempool, &miner, true)?
+        .unwrap();
+    ctx.blockchain.apply_block(&draft.block, false)?;
+    ctx.blockchain.update_states(&draft.patch)?;
+    let treasury = ctx.blockchain.get_account(Address::Treasury)?;
+    let ctx = Arc::new(RwLock::new(ctx));
+
+    let body = api::get_balances_csv(Arc::clone(&ctx)).await?;
+    let csv = String::from_utf8(hyper::body::to_bytes(body).await?.to_vec()).unwrap();
+    let lines = csv.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "address,balance,nonce");
+    for line in [
+        format!("{},{},{}", alice.get_address(), 10000 - 300, 2),
+        format!("{},{},{}", bob.get_address(), 300, 0),
+        format!("Treasury,{},{}", treasury.balance, treasury.nonce),
+    ] {
+        assert!(lines.contains(&line.as_str()));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bincode_blocks_are_streamed() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let miner = Wallet::new(Vec::from("MINER"));
+    for ts in 1..4 {
+        let draft = ctx
+            .blockchain
+            .draft_block(ts, &HashMap::new(), &miner, true)?
+            .unwrap();
+        ctx.blockchain.apply_block(&draft.block, false)?;
+    }
+    let height = ctx.blockchain.get_height()?;
+    let ctx = Arc::new(RwLock::new(ctx));
+
+    // Encoded exactly like a `GetBlocksResponse`
+    for since in [0, 2, height, height + 5] {
+        let body =
+            api::get_blocks(Arc::clone(&ctx), GetBlocksRequest { since, until: None }).await?;
+        let bytes = hyper::body::to_bytes(body).await?;
+        let resp: GetBlocksResponse = bincode::deserialize(&bytes)?;
+        assert_eq!(
+            resp.blocks,
+            ctx.read().await.blockchain.get_blocks(since, None)?
+        );
+
+        // Compressing keeps the body streamed and decodes back to the same bytes
+        let body =
+            api::get_blocks(Arc::clone(&ctx), GetBlocksRequest { since, until: None }).await?;
+        let gzipped = hyper::body::to_bytes(compression::gzip_compress_body(body)).await?;
+        assert_eq!(compression::gzip_decompress(&gzipped, None)?, bytes);
+    }
+
+    // A rollback while the blocks are being sent ends the body with an error
+    // rather than a shorter, valid looking response
+    let body = api::get_blocks(
+        Arc::clone(&ctx),
+        GetBlocksRequest {
+            since: 0,
+            until: None,
+        },
+    )
+    .await?;
+    ctx.write().await.blockchain.rollback()?;
+    assert!(hyper::body::to_bytes(body).await.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_non_canonical_encodings_are_rejected() -> Result<(), NodeError> {
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let req = TransactRequest {
+        tx_delta: alice.create_transaction(bob.get_address(), 100, 1, 1),
+    };
+    let bytes = bincode::serialize(&req)?;
+    let decoded: TransactRequest = bincode_deserialize(&bytes, true)?;
+    assert_eq!(decoded.tx_delta, req.tx_delta);
+
+    // Trailing bytes decode to the same transaction
+    let mut padded = bytes.clone();
+    padded.push(0);
+    let decoded: TransactRequest = bincode_deserialize(&padded, false)?;
+    assert_eq!(decoded.tx_delta, req.tx_delta);
+    assert!(matches!(
+        bincode_deserialize::<TransactRequest>(&padded, true),
+        Err(NodeError::NonCanonicalEncoding)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_block_reward_includes_fees() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let mut conf = blockchain::get_test_blockchain_config();
+    conf.fee_split.miner_bps = 5000;
+    conf.fee_split.burn_bps = 5000;
+    ctx.blockchain = KvStoreChain::new(RamKvStore::new(), conf)?;
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+
+    let mempool = [
+        alice.create_transaction(bob.get_address(), 100, 3, 1),
+        alice.create_transaction(bob.get_address(), 200, 5, 2),
+    ]
+    .into_iter()
+    .map(|tx| (tx, TransactionStats { first_seen: 0 }))
+    .collect::<HashMap<_, _>>();
+    let next_reward = ctx.blockchain.next_reward()?;
+    let draft = ctx
+        .blockchain
+        .draft_block(1, &mempool, &miner, true)?
+        .unwrap();
+    assert_eq!(draft.block.body.len(), 3);
+    ctx.blockchain.apply_block(&draft.block, false)?;
+    // The miner's share of the fees is paid along with the reward
+    assert_eq!(
+        ctx.blockchain.get_account(miner.get_address())?.balance,
+        next_reward + 4
+    );
+    let ctx = Arc::new(RwLock::new(ctx));
+
+    let resp = api::get_block_reward(Arc::clone(&ctx), GetBlockRewardRequest { index: 1 }).await?;
+    assert_eq!(resp.reward, next_reward);
+    assert_eq!(resp.fees, 8);
+    assert_eq!(resp.total, next_reward + 8);
+
+    let resp = api::get_block_summaries(
+        Arc::clone(&ctx),
+        GetBlockSummariesRequest { since: 1, limit: 1 },
+    )
+    .await?;
+    assert_eq!(resp.summaries[0].reward, next_reward);
+    assert_eq!(resp.summaries[0].total_fees, 8);
+
+    // Genesis block has no miner reward
+    let resp = api::get_block_reward(Arc::clone(&ctx), GetBlockRewardRequest { index: 0 }).await?;
+    assert_eq!(resp.reward, 0);
+
+    assert!(matches!(
+        api::get_block_reward(Arc::clone(&ctx), GetBlockRewardRequest { index: 2 }).await,
+        Err(NodeError::BlockchainError(BlockchainError::BlockNotFound))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_decode_tx() -> Result<(), NodeError> {
+    let ctx = Arc::new(RwLock::new(harness::test_context()));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let tx = alice.create_transaction(bob.get_address(), 100, 3, 1).tx;
+
+    let resp = api::decode_tx(
+        Arc::clone(&ctx),
+        DecodeTxRequest {
+            tx: hex::encode(bincode::serialize(&tx)?),
+        },
+    )
+    .await?;
+    assert_eq!(resp.tx.hash, hex::encode(tx.hash()));
+    assert_eq!(resp.tx.src, alice.get_address().to_string());
+    assert_eq!(resp.tx.fee, 3);
+    assert!(resp.valid_signature);
+    assert_eq!(resp.size, tx.size());
+    assert_eq!(resp.fee_rate, tx.fee_rate());
+
+    // Changing a signed transaction invalidates its signature
+    let mut tampered = tx.clone();
+    tampered.fee = 1;
+    let resp = api::decode_tx(
+        Arc::clone(&ctx),
+        DecodeTxRequest {
+            tx: hex::encode(bincode::serialize(&tampered)?),
+        },
+    )
+    .await?;
+    assert_ne!(resp.tx.hash, hex::encode(tx.hash()));
+    assert!(!resp.valid_signature);
+
+    assert!(matches!(
+        api::decode_tx(
+            Arc::clone(&ctx),
+            DecodeTxRequest {
+                tx: "not hex".into()
+            }
+        )
+        .await,
+        Err(NodeError::InputError)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_mempools_survive_restart() -> Result<(), NodeError> {
+    let dir = TempDir::new("bazuka_mempool")?;
+    let path = dir.path().join("mempool.bin");
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let stale = alice.create_transaction(bob.get_address(), 100, 0, 1);
+    let valid = alice.create_transaction(bob.get_address(), 200, 0, 2);
+
+    let mut ctx = harness::test_context();
+    // Nothing is loaded when nothing has been saved yet
+    ctx.load_mempools(&path)?;
+    assert!(ctx.mempool.is_empty());
+    ctx.add_transaction(stale.clone(), 0)?;
+    ctx.add_transaction(valid.clone(), 0)?;
+    ctx.save_mempools(&path)?;
+
+    // Meanwhile, another transaction with the same nonce gets confirmed
+    let mut ctx = harness::test_context();
+    let mempool = [(
+        alice.create_transaction(bob.get_address(), 300, 0, 1),
+        TransactionStats { first_seen: 0 },
+    )]
+    .into_iter()
+    .collect::<HashMap<_, _>>();
+    let draft = ctx
+        .blockchain
+        .draft_block(1, &mempool, &miner, true)?
+        .unwrap();
+    ctx.blockchain.apply_block(&draft.block, false)?;
+
+    ctx.load_mempools(&path)?;
+    assert_eq!(ctx.mempool.keys().cloned().collect::<Vec<_>>(), vec![valid]);
+
+    // A corrupted file is ignored
+    std::fs::write(&path, b"garbage")?;
+    let mut ctx = harness::test_context();
+    assert!(ctx.load_mempools(&path).is_err());
+    ctx.restore_mempools(&path);
+    assert!(ctx.mempool.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_syncing_blocks_without_suitable_peers_is_not_fatal() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let peer = PeerAddress(SocketAddr::from(([127, 0, 0, 1], 3031)));
+    ctx.peers.insert(
+        peer,
+        Peer {
+            pub_key: None,
+            address: peer,
+            punished_until: 0,
+            info: None,
+        },
+    );
+    let ctx = Arc::new(RwLock::new(ctx));
+
+    // The only peer has no known info, so there's nothing to sync from
+    heartbeat::sync_blocks::sync_blocks(&ctx).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failing_to_persist_mempool_is_not_fatal() -> Result<(), NodeError> {
+    let dir = TempDir::new("bazuka_mempool")?;
+    let mut ctx = harness::test_context();
+    // The parent directory doesn't exist, so saving fails
+    ctx.opts.mempool_path = Some(dir.path().join("missing").join("mempool.bin"));
+    let ctx = Arc::new(RwLock::new(ctx));
+
+    heartbeat::persist_mempool::persist_mempool(&ctx).await?;
+    assert!(ctx.read().await.mempool_persisted_at > 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_mempool_trimming() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    ctx.opts.mempool_capacity = 2;
+    let max_age = ctx.opts.mempool_max_age;
+
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let tx = |fee, nonce| alice.create_transaction(bob.get_address(), 10, fee, nonce);
+
+    let old = tx(0, 1);
+    ctx.add_transaction(old.clone(), ctx.network_timestamp())?;
+    assert_eq!(ctx.trim_mempool(ctx.network_timestamp()), 0);
+
+    // Advance the network time past the maximum age
+    ctx.timestamp_offset += max_age as i32 + 1;
+    let now = ctx.network_timestamp();
+    ctx.add_transaction(tx(3, 2), now)?;
+    ctx.mempool.insert(
+        bob.create_transaction(alice.get_address(), 10, 0, 1),
+        TransactionStats { first_seen: now },
+    );
+    // Later transactions of the sender are dropped along with the expired one
+    assert_eq!(ctx.trim_mempool(now), 2);
+    assert!(!ctx.mempool.contains_key(&old));
+    assert_eq!(ctx.mempool.len(), 1);
+    ctx.mempool.clear();
+    ctx.add_transaction(tx(3, 2), now)?;
+
+    // The cheapest transactions are evicted when over capacity
+    let charlie = Wallet::new(Vec::from("CHARLIE"));
+    ctx.add_transaction(tx(1, 3), now)?;
+    ctx.mempool.insert(
+        charlie.create_transaction(bob.get_address(), 10, 2, 1),
+        TransactionStats { first_seen: now },
+    );
+    assert_eq!(ctx.trim_mempool(now), 1);
+    let mut fees = ctx.mempool.keys().map(|t| t.tx.fee).collect::<Vec<_>>();
+    fees.sort();
+    assert_eq!(fees, vec![2, 3]);
+
+    // ...but only from the end of the nonce chain of their sender
+    ctx.add_transaction(tx(1, 3), now)?;
+    ctx.add_transaction(tx(5, 4), now)?;
+    assert_eq!(ctx.trim_mempool(now), 2);
+    let mut nonces = ctx
+        .mempool
+        .keys()
+        .filter(|t| t.tx.src == alice.get_address())
+        .map(|t| t.tx.nonce)
+        .collect::<Vec<_>>();
+    nonces.sort();
+    assert_eq!(nonces, vec![2, 3]);
+
+    Ok(())
+}
+
+#[test]
+fn test_mempool_per_sender_cap() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    ctx.opts.max_txs_per_sender = 3;
+
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let tx = |nonce| alice.create_transaction(bob.get_address(), 10, 0, nonce);
+
+    ctx.add_transaction(tx(1), 0)?;
+    ctx.add_transaction(tx(2), 0)?;
+    ctx.add_transaction(tx(5), 0)?;
+
+    // Lower nonce replaces the highest-nonce one
+    ctx.add_transaction(tx(3), 0)?;
+
+    // Higher nonces are rejected when the sender is at its cap
+    assert!(matches!(
+        ctx.add_transaction(tx(4), 0),
+        Err(NodeError::SenderMempoolFull)
+    ));
+    assert!(matches!(
+        ctx.add_transaction(tx(6), 0),
+        Err(NodeError::SenderMempoolFull)
+    ));
+
+    let mut nonces = ctx.mempool.keys().map(|t| t.tx.nonce).collect::<Vec<_>>();
+    nonces.sort_unstable();
+    assert_eq!(nonces, vec![1, 2, 3]);
+
+    // Other senders are not affected
+    ctx.add_transaction(bob.create_transaction(alice.get_address(), 10, 0, 1), 0)?;
+    assert_eq!(ctx.mempool.len(), 4);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_api_schema_is_served() -> Result<(), NodeError> {
+    init();
+
+    let rules = Arc::new(RwLock::new(Vec::new()));
+    let (node_futs, route_futs, chans) = harness::spawn_network(Arc::clone(&rules), 1);
+
+    let test_logic = async {
+        let definitions = chans[0].schema().await?.definitions;
+        assert!(definitions.contains_key("AccountResponse"));
+        assert!(definitions.contains_key("GetAccountResponse"));
+        assert!(definitions.contains_key("GetStatsResponse"));
+        assert!(definitions.contains_key("Puzzle"));
+
+        for chan in chans.iter() {
+            chan.shutdown().await?;
+        }
+
+        Ok::<(), NodeError>(())
+    };
+    tokio::try_join!(node_futs, route_futs, test_logic)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_info() -> Result<(), NodeError> {
+    init();
+
+    let rules = Arc::new(RwLock::new(Vec::new()));
+    let (node_futs, route_futs, chans) = harness::spawn_network(Arc::clone(&rules), 1);
+
+    let test_logic = async {
+        let creator = Wallet::new(Vec::from("ABC"));
+        let state_model = zk::ZkStateModel::List {
+            item_type: Box::new(zk::ZkStateModel::Scalar),
+            log4_size: 3,
+        };
+        let tx_delta = creator.create_contract(
+            zk::ZkContract {
+                state_model: state_model.clone(),
+                initial_state: state_model
+                    .compress::<ZkHasher>(&Default::default())
+                    .unwrap(),
+                log4_deposit_withdraw_capacity: 1,
+                deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+                functions: vec![zk::ZkVerifierKey::Dummy, zk::ZkVerifierKey::Dummy],
+            },
+            Default::default(),
+            0,
+            1,
+        );
+        let cid = ContractId::new(&tx_delta.tx);
+
+        assert!(matches!(
+            chans[0].contract_info(cid).await,
+            Err(NodeError::RequestFailed(StatusCode::NOT_FOUND))
+        ));
+
+        chans[0].transact(tx_delta).await?;
+        chans[0].mine().await?;
+
+        let info = chans[0].contract_info(cid).await?;
+        assert_eq!(info.state_model, state_model);
+        assert_eq!(info.log4_deposit_withdraw_capacity, 1);
+        assert_eq!(info.num_functions, 2);
+        assert_eq!(info.functions[0], info.deposit_withdraw_function);
+
+        for chan in chans.iter() {
+            chan.shutdown().await?;
+        }
+
+        Ok::<(), NodeError>(())
+    };
+    tokio::try_join!(node_futs, route_futs, test_logic)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_events_are_reported_to_embedders() -> Result<(), NodeError> {
+    let (events_send, mut events) = mpsc::unbounded_channel();
+    let mut ctx = harness::test_context();
+    ctx.opts.events = Some(events_send);
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+
+    let tx = alice.create_transaction(bob.get_address(), 100, 0, 1);
+    ctx.add_transaction(tx.clone(), 0)?;
+    assert!(matches!(
+        events.try_recv(),
+        Ok(NodeEvent::TransactionAccepted(accepted)) if *accepted == tx
+    ));
+
+    // Re-submissions are not reported again
+    ctx.add_transaction(tx.clone(), 0)?;
+    assert!(events.try_recv().is_err());
+
+    let mut draft = ctx
+        .blockchain
+        .draft_block(1, &ctx.mempool, &miner, true)?
+        .unwrap();
+    let pow_key = ctx.blockchain.pow_key(draft.block.header.number)?;
+    while !draft.block.header.meets_target(&pow_key) {
+        draft.block.header.proof_of_work.nonce += 1;
+    }
+    let ctx = Arc::new(RwLock::new(ctx));
+    api::post_block(
+        Arc::clone(&ctx),
+        PostBlockRequest {
+            block: draft.block.clone(),
+            patch: draft.patch.clone(),
+        },
+    )
+    .await?;
+    match events.try_recv() {
+        Ok(NodeEvent::BlockApplied(block)) => {
+            assert_eq!(block.header, draft.block.header);
+            assert!(block.body.contains(&tx.tx));
+        }
+        _ => panic!("block application not reported"),
+    }
+    assert!(events.try_recv().is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_duplicate_block_submission_is_noop() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let miner = Wallet::new(Vec::from("MINER"));
+    let draft = ctx
+        .blockchain
+        .draft_block(1, &HashMap::new(), &miner, true)?
+        .unwrap();
+    ctx.blockchain.apply_block(&draft.block, false)?;
+    ctx.blockchain.update_states(&draft.patch)?;
+    let ctx = Arc::new(RwLock::new(ctx));
+
+    let resp = api::post_block(
+        Arc::clone(&ctx),
+        PostBlockRequest {
+            block: draft.block.clone(),
+            patch: draft.patch.clone(),
+        },
+    )
+    .await?;
+    assert!(resp.already_known);
+    assert_eq!(ctx.read().await.blockchain.get_height()?, 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_account_existence() -> Result<(), NodeError> {
+    init();
+
+    let rules = Arc::new(RwLock::new(Vec::new()));
+    let (node_futs, route_futs, chans) = harness::spawn_network(Arc::clone(&rules), 1);
+
+    let test_logic = async {
+        let alice = Wallet::new(Vec::from("ABC"));
+        let fresh = Wallet::new(Vec::from("FRESH"));
+
+        let resp = chans[0].get_account(fresh.get_address(), false).await?;
+        assert!(!resp.exists);
+        assert_eq!(resp.account.balance, 0);
+
+        chans[0]
+            .transact(alice.create_transaction(fresh.get_address(), 10, 0, 1))
+            .await?;
+        chans[0].mine().await?;
+
+        let resp = chans[0].get_account(fresh.get_address(), false).await?;
+        assert!(resp.exists);
+        assert_eq!(resp.account.balance, 10);
+
+        for chan in chans.iter() {
+            chan.shutdown().await?;
+        }
+
+        Ok::<(), NodeError>(())
+    };
+    tokio::try_join!(node_futs, route_futs, test_logic)?;
+    Ok(())
+}
+
+#[test]
+fn test_most_powerful_peer() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+
+    // Without any peers, there is nobody to sync from
+    assert!(ctx.most_powerful_peer(0).is_none());
+
+    let infos = [
+        (
+            3032,
+            Some(PeerInfo {
+                height: 5,
+                power: 50,
+            }),
+        ),
+        (
+            3033,
+            Some(PeerInfo {
+                height: 9,
+                power: 90,
+            }),
+        ),
+        (
+            3034,
+            Some(PeerInfo {
+                height: 7,
+                power: 70,
+            }),
+        ),
+        (3035, None),
+    ];
+    for (port, info) in infos {
+        let addr = PeerAddress(SocketAddr::from(([127, 0, 0, 1], port)));
+        ctx.peers.insert(
+            addr,
+            Peer {
+                pub_key: None,
+                address: addr,
+                punished_until: 0,
+                info: None,
+            },
+        );
+        if let Some(info) = info {
+            ctx.update_peer_info(addr, info, 0);
+        }
+    }
+    let peer = ctx.most_powerful_peer(0).unwrap();
+    assert_eq!(peer.info.map(|i| i.power), Some(90));
+    assert_eq!(
+        peer.address,
+        PeerAddress(SocketAddr::from(([127, 0, 0, 1], 3033)))
+    );
+
+    // Punished peers are not trusted
+    ctx.peers
+        .get_mut(&PeerAddress(SocketAddr::from(([127, 0, 0, 1], 3033))))
+        .unwrap()
+        .punished_until = u32::MAX;
+    let peer = ctx.most_powerful_peer(0).unwrap();
+    assert_eq!(peer.info.map(|i| i.power), Some(70));
+    assert_eq!(
+        peer.address,
+        PeerAddress(SocketAddr::from(([127, 0, 0, 1], 3034)))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_state_unavailable_peers_are_skipped() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let peer = PeerAddress(SocketAddr::from(([127, 0, 0, 1], 3031)));
+    ctx.peers.insert(
+        peer,
+        Peer {
+            pub_key: None,
+            address: peer,
+            punished_until: 0,
+            info: Some(ctx.get_info()?),
+        },
+    );
+    let height = ctx.blockchain.get_height()?;
+    let threshold = ctx.opts.state_failure_threshold;
+    let ban_time = ctx.opts.state_unavailable_ban_time;
+
+    for _ in 0..threshold {
+        ctx.report_state_failure(peer, 100);
+    }
+    assert_eq!(ctx.state_peers(height, 100).len(), 1);
+    assert_eq!(ctx.num_state_unavailable_peers(100), 0);
+
+    // Going past the threshold stops the node from asking the peer for states
+    ctx.report_state_failure(peer, 100);
+    assert!(ctx.state_peers(height, 100).is_empty());
+    assert_eq!(ctx.num_state_unavailable_peers(100), 1);
+
+    // ...until the ban expires
+    assert_eq!(ctx.state_peers(height, 100 + ban_time).len(), 1);
+    assert_eq!(ctx.num_state_unavailable_peers(100 + ban_time), 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_peers_serving_invalid_states_are_punished() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    ctx.opts.invalid_data_punish = 10;
+    ctx.opts.max_punish = 100;
+
+    // Apply a block calling the contract, without its state patch
+    let miner = Wallet::new(Vec::from("MINER"));
+    let mempool = [(sample_contract_call(1), TransactionStats { first_seen: 0 })]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let draft = ctx
+        .blockchain
+        .draft_block(1, &mempool, &miner, true)?
+        .unwrap();
+    ctx.blockchain.apply_block(&draft.block, false)?;
+    assert_eq!(ctx.blockchain.get_outdated_contracts()?.len(), 1);
+
+    let info = PeerInfo {
+        height: ctx.blockchain.get_height()?,
+        power: ctx.blockchain.get_power()?,
+    };
+    for port in [3031, 3032] {
+        let addr = PeerAddress(SocketAddr::from(([127, 0, 0, 1], port)));
+        ctx.peers.insert(
+            addr,
+            Peer {
+                pub_key: None,
+                address: addr,
+                punished_until: 0,
+                info: Some(info.clone()),
+            },
+        );
+    }
+    // The first peer asked for the states is the liar
+    let peers = ctx.state_peers(info.height, 0);
+    let (liar, honest) = (peers[0].address, peers[1].address);
+
+    let cid = blockchain::get_test_mpn_contract_id();
+    let mut forged = draft.patch.clone();
+    forged.patches.insert(
+        cid,
+        zk::ZkStatePatch::Delta(zk::ZkDeltaPairs(
+            [(zk::ZkDataLocator(vec![123]), Some(zk::ZkScalar::from(345)))]
+                .into_iter()
+                .collect(),
+        )),
+    );
+
+    // Answer the state requests of the node on behalf of the peers
+    let (out_send, mut out_recv) = mpsc::unbounded_channel::<NodeRequest>();
+    ctx.outgoing = Arc::new(OutgoingSender {
+        chan: out_send,
+        priv_key: ctx.outgoing.priv_key.clone(),
+    });
+    let liar_url = format!("{}/bincode/states", liar);
+    let honest_patch = draft.patch.clone();
+    tokio::spawn(async move {
+        while let Some(req) = out_recv.recv().await {
+            let patch = if req.body.uri().to_string().starts_with(&liar_url) {
+                forged.clone()
+            } else {
+                honest_patch.clone()
+            };
+            let resp = GetStateChunksResponse {
+                patch,
+                more_available: false,
+            };
+            let body = Body::from(bincode::serialize(&resp).unwrap());
+            let _ = req.resp.send(Ok(Response::new(body))).await;
+        }
+    });
+
+    let ctx = Arc::new(RwLock::new(ctx));
+    heartbeat::sync_state::sync_state(&ctx).await?;
+
+    let ctx = ctx.read().await;
+    assert!(ctx.blockchain.get_outdated_contracts()?.is_empty());
+    assert!(ctx.peers[&liar].is_punished());
+    assert!(!ctx.peers[&honest].is_punished());
+    assert_eq!(ctx.num_state_unavailable_peers(0), 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_oversized_state_responses_are_refused_before_decoding() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let mut conf = blockchain::get_test_blockchain_config();
+    conf.max_state_patch_size = 1;
+    ctx.blockchain = KvStoreChain::new(RamKvStore::new(), conf)?;
+    ctx.opts.invalid_data_punish = 10;
+    ctx.opts.max_punish = 100;
+
+    let miner = Wallet::new(Vec::from("MINER"));
+    let mempool = [(sample_contract_call(1), TransactionStats { first_seen: 0 })]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let draft = ctx
+        .blockchain
+        .draft_block(1, &mempool, &miner, true)?
+        .unwrap();
+    ctx.blockchain.apply_block(&draft.block, false)?;
+    assert_eq!(ctx.blockchain.get_outdated_contracts()?.len(), 1);
+
+    let addr = PeerAddress(SocketAddr::from(([127, 0, 0, 1], 3031)));
+    ctx.peers.insert(
+        addr,
+        Peer {
+            pub_key: None,
+            address: addr,
+            punished_until: 0,
+            info: Some(PeerInfo {
+                height: ctx.blockchain.get_height()?,
+                power: ctx.blockchain.get_power()?,
+            }),
+        },
+    );
+
+    // Garbage, which would fail decoding if it was ever decoded
+    let (out_send, mut out_recv) = mpsc::unbounded_channel::<NodeRequest>();
+    ctx.outgoing = Arc::new(OutgoingSender {
+        chan: out_send,
+        priv_key: ctx.outgoing.priv_key.clone(),
+    });
+    tokio::spawn(async move {
+        while let Some(req) = out_recv.recv().await {
+            let body = Body::from(vec![0xffu8; 4096]);
+            let _ = req.resp.send(Ok(Response::new(body))).await;
+        }
+    });
+
+    let ctx = Arc::new(RwLock::new(ctx));
+    heartbeat::sync_state::sync_state(&ctx).await?;
+
+    let ctx = ctx.read().await;
+    assert_eq!(ctx.blockchain.get_outdated_contracts()?.len(), 1);
+    assert!(ctx.peers[&addr].is_punished());
+
+    Ok(())
+}
+
+#[test]
+fn test_failing_sync_peers_are_skipped_until_cooldown() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let peer = PeerAddress(SocketAddr::from(([127, 0, 0, 1], 3031)));
+    let threshold = ctx.opts.sync_failure_threshold;
+    let cooldown = ctx.opts.sync_cooldown;
+
+    for _ in 0..threshold - 1 {
+        ctx.report_sync_failure(peer, 100);
+    }
+    assert!(!ctx.is_sync_skipped(peer, 100));
+
+    // The breaker opens after too many consecutive failures...
+    ctx.report_sync_failure(peer, 100);
+    assert!(ctx.is_sync_skipped(peer, 100));
+    assert!(ctx.is_sync_skipped(peer, 100 + cooldown - 1));
+
+    // ...and half-opens after the cooldown, a single failure opens it again
+    assert!(!ctx.is_sync_skipped(peer, 100 + cooldown));
+    ctx.report_sync_failure(peer, 100 + cooldown);
+    assert!(ctx.is_sync_skipped(peer, 100 + cooldown));
+
+    // A success closes it
+    assert!(!ctx.is_sync_skipped(peer, 100 + 2 * cooldown));
+    ctx.report_sync_success(peer);
+    ctx.report_sync_failure(peer, 100 + 2 * cooldown);
+    assert!(!ctx.is_sync_skipped(peer, 100 + 2 * cooldown));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bincode_responses_get_compressed() -> Result<(), NodeError> {
+    init();
+
+    let rules = Arc::new(RwLock::new(Vec::new()));
+    let (node_futs, route_futs, chans) = harness::spawn_network(Arc::clone(&rules), 1);
+
+    let test_logic = async {
+        chans[0].mine().await?;
+
+        let addr = format!("{}/bincode/blocks", chans[0].peer);
+        let req = GetBlocksRequest {
+            since: 0,
+            until: None,
+        };
+        let raw_request = |accept_gzip: bool| {
+            let mut builder = Request::builder().method(Method::GET).uri(&addr);
+            if accept_gzip {
+                builder = builder.header(ACCEPT_ENCODING, compression::GZIP);
+            }
+            builder
+                .body(Body::from(bincode::serialize(&req).unwrap()))
+                .unwrap()
+        };
+
+        let plain = chans[0]
+            .sender
+            .raw_response(raw_request(false), &Limit::default())
+            .await?;
+        assert!(plain.headers().get(CONTENT_ENCODING).is_none());
+        let plain = hyper::body::to_bytes(plain.into_body()).await?;
+
+        let gzipped = chans[0]
+            .sender
+            .raw_response(raw_request(true), &Limit::default())
+            .await?;
+        assert_eq!(gzipped.headers()[CONTENT_ENCODING], compression::GZIP);
+        let gzipped = hyper::body::to_bytes(gzipped.into_body()).await?;
+        assert_eq!(
+            compression::gzip_decompress(&gzipped, None)?,
+            plain.to_vec()
+        );
+
+        // The client helper decompresses transparently
+        let blocks = chans[0]
+            .sender
+            .bincode_get::<GetBlocksRequest, GetBlocksResponse>(
+                addr.clone(),
+                req.clone(),
+                Limit::default(),
+            )
+            .await?
+            .blocks;
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(
+            bincode::serialize(&GetBlocksResponse { blocks })?,
+            plain.to_vec()
+        );
+
+        for chan in chans.iter() {
+            chan.shutdown().await?;
+        }
+
+        Ok::<(), NodeError>(())
+    };
+    tokio::try_join!(node_futs, route_futs, test_logic)?;
+    Ok(())
+}
+
+#[test]
+fn test_banned_headers_are_swept() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    ctx.opts.max_banned_headers = 3;
+    let ban_time = ctx.opts.state_unavailable_ban_time;
+    let mut header = ctx.blockchain.get_tip()?;
+
+    let mut headers = Vec::new();
+    for ts in 0..5 {
+        header.number = ts as u64 + 1;
+        headers.push(header.clone());
+        ctx.ban_header(header.clone(), 100 + ts);
+    }
+
+    // Oldest bans are evicted to keep the map bounded
+    assert_eq!(ctx.banned_headers.len(), 3);
+    assert!(!ctx.banned_headers.contains_key(&headers[0]));
+    assert!(!ctx.banned_headers.contains_key(&headers[1]));
+
+    ctx.sweep_banned_headers(102 + ban_time);
+    assert_eq!(ctx.banned_headers.len(), 2);
+    assert!(!ctx.banned_headers.contains_key(&headers[2]));
+
+    ctx.sweep_banned_headers(104 + ban_time);
+    assert!(ctx.banned_headers.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_pending_balance_delta() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let charlie = Wallet::new(Vec::from("CHARLIE"));
+
+    ctx.add_transaction(alice.create_transaction(bob.get_address(), 10, 1, 1), 0)?;
+    ctx.add_transaction(alice.create_transaction(bob.get_address(), 20, 2, 2), 0)?;
+    // Conflicts with the previous one, and pays a higher fee
+    ctx.add_transaction(alice.create_transaction(bob.get_address(), 5, 3, 2), 0)?;
+    ctx.add_transaction(bob.create_transaction(alice.get_address(), 3, 0, 1), 0)?;
+
+    assert_eq!(ctx.pending_balance_delta(&alice.get_address()), -11 - 8 + 3);
+    assert_eq!(ctx.pending_balance_delta(&bob.get_address()), 10 + 5 - 3);
+    assert_eq!(ctx.pending_balance_delta(&charlie.get_address()), 0);
+
+    // The conflicting transaction paying to charlie loses to the one paying to bob
+    for tx in [
+        alice.create_transaction(charlie.get_address(), 7, 1, 3),
+        alice.create_transaction(bob.get_address(), 9, 4, 3),
+    ] {
+        ctx.mempool.insert(tx, TransactionStats { first_seen: 0 });
+    }
+    assert_eq!(
+        ctx.pending_balance_delta(&alice.get_address()),
+        -11 - 8 + 3 - 13
+    );
+    assert_eq!(
+        ctx.pending_balance_delta(&bob.get_address()),
+        10 + 5 - 3 + 9
+    );
+    assert_eq!(ctx.pending_balance_delta(&charlie.get_address()), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_replacing_middle_of_chain_transaction() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let tx = |amount, fee, nonce| alice.create_transaction(bob.get_address(), amount, fee, nonce);
+
+    ctx.add_transaction(tx(100, 1, 1), 0)?;
+    ctx.add_transaction(tx(100, 1, 2), 0)?;
+    ctx.add_transaction(tx(100, 1, 3), 0)?;
+
+    // Leaves no balance for nonce 3, so it has to pay for both nonce 2 and 3
+    assert!(matches!(
+        ctx.add_transaction(tx(9800, 2, 2), 0),
+        Err(NodeError::ReplacementFeeTooLow)
+    ));
+    assert_eq!(ctx.mempool.len(), 3);
+
+    // Descendants remaining valid are kept
+    let cheap = tx(200, 2, 2);
+    ctx.add_transaction(cheap.clone(), 0)?;
+    let mut txs = ctx.mempool.keys().cloned().collect::<Vec<_>>();
+    txs.sort_by_key(|t| t.tx.nonce);
+    assert_eq!(txs, vec![tx(100, 1, 1), cheap, tx(100, 1, 3)]);
+
+    // Invalidated descendants are dropped
+    let expensive = tx(9800, 4, 2);
+    ctx.add_transaction(expensive.clone(), 0)?;
+    let mut txs = ctx.mempool.keys().cloned().collect::<Vec<_>>();
+    txs.sort_by_key(|t| t.tx.nonce);
+    assert_eq!(txs, vec![tx(100, 1, 1), expensive]);
+
+    Ok(())
+}
+
+#[test]
+fn test_canceling_pending_transaction() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+
+    let stuck = alice.create_transaction(bob.get_address(), 100, 2, 1);
+    ctx.add_transaction(stuck.clone(), 0)?;
+
+    // Canceling should pay more than the canceled transaction
+    assert!(matches!(
+        ctx.add_transaction(alice.create_cancel(1, 2), 0),
+        Err(NodeError::ReplacementFeeTooLow)
+    ));
+    assert_eq!(ctx.mempool.keys().cloned().collect::<Vec<_>>(), vec![stuck]);
+
+    let cancel = alice.create_cancel(1, 3);
+    ctx.add_transaction(cancel.clone(), 0)?;
+    assert_eq!(
+        ctx.mempool.keys().cloned().collect::<Vec<_>>(),
+        vec![cancel]
+    );
+    assert_eq!(ctx.pending_balance_delta(&alice.get_address()), -3);
+    assert_eq!(ctx.pending_balance_delta(&bob.get_address()), 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_peers_with_different_network_magic_are_rejected() -> Result<(), NodeError> {
+    let ctx = Arc::new(RwLock::new(harness::test_context()));
+    let network_magic = ctx.read().await.blockchain.config().network_magic;
+    let info = ctx.read().await.get_info()?;
+    let peer = |port| PostPeerRequest {
+        address: PeerAddress(SocketAddr::from(([127, 0, 0, 1], port))),
+        info: info.clone(),
+        timestamp: 0,
+        network_magic,
+    };
+
+    assert!(matches!(
+        api::post_peer(
+            Arc::clone(&ctx),
+            PostPeerRequest {
+                network_magic: network_magic + 1,
+                ..peer(3031)
+            }
+        )
+        .await,
+        Err(NodeError::NetworkMagicMismatch)
+    ));
+    assert!(ctx.read().await.peers.is_empty());
+
+    let resp = api::post_peer(Arc::clone(&ctx), peer(3032)).await?;
+    assert_eq!(resp.network_magic, network_magic);
+    assert_eq!(ctx.read().await.peers.len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_punishment_events_are_recorded() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    ctx.opts.incorrect_power_punish = 12;
+    ctx.opts.max_punish = 100;
+    let peer = PeerAddress(SocketAddr::from(([127, 0, 0, 1], 3031)));
+    ctx.peers.insert(
+        peer,
+        Peer {
+            pub_key: None,
+            address: peer,
+            punished_until: 0,
+            info: None,
+        },
+    );
+
+    ctx.punish(peer, PunishmentReason::IncorrectPower);
+    assert!(ctx.peers[&peer].is_punished());
+
+    let ctx = Arc::new(RwLock::new(ctx));
+    let punishments = api::get_punishments(Arc::clone(&ctx), GetPunishmentsRequest {})
+        .await?
+        .punishments;
+    assert_eq!(punishments.len(), 1);
+    assert_eq!(punishments[0].peer, peer);
+    assert_eq!(punishments[0].reason, PunishmentReason::IncorrectPower);
+    assert_eq!(punishments[0].amount, 12);
+
+    // Only the most recent events are kept
+    let mut ctx = ctx.write().await;
+    let max_events = ctx.opts.max_punishment_events;
+    for _ in 0..max_events {
+        ctx.punish(peer, PunishmentReason::NoResponse);
+    }
+    assert_eq!(ctx.punishments.len(), max_events);
+    assert!(ctx
+        .punishments
+        .iter()
+        .all(|p| p.reason == PunishmentReason::NoResponse));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_peers_are_not_punished_during_startup_grace_period() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    ctx.opts.no_response_punish = 10;
+    ctx.opts.invalid_data_punish = 10;
+    ctx.opts.max_punish = 100;
+    ctx.opts.startup_grace_period = 60;
+    ctx.started_at = utils::local_timestamp();
+    let peer = PeerAddress(SocketAddr::from(([127, 0, 0, 1], 3031)));
+    ctx.peers.insert(
+        peer,
+        Peer {
+            pub_key: None,
+            address: peer,
+            punished_until: 0,
+            info: None,
+        },
+    );
+    let ctx = Arc::new(RwLock::new(ctx));
+
+    // The context isn't connected to any network, so the peer never responds
+    heartbeat::sync_peers::sync_peers(&ctx).await?;
+    {
+        let ctx = ctx.read().await;
+        assert!(!ctx.peers[&peer].is_punished());
+        assert!(ctx.punishments.is_empty());
+    }
+
+    // Invalid data is punished regardless
+    ctx.write()
+        .await
+        .punish(peer, PunishmentReason::InvalidData);
+    {
+        let mut ctx = ctx.write().await;
+        assert!(ctx.peers[&peer].is_punished());
+        assert_eq!(ctx.punishments.len(), 1);
+        ctx.peers.get_mut(&peer).unwrap().punished_until = 0;
+        ctx.started_at -= 60;
+    }
+
+    heartbeat::sync_peers::sync_peers(&ctx).await?;
+    let ctx = ctx.read().await;
+    assert!(ctx.peers[&peer].is_punished());
+    assert_eq!(ctx.punishments.len(), 2);
+    assert_eq!(
+        ctx.punishments.back().unwrap().reason,
+        PunishmentReason::NoResponse
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sync_status_reports_lagging_node() -> Result<(), NodeError> {
+    init();
+
+    let rules = Arc::new(RwLock::new(vec![Rule::drop_all()]));
+    let (node_futs, route_futs, chans) = harness::spawn_network(Arc::clone(&rules), 2);
+
+    let test_logic = async {
+        for _ in 0..2 {
+            chans[0].mine().await?;
+        }
+
+        // Peers can see each other, but blocks are slow to download
+        *rules.write().await = vec![Rule {
+            from: Endpoint::Any,
+            to: Endpoint::Any,
+            url: "bincode/blocks".into(),
+            action: Action::Delay(Duration::from_secs(5)),
+        }];
+        assert!(
+            catch_change(|| async { Ok(chans[1].sync_status().await?.status.is_syncing) }).await?
+        );
+        let status = chans[1].sync_status().await?.status;
+        assert_eq!(status.tip_height, 1);
+        assert_eq!(status.best_peer_height, 3);
+
+        rules.write().await.clear();
+        assert!(
+            !catch_change(|| async { Ok(chans[1].sync_status().await?.status.is_syncing) }).await?
+        );
+        let status = chans[1].sync_status().await?.status;
+        assert_eq!(status.tip_height, 3);
+        assert!(!status.states_outdated);
+
+        for chan in chans.iter() {
+            chan.shutdown().await?;
+        }
+
+        Ok::<(), NodeError>(())
+    };
+    tokio::try_join!(node_futs, route_futs, test_logic)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_miner_solutions() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    ctx.wallet = Some(Wallet::new(Vec::from("MINER")));
+    let ctx = Arc::new(RwLock::new(ctx));
+
+    let puzzle = api::get_miner_puzzle(Arc::clone(&ctx), GetMinerPuzzleRequest {})
+        .await?
+        .puzzle
+        .unwrap();
+
+    // Garbage nonces are rejected
+    let resp = api::post_miner_solution(
+        Arc::clone(&ctx),
+        PostMinerSolutionRequest { nonce: "zz".into() },
+    )
+    .await?;
+    assert!(!resp.accepted);
+    assert_eq!(resp.reason.as_deref(), Some("invalid nonce"));
+
+    // Failures other than an unmet target are not blamed on the nonce
+    ctx.write()
+        .await
+        .miner_puzzle
+        .as_mut()
+        .unwrap()
+        .0
+        .block
+        .header
+        .block_root = Default::default();
+    assert!(matches!(
+        api::post_miner_solution(Arc::clone(&ctx), crate::client::mine_puzzle(&puzzle)).await,
+        Err(NodeError::BlockchainError(
+            BlockchainError::InvalidMerkleRoot
+        ))
+    ));
+
+    // Someone else extends the chain before our solution arrives
+    {
+        let mut ctx = ctx.write().await;
+        let other = Wallet::new(Vec::from("OTHER"));
+        let draft = ctx
+            .blockchain
+            .draft_block(1, &HashMap::new(), &other, true)?
+            .unwrap();
+        ctx.blockchain.apply_block(&draft.block, false)?;
+        ctx.blockchain.update_states(&draft.patch)?;
+    }
+    let resp =
+        api::post_miner_solution(Arc::clone(&ctx), crate::client::mine_puzzle(&puzzle)).await?;
+    assert!(!resp.accepted);
+    assert_eq!(resp.reason.as_deref(), Some("stale"));
+    assert!(ctx.read().await.miner_puzzle.is_none());
+
+    // A fresh puzzle is built on the new tip
+    let puzzle = api::get_miner_puzzle(Arc::clone(&ctx), GetMinerPuzzleRequest {})
+        .await?
+        .puzzle
+        .unwrap();
+    let resp =
+        api::post_miner_solution(Arc::clone(&ctx), crate::client::mine_puzzle(&puzzle)).await?;
+    assert!(resp.accepted);
+    assert_eq!(resp.reason, None);
+    assert_eq!(ctx.read().await.blockchain.get_height()?, 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_idle_connections_time_out() -> Result<(), NodeError> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let (client, server) = tokio::io::duplex(64);
+    let mut server = IdleTimeoutStream::new(server, Duration::from_millis(200));
+    let mut client = client;
+    let mut buf = [0u8; 4];
+
+    // Activity keeps the connection alive
+    for _ in 0..3 {
+        sleep(Duration::from_millis(100)).await;
+        client.write_all(b"ping").await?;
+        server.read_exact(&mut buf).await?;
+        server.write_all(b"pong").await?;
+        client.read_exact(&mut buf).await?;
+    }
+
+    let err = server.read(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_slow_request_body_times_out() -> Result<(), NodeError> {
+    let ctx = Arc::new(RwLock::new(harness::test_context()));
+    let timeout = ctx.read().await.opts.http_body_read_timeout;
+
+    // Body is never completed
+    let (mut sender, body) = Body::channel();
+    sender.send_data("{".into()).await?;
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri("/bincode/transact")
+        .body(body)
+        .unwrap();
+
+    let start = std::time::Instant::now();
+    let resp = node_service(None, Arc::clone(&ctx), req).await?;
+    assert_eq!(resp.status(), StatusCode::REQUEST_TIMEOUT);
+    assert!(start.elapsed() >= timeout);
+    assert!(start.elapsed() < timeout * 3);
+    drop(sender);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_responses_carry_request_id() -> Result<(), NodeError> {
+    init();
+    let ctx = Arc::new(RwLock::new(harness::test_context()));
+
+    let req = Request::builder()
+        .method(Method::GET)
+        .uri("/stats")
+        .body(Body::empty())
+        .unwrap();
+    let resp = node_service(None, Arc::clone(&ctx), req).await?;
+    let request_id = resp
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(request_id.len(), 8);
+    assert!(captured_log_lines()
+        .iter()
+        .any(|l| l.ends_with(&format!("[{}] GET /stats -> 200 OK", request_id))));
+
+    // Each request gets its own id
+    let req = Request::builder()
+        .method(Method::GET)
+        .uri("/stats")
+        .body(Body::empty())
+        .unwrap();
+    let resp = node_service(None, Arc::clone(&ctx), req).await?;
+    assert_ne!(
+        resp.headers()
+            .get(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap(),
+        request_id
+    );
+
+    // Failures are logged along with the id
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri("/bincode/transact")
+        .body(Body::from("garbage"))
+        .unwrap();
+    let resp = node_service(None, Arc::clone(&ctx), req).await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let failed_id = resp
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let line = captured_log_lines()
+        .into_iter()
+        .find(|l| l.contains("POST /bincode/transact failed"))
+        .unwrap();
+    let tag = line.split("] [").nth(1).unwrap().split(']').next().unwrap();
+    assert_eq!(tag, failed_id);
+    assert_ne!(tag, request_id);
+
+    // Nothing is tagged outside of a request
+    assert!(current_request_id().is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_account_with_pending_transactions() -> Result<(), NodeError> {
+    init();
+
+    let rules = Arc::new(RwLock::new(Vec::new()));
+    let (node_futs, route_futs, chans) = harness::spawn_network(Arc::clone(&rules), 1);
+
+    let test_logic = async {
+        let alice = Wallet::new(Vec::from("ABC"));
+        let bob = Wallet::new(Vec::from("CBA"));
+
+        let resp = chans[0].get_account(alice.get_address(), false).await?;
+        assert!(resp.pending.is_none());
+        let confirmed = resp.account;
+
+        chans[0]
+            .transact(alice.create_transaction(bob.get_address(), 100, 2, 1))
+            .await?;
+        // Transactions are validated against the chain, a nonce gap is not accepted
+        chans[0]
+            .transact(alice.create_transaction(bob.get_address(), 50, 1, 3))
+            .await?;
+
+        let resp = chans[0].get_account(alice.get_address(), true).await?;
+        assert_eq!(resp.account, confirmed);
+        let pending = resp.pending.unwrap();
+        assert_eq!(pending.balance, confirmed.balance - 102);
+        assert_eq!(pending.nonce, confirmed.nonce + 1);
+
+        let pending = chans[0]
+            .get_account(bob.get_address(), true)
+            .await?
+            .pending
+            .unwrap();
+        assert_eq!(pending.balance, 100);
+        assert_eq!(pending.nonce, 0);
+
+        for chan in chans.iter() {
+            chan.shutdown().await?;
+        }
+
+        Ok::<(), NodeError>(())
+    };
+    tokio::try_join!(node_futs, route_futs, test_logic)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_json_responses_are_canonical() -> Result<(), NodeError> {
+    let ctx = Arc::new(RwLock::new(harness::test_context()));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let genesis = ctx.read().await.blockchain.get_block(0)?;
+
+    let account = serde_json::to_value(
+        api::get_account(
+            Arc::clone(&ctx),
+            GetAccountRequest {
+                address: alice.get_address().to_string(),
+                include_pending: false,
+            },
+        )
+        .await?,
+    )?;
+    assert_eq!(
+        account["account"],
+        serde_json::json!({"balance": 10000, "nonce": 0})
+    );
+
+    let blocks = serde_json::to_value(
+        api::get_json_blocks(
+            Arc::clone(&ctx),
+            GetBlocksRequest {
+                since: 0,
+                until: Some(1),
+            },
+        )
+        .await?,
+    )?;
+    let header = &blocks["blocks"][0]["header"];
+    assert_eq!(header["hash"], hex::encode(genesis.header.hash()));
+    assert_eq!(header["parent_hash"], "00".repeat(32));
+    assert_eq!(header["block_root"], hex::encode(genesis.header.block_root));
+    assert_eq!(header["number"], 0);
+    assert_eq!(header["version"], 0);
+    assert_eq!(header["timestamp"], genesis.header.proof_of_work.timestamp);
+
+    let txs = blocks["blocks"][0]["body"].as_array().unwrap();
+    assert_eq!(txs.len(), genesis.body.len());
+    assert_eq!(txs[0]["hash"], hex::encode(genesis.body[0].hash()));
+    assert_eq!(txs[0]["src"], "Treasury");
+    assert_eq!(txs[1]["data"]["type"], "create_contract");
+    assert_eq!(
+        txs[1]["data"]["contract_id"],
+        blockchain::get_test_mpn_contract_id().to_string()
+    );
+
+    let headers = api::get_json_headers(
+        Arc::clone(&ctx),
+        GetHeadersRequest {
+            since: 0,
+            until: Some(1),
+        },
+    )
+    .await?;
+    assert_eq!(headers.headers[0], (&genesis.header).into());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_connections_beyond_cap_are_refused() -> Result<(), NodeError> {
+    let ctx = Arc::new(RwLock::new(harness::test_context()));
+    let limiter = Arc::clone(&ctx.read().await.connections);
+    let max_connections = limiter.max_connections();
+
+    let mut guards = (0..max_connections)
+        .map(|_| limiter.try_acquire())
+        .collect::<Option<Vec<_>>>()
+        .unwrap();
+    assert!(limiter.try_acquire().is_none());
+
+    let metrics = api::get_metrics(Arc::clone(&ctx), GetMetricsRequest {}).await?;
+    assert_eq!(metrics.open_connections, max_connections);
+    assert_eq!(metrics.max_connections, max_connections);
+
+    // Closing a connection frees a slot
+    guards.pop();
+    assert_eq!(limiter.open_connections(), max_connections - 1);
+    guards.push(limiter.try_acquire().unwrap());
+    assert!(limiter.try_acquire().is_none());
+
+    drop(guards);
+    let metrics = api::get_metrics(Arc::clone(&ctx), GetMetricsRequest {}).await?;
+    assert_eq!(metrics.open_connections, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_propagation_and_latency_metrics() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let fast = PeerAddress(SocketAddr::from(([127, 0, 0, 1], 3031)));
+    let slow = PeerAddress(SocketAddr::from(([127, 0, 0, 1], 3032)));
+    let mut header = ctx.blockchain.get_tip()?;
+    header.proof_of_work.timestamp = 1000;
+
+    // Blocks applied 1, 1 and 4 seconds after their timestamps
+    ctx.record_block_propagation(fast, &header, 1001);
+    ctx.record_block_propagation(fast, &header, 1001);
+    ctx.record_block_propagation(fast, &header, 1004);
+    // ...and 20 and 400 seconds after
+    ctx.record_block_propagation(slow, &header, 1020);
+    ctx.record_block_propagation(slow, &header, 1400);
+
+    ctx.record_request_latency(fast, Duration::from_millis(30));
+    ctx.record_request_latency(slow, Duration::from_millis(800));
+
+    let ctx = Arc::new(RwLock::new(ctx));
+    let metrics = api::get_metrics(Arc::clone(&ctx), GetMetricsRequest {}).await?;
+    let propagation = &metrics.block_propagation;
+    assert_eq!(
+        propagation[&fast.to_string()].bounds,
+        vec![1, 2, 5, 10, 30, 60, 300]
+    );
+    assert_eq!(
+        propagation[&fast.to_string()].counts,
+        vec![2, 0, 1, 0, 0, 0, 0, 0]
+    );
+    assert_eq!(
+        propagation[&slow.to_string()].counts,
+        vec![0, 0, 0, 0, 1, 0, 0, 1]
+    );
+
+    let latency = &metrics.request_latency;
+    assert_eq!(
+        latency[&fast.to_string()].counts,
+        vec![0, 1, 0, 0, 0, 0, 0, 0]
+    );
+    assert_eq!(
+        latency[&slow.to_string()].counts,
+        vec![0, 0, 0, 0, 0, 1, 0, 0]
+    );
+
+    // Blocks of a catch-up sync are not observed, unlike the ones near the tip
+    let mut ctx = ctx.write().await;
+    let miner = Wallet::new(Vec::from("MINER"));
+    let mut blocks = Vec::new();
+    let mut chain = harness::test_context().blockchain;
+    for ts in 1..10 {
+        let draft = chain
+            .draft_block(ts, &HashMap::new(), &miner, true)?
+            .unwrap();
+        chain.apply_block(&draft.block, false)?;
+        blocks.push(draft.block);
+    }
+    ctx.record_synced_blocks(fast, &blocks, 1000);
+    assert_eq!(ctx.block_propagation[&fast].counts.iter().sum::<u64>(), 3);
+    ctx.record_synced_blocks(fast, &blocks[8..], 1000);
+    assert_eq!(ctx.block_propagation[&fast].counts.iter().sum::<u64>(), 4);
+
+    // Nothing is kept about forgotten peers
+    ctx.forget_peer(slow);
+    assert!(!ctx.block_propagation.contains_key(&slow));
+    assert!(!ctx.request_latency.contains_key(&slow));
+
+    Ok(())
+}
+
+#[test]
+fn test_conflicting_contract_updates() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let cid = blockchain::get_test_mpn_contract_id();
+    let call = |wallet: &Wallet, next: u64, fee, nonce| {
+        let state_model = zk::ZkStateModel::List {
+            item_type: Box::new(zk::ZkStateModel::Scalar),
+            log4_size: 5,
+        };
+        let state_delta = zk::ZkDeltaPairs(
+            [(zk::ZkDataLocator(vec![123]), Some(zk::ZkScalar::from(next)))]
+                .into_iter()
+                .collect(),
+        );
+        let next_state = state_model
+            .compress::<ZkHasher>(&zk::ZkDataPairs(
+                [(zk::ZkDataLocator(vec![123]), zk::ZkScalar::from(next))]
+                    .into_iter()
+                    .collect(),
+            ))
+            .unwrap();
+        wallet.call_function(
+            cid,
+            0,
+            state_delta,
+            next_state,
+            zk::ZkProof::Dummy(true),
+            fee,
+            nonce,
+        )
+    };
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let charlie = Wallet::new(Vec::from("CHARLIE"));
+
+    ctx.add_transaction(call(&alice, 1, 1, 1), 0)?;
+
+    // Higher fee replaces the conflicting update
+    let bob_tx = call(&bob, 2, 5, 1);
+    ctx.add_transaction(bob_tx.clone(), 0)?;
+
+    // Lower fee is refused
+    assert!(matches!(
+        ctx.add_transaction(call(&charlie, 3, 2, 1), 0),
+        Err(NodeError::ConflictingContractUpdate)
+    ));
+
+    // Updates built on top of a pending update don't conflict with it
+    let bob_next_tx = call(&bob, 4, 0, 2);
+    ctx.add_transaction(bob_next_tx.clone(), 0)?;
+
+    let mut txs = ctx.mempool.keys().cloned().collect::<Vec<_>>();
+    txs.sort_by_key(|t| t.tx.nonce);
+    assert_eq!(txs, vec![bob_tx, bob_next_tx]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_solo_mining() -> Result<(), NodeError> {
+    init();
+
+    let mut ctx = harness::test_context();
+    ctx.opts.solo_mine = true;
+    ctx.wallet = Some(Wallet::new(Vec::from("MINER")));
+    let ctx = Arc::new(RwLock::new(ctx));
+
+    let miner = solo_miner::solo_miner(Arc::clone(&ctx));
+    let test_logic = async {
+        for _ in 0..MAX_WAIT_FOR_CHANGE * 5 {
+            if ctx.read().await.blockchain.get_height()? >= 4 {
+                break;
+            }
+            sleep(Duration::from_millis(200)).await;
+        }
+        ctx.write().await.shutdown = true;
+        Ok::<(), NodeError>(())
+    };
+    tokio::try_join!(miner, test_logic)?;
+
+    let ctx = ctx.read().await;
+    assert!(ctx.blockchain.get_height()? >= 4);
+    let miner = Wallet::new(Vec::from("MINER")).get_address();
+    assert!(ctx.blockchain.get_account(miner)?.balance > 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_miner_stops_on_exhausted_nonce_space() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let (_, mut puzzle) = ctx.get_puzzle(Wallet::new(Vec::from("MINER")))?.unwrap();
+
+    // A single byte of nonce, and a target no hash meets
+    puzzle.size = 1;
+    puzzle.target = 0x08000000;
+    let abort = AtomicBool::new(false);
+    assert!(solo_miner::mine_puzzle(&puzzle, &abort).is_none());
+    assert!(!abort.load(Ordering::Relaxed));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_accounts_are_paginated() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let alice = Wallet::new(Vec::from("ABC"));
+    let miner = Wallet::new(Vec::from("MINER"));
+    let mempool = (0..5)
+        .map(|i| {
+            let receiver = Wallet::new(format!("RECEIVER{}", i).into_bytes()).get_address();
+            alice.create_transaction(receiver, 100, 0, i + 1)
+        })
+        .map(|tx| (tx, TransactionStats { first_seen: 0 }))
+        .collect::<HashMap<_, _>>();
+    let draft = ctx
+        .blockchain
+        .draft_block(1, &mempool, &miner, true)?
+        .unwrap();
+    ctx.blockchain.apply_block(&draft.block, false)?;
+    let all = ctx
+        .blockchain
+        .all_accounts()?
+        .into_iter()
+        .map(|(addr, _)| addr.to_string())
+        .collect::<Vec<_>>();
+    let ctx = Arc::new(RwLock::new(ctx));
+
+    let mut paged = Vec::new();
+    loop {
+        let resp = api::get_accounts(
+            Arc::clone(&ctx),
+            GetAccountsRequest {
+                offset: paged.len(),
+                limit: 3,
+            },
+        )
+        .await?;
+        if resp.accounts.is_empty() {
+            break;
+        }
+        assert!(resp.accounts.len() <= 3);
+        paged.extend(resp.accounts.into_iter().map(|entry| entry.address));
+    }
+    assert_eq!(paged, all);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_block_summaries_match_blocks() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let miner = Wallet::new(Vec::from("MINER"));
+    let mempool = [
+        alice.create_transaction(bob.get_address(), 100, 2, 1),
+        alice.create_transaction(bob.get_address(), 50, 3, 2),
+    ]
+    .into_iter()
+    .map(|tx| (tx, TransactionStats { first_seen: 0 }))
+    .collect::<HashMap<_, _>>();
+    let draft = ctx
+        .blockchain
+        .draft_block(1, &mempool, &miner, true)?
+        .unwrap();
+    ctx.blockchain.apply_block(&draft.block, false)?;
+    ctx.blockchain.update_states(&draft.patch)?;
+    let ctx = Arc::new(RwLock::new(ctx));
+
+    let resp = api::get_block_summaries(
+        Arc::clone(&ctx),
+        GetBlockSummariesRequest {
+            since: 0,
+            limit: 10,
+        },
+    )
+    .await?;
+    assert_eq!(resp.summaries.len(), 2);
+
+    let genesis = &resp.summaries[0];
+    assert_eq!(genesis.height, 0);
+    assert_eq!(genesis.reward, 0);
+    assert!(genesis.miner.is_none());
+
+    let summary = &resp.summaries[1];
+    let block = &draft.block;
+    assert_eq!(summary.height, 1);
+    assert_eq!(summary.hash, hex::encode(block.header.hash()));
+    assert_eq!(summary.num_txs, block.body.len());
+    assert_eq!(summary.num_txs, 3);
+    assert_eq!(
+        summary.total_fees,
+        block.body.iter().map(|tx| tx.fee).sum::<Money>()
+    );
+    assert_eq!(summary.total_fees, 5);
+    assert_eq!(summary.miner, Some(miner.get_address().to_string()));
+    assert!(summary.reward > 0);
+
+    // Huge limits are clamped
+    let resp = api::get_block_summaries(
+        Arc::clone(&ctx),
+        GetBlockSummariesRequest {
+            since: 1,
+            limit: u64::MAX,
+        },
+    )
+    .await?;
+    assert_eq!(resp.summaries.len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_huge_states_are_synced_in_chunks() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    ctx.opts.state_chunk_size = 2;
+    let mut syncer = harness::test_context();
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 5,
+    };
+    let create_tx = alice.create_contract(
+        zk::ZkContract {
+            initial_state: state_model
+                .compress::<ZkHasher>(&Default::default())
+                .unwrap(),
+            state_model: state_model.clone(),
+            deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+            log4_deposit_withdraw_capacity: 0,
+            functions: vec![zk::ZkVerifierKey::Dummy],
+        },
+        Default::default(),
+        0,
+        1,
+    );
+    let cid = ContractId::new(&create_tx.tx);
+
+    // More updates than the rollbacks kept, so that a full-state is needed
+    let mut txs = vec![create_tx];
+    let mut data = zk::ZkDataPairs::default();
+    for i in 0..7 {
+        let delta = zk::ZkDeltaPairs(
+            [(
+                zk::ZkDataLocator(vec![i]),
+                Some(zk::ZkScalar::from(i as u64 + 1)),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        data.0
+            .insert(zk::ZkDataLocator(vec![i]), zk::ZkScalar::from(i as u64 + 1));
+        txs.push(alice.call_function(
+            cid,
+            0,
+            delta,
+            state_model.compress::<ZkHasher>(&data).unwrap(),
+            zk::ZkProof::Dummy(true),
+            0,
+            i + 2,
+        ));
+    }
+    for (i, tx) in txs.into_iter().enumerate() {
+        let mempool = [(tx, TransactionStats { first_seen: 0 })]
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        let draft = ctx
+            .blockchain
+            .draft_block(i as u32 + 1, &mempool, &miner, true)?
+            .unwrap();
+        ctx.blockchain.apply_block(&draft.block, false)?;
+        ctx.blockchain.update_states(&draft.patch)?;
+        syncer.blockchain.apply_block(&draft.block, false)?;
+    }
+
+    let outdated_heights = syncer.blockchain.get_outdated_heights()?;
+    assert!(outdated_heights.contains_key(&cid));
+    let to = hex::encode(syncer.blockchain.get_tip()?.hash());
+    let ctx = Arc::new(RwLock::new(ctx));
+    let get_chunk = |chunk| {
+        api::get_state_chunks(
+            Arc::clone(&ctx),
+            GetStateChunksRequest {
+                outdated_heights: outdated_heights.clone(),
+                to: to.clone(),
+                chunk,
+            },
+        )
+    };
+
+    let mut patch = get_chunk(0).await?.patch;
+    let total = match patch.patches.get(&cid) {
+        Some(zk::ZkStatePatch::Chunks(chunks)) => chunks[0].total,
+        _ => panic!("full-state is not chunked"),
+    };
+    assert_eq!(total, 4);
+
+    // A partial state is not accepted
+    assert!(matches!(
+        syncer.blockchain.update_states(&patch),
+        Err(BlockchainError::FullStateNotValid)
+    ));
+
+    for chunk in 1..total {
+        let part = get_chunk(chunk).await?.patch.patches.remove(&cid);
+        if let (Some(zk::ZkStatePatch::Chunks(chunks)), Some(zk::ZkStatePatch::Chunks(part))) =
+            (patch.patches.get_mut(&cid), part)
+        {
+            assert!(part.iter().all(|c| c.state.data.size() <= 2));
+            chunks.extend(part);
+        }
+    }
+    assert!(matches!(get_chunk(total).await, Err(NodeError::InputError)));
+    assert!(ctx.read().await.state_chunks.contains_key(&cid));
+
+    // Peers of older versions still receive the whole full-state at once
+    let legacy = api::get_states(
+        Arc::clone(&ctx),
+        GetStatesRequest {
+            outdated_heights: outdated_heights.clone(),
+            to: to.clone(),
+        },
+    )
+    .await?;
+    assert!(matches!(
+        legacy.patch.patches.get(&cid),
+        Some(zk::ZkStatePatch::Full(_))
+    ));
+
+    syncer.blockchain.update_states(&patch)?;
+    assert!(syncer.blockchain.get_outdated_contracts()?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_state_patch_responses_are_capped() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    ctx.opts.max_state_patch_contracts = 3;
+    let mut syncer = harness::test_context();
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+
+    let state_model = zk::ZkStateModel::List {
+        item_type: Box::new(zk::ZkStateModel::Scalar),
+        log4_size: 1,
+    };
+    let mut mempool = HashMap::new();
+    for i in 0..4 {
+        let initial_data = zk::ZkDataPairs(
+            [(zk::ZkDataLocator(vec![0]), zk::ZkScalar::from(i as u64 + 1))]
+                .into_iter()
+                .collect(),
+        );
+        let tx_delta = alice.create_contract(
+            zk::ZkContract {
+                state_model: state_model.clone(),
+                initial_state: state_model.compress::<ZkHasher>(&initial_data).unwrap(),
+                log4_deposit_withdraw_capacity: 0,
+                deposit_withdraw_function: zk::ZkVerifierKey::Dummy,
+                functions: vec![zk::ZkVerifierKey::Dummy],
+            },
+            initial_data,
+            0,
+            i + 1,
+        );
+        mempool.insert(tx_delta, TransactionStats { first_seen: 0 });
+    }
+    let draft = ctx
+        .blockchain
+        .draft_block(1, &mempool, &miner, true)?
+        .unwrap();
+    ctx.blockchain.apply_block(&draft.block, false)?;
+    ctx.blockchain.update_states(&draft.patch)?;
+    syncer.blockchain.apply_block(&draft.block, false)?;
+    let all_heights = syncer.blockchain.get_outdated_heights()?;
+    assert_eq!(all_heights.len(), 4);
+
+    let to = hex::encode(syncer.blockchain.get_tip()?.hash());
+    let ctx = Arc::new(RwLock::new(ctx));
+    let req = |outdated_heights| GetStateChunksRequest {
+        outdated_heights,
+        to: to.clone(),
+        chunk: 0,
+    };
+
+    let resp = api::get_state_chunks(Arc::clone(&ctx), req(all_heights.clone())).await?;
+    assert_eq!(resp.patch.patches.len(), 3);
+    assert!(resp.more_available);
+    syncer.blockchain.update_states_partial(&resp.patch)?;
+
+    // The rest is served in the next request
+    let resp = api::get_state_chunks(
+        Arc::clone(&ctx),
+        req(syncer.blockchain.get_outdated_heights()?),
+    )
+    .await?;
+    assert_eq!(resp.patch.patches.len(), 1);
+    assert!(!resp.more_available);
+    syncer.blockchain.update_states(&resp.patch)?;
+    assert!(syncer.blockchain.get_outdated_contracts()?.is_empty());
+
+    // A single contract is still served when it exceeds the byte limit
+    ctx.write().await.opts.max_state_patch_bytes = 1;
+    let resp = api::get_state_chunks(Arc::clone(&ctx), req(all_heights.clone())).await?;
+    assert_eq!(resp.patch.patches.len(), 1);
+    assert!(resp.more_available);
+
+    // Partial patches can't be served on the old endpoint
+    let legacy = |outdated_heights| {
+        api::get_states(
+            Arc::clone(&ctx),
+            GetStatesRequest {
+                outdated_heights,
+                to: to.clone(),
+            },
+        )
+    };
+    assert!(matches!(
+        legacy(all_heights.clone()).await,
+        Err(NodeError::InputError)
+    ));
+    let single = all_heights.into_iter().take(1).collect::<HashMap<_, _>>();
+    assert!(matches!(
+        legacy(single.clone()).await,
+        Err(NodeError::InputError)
+    ));
+    ctx.write().await.opts.max_state_patch_bytes = 1024;
+    assert_eq!(legacy(single).await?.patch.patches.len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_header_stream_serves_the_whole_chain() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    ctx.opts.header_stream_chunk_size = 3;
+    let miner = Wallet::new(Vec::from("MINER"));
+    for i in 0..10 {
+        let draft = ctx
+            .blockchain
+            .draft_block(i + 1, &HashMap::new(), &miner, true)?
+            .unwrap();
+        ctx.blockchain.apply_block(&draft.block, false)?;
+    }
+    let expected = ctx.blockchain.get_headers(0, None)?;
+    assert_eq!(expected.len(), 11);
+    let ctx = Arc::new(RwLock::new(ctx));
+
+    let mut headers = Vec::new();
+    let mut since = Some(0);
+    let mut chunks = 0;
+    while let Some(s) = since {
+        let resp =
+            api::get_header_stream(Arc::clone(&ctx), GetHeaderStreamRequest { since: s }).await?;
+        assert!(resp.headers.len() <= 3);
+        headers.extend(resp.headers);
+        since = resp.next;
+        chunks += 1;
+    }
+    assert_eq!(chunks, 4);
+    assert_eq!(headers, expected);
+    assert!(headers
+        .iter()
+        .enumerate()
+        .all(|(i, h)| h.number == i as u64));
+
+    Ok(())
+}
+
+#[test]
+fn test_hashrate_warning() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let miner = Wallet::new(Vec::from("MINER"));
+    let block_time = blockchain::get_test_blockchain_config().block_time as u32;
+    let apply_with_gap =
+        |ctx: &mut NodeContext<KvStoreChain<RamKvStore>>, gap: u32| -> Result<(), NodeError> {
+            let ts = ctx.blockchain.get_tip()?.proof_of_work.timestamp + gap;
+            let draft = ctx
+                .blockchain
+                .draft_block(ts, &HashMap::new(), &miner, true)?
+                .unwrap();
+            ctx.blockchain.apply_block(&draft.block, false)?;
+            Ok(())
+        };
+
+    // Not enough blocks to tell
+    assert!(!ctx.hashrate_warning()?);
+
+    for _ in 0..5 {
+        apply_with_gap(&mut ctx, block_time)?;
+    }
+    assert!(!ctx.hashrate_warning()?);
+
+    // Gaps keep widening, until the recent average is too slow
+    let mut warned_at = None;
+    for i in 1..=10 {
+        apply_with_gap(&mut ctx, block_time * i)?;
+        if ctx.hashrate_warning()? {
+            warned_at = Some(i);
+            break;
+        }
+    }
+    assert!(matches!(warned_at, Some(i) if i > 4));
+
+    Ok(())
+}
+
+#[test]
+fn test_self_test_passes() -> Result<(), NodeError> {
+    let ctx = harness::test_context();
+    let pow_key = ctx.blockchain.pow_key(ctx.blockchain.get_height()?)?;
+    assert!(self_test::self_test(&pow_key).is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_stale_peer_info_is_not_selected() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let ttl = ctx.opts.peer_info_ttl;
+
+    let strong = PeerAddress(SocketAddr::from(([127, 0, 0, 1], 3040)));
+    let weak = PeerAddress(SocketAddr::from(([127, 0, 0, 1], 3041)));
+    for addr in [strong, weak] {
+        ctx.peers.insert(
+            addr,
+            Peer {
+                pub_key: None,
+                address: addr,
+                punished_until: 0,
+                info: None,
+            },
+        );
+    }
+    let strong_info = PeerInfo {
+        height: 9,
+        power: 90,
+    };
+    let weak_info = PeerInfo {
+        height: 5,
+        power: 50,
+    };
+
+    ctx.update_peer_info(strong, strong_info.clone(), 1000);
+    ctx.update_peer_info(weak, weak_info.clone(), 1000 + ttl / 2);
+    assert_eq!(
+        ctx.most_powerful_peer(1000).map(|p| p.address),
+        Some(strong)
+    );
+
+    // The claim of the strong peer is too old to be trusted
+    assert_eq!(
+        ctx.most_powerful_peer(1000 + ttl).map(|p| p.address),
+        Some(weak)
+    );
+
+    // Selected again once refreshed
+    ctx.update_peer_info(strong, strong_info, 1000 + ttl);
+    assert_eq!(
+        ctx.most_powerful_peer(1000 + ttl).map(|p| p.address),
+        Some(strong)
+    );
+
+    // Nothing is selected when all the infos are stale
+    assert!(ctx.most_powerful_peer(1000 + 3 * ttl).is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_transaction_status() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let miner = Wallet::new(Vec::from("MINER"));
+    let alice = Wallet::new(Vec::from("ABC"));
+    let bob = Wallet::new(Vec::from("CBA"));
+    let tx = alice.create_transaction(bob.get_address(), 100, 0, 1);
+    let hash = hex::encode(tx.tx.hash());
+    ctx.add_transaction(tx, 0)?;
+    let ctx = Arc::new(RwLock::new(ctx));
+
+    let status = |hash: String| {
+        api::get_transaction_status(Arc::clone(&ctx), GetTransactionStatusRequest { hash })
+    };
+    assert_eq!(
+        status(hash.clone()).await?,
+        GetTransactionStatusResponse::Pending
+    );
+
+    {
+        let mut ctx = ctx.write().await;
+        let mempool = ctx.mempool.clone();
+        let draft = ctx
+            .blockchain
+            .draft_block(1, &mempool, &miner, true)?
+            .unwrap();
+        ctx.blockchain.apply_block(&draft.block, false)?;
+        ctx.cleanup_mempools()?;
+        assert!(ctx.mempool.is_empty());
+    }
+    assert_eq!(
+        status(hash).await?,
+        GetTransactionStatusResponse::Confirmed { height: 1 }
+    );
+
+    assert_eq!(
+        status(hex::encode([0u8; 32])).await?,
+        GetTransactionStatusResponse::Unknown
+    );
+    assert!(matches!(
+        status("not hex".into()).await,
+        Err(NodeError::InputError)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_peers_serving_lighter_chains_are_punished() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    ctx.opts.incorrect_power_punish = 10;
+    ctx.opts.max_punish = 100;
+    let liar = PeerAddress(SocketAddr::from(([127, 0, 0, 1], 3031)));
+    ctx.peers.insert(
+        liar,
+        Peer {
+            pub_key: None,
+            address: liar,
+            punished_until: 0,
+            info: None,
+        },
+    );
+
+    let miner = Wallet::new(Vec::from("MINER"));
+    for ts in 1..3 {
+        let draft = ctx
+            .blockchain
+            .draft_block(ts, &HashMap::new(), &miner, true)?
+            .unwrap();
+        ctx.blockchain.apply_block(&draft.block, false)?;
+    }
+    let tip = ctx.blockchain.get_header(2)?;
+    let power = ctx.blockchain.get_power()?;
+
+    // The peer replaces our last two blocks with a single one
+    let lighter = {
+        let mut fork = ctx.blockchain.fork_on_ram();
+        fork.rollback()?;
+        fork.rollback()?;
+        fork.draft_block(5, &HashMap::new(), &miner, true)?
+            .unwrap()
+            .block
+    };
+    let err = ctx.extend_chain(1, &[lighter]).unwrap_err();
+    assert!(matches!(err, BlockchainError::PowerNotIncreased));
+    ctx.report_sync_error(liar, &err.into(), 100);
+
+    // Nothing is changed
+    assert_eq!(ctx.blockchain.get_height()?, 3);
+    assert_eq!(ctx.blockchain.get_header(2)?, tip);
+    assert_eq!(ctx.blockchain.get_power()?, power);
+
+    assert!(ctx.peers[&liar].is_punished());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_outdated_contracts() -> Result<(), NodeError> {
+    let mut ctx = harness::test_context();
+    let miner = Wallet::new(Vec::from("MINER"));
+
+    // Apply a block calling the contract, without its state patch
+    let mempool = [(sample_contract_call(1), TransactionStats { first_seen: 0 })]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let draft = ctx
+        .blockchain
+        .draft_block(1, &mempool, &miner, true)?
+        .unwrap();
+    ctx.blockchain.apply_block(&draft.block, false)?;
+    let cid = blockchain::get_test_mpn_contract_id();
+    let state = ctx.blockchain.get_contract_account(cid)?.compressed_state;
+    let ctx = Arc::new(RwLock::new(ctx));
+
+    let resp =
+        api::get_outdated_contracts(Arc::clone(&ctx), GetOutdatedContractsRequest {}).await?;
+    assert_eq!(
+        resp.outdated_contracts,
+        [(cid, state)].into_iter().collect::<HashMap<_, _>>()
+    );
+
+    // Supplying the full state brings the contract up to date
+    ctx.write().await.blockchain.update_states(&draft.patch)?;
+    let resp = api::get_outdated_contracts(ctx, GetOutdatedContractsRequest {}).await?;
+    assert!(resp.outdated_contracts.is_empty());
+
+    Ok(())
+}
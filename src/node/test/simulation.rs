@@ -8,6 +8,7 @@ use crate::crypto::SignatureScheme;
 use crate::db::RamKvStore;
 use crate::wallet::Wallet;
 
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tokio::time::{sleep, Duration};
@@ -34,8 +35,10 @@ fn create_test_node(
     let chain = KvStoreChain::new(RamKvStore::new(), opts.config).unwrap();
     let (inc_send, inc_recv) = mpsc::unbounded_channel::<NodeRequest>();
     let (out_send, out_recv) = mpsc::unbounded_channel::<NodeRequest>();
+    let node_opts = config::node::get_test_node_options();
+    let connections = Arc::new(ConnectionLimiter::new(node_opts.max_connections));
     let node = node_create(
-        config::node::get_test_node_options(),
+        node_opts,
         addr,
         opts.priv_key.clone(),
         opts.bootstrap
@@ -45,6 +48,7 @@ fn create_test_node(
         chain,
         opts.timestamp_offset,
         opts.wallet,
+        connections,
         inc_recv,
         out_send,
     );
@@ -83,41 +87,60 @@ async fn route(
         );
         let rule = rules.iter().find(|r| r.applies(&req.body, src, dst));
 
+        let mut delay = None;
         if let Some(rule) = rule {
             match rule.action {
                 Action::Drop => {
                     continue;
                 }
                 Action::Delay(dur) => {
-                    sleep(dur).await;
+                    delay = Some(dur);
                 }
                 Action::Redirect(port) => {
                     dst = PeerAddress(SocketAddr::from(([127, 0, 0, 1], port)));
                 }
+                Action::Count(ref counter) => {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                }
             }
         }
 
-        let (resp_snd, mut resp_rcv) = mpsc::channel::<Result<Response<Body>, NodeError>>(1);
-        let inc_req = NodeRequest {
-            socket_addr: None,
-            body: req.body,
-            resp: resp_snd,
-        };
-        if incs[&dst].sender.chan.send(inc_req).is_ok() {
-            if let Some(answer) = resp_rcv.recv().await {
-                let _ = req.resp.send(answer).await;
-            }
+        let inc = incs[&dst].clone();
+        if let Some(dur) = delay {
+            // A slow link shouldn't hold back the other requests of the node
+            tokio::spawn(async move {
+                sleep(dur).await;
+                forward(inc, req).await;
+            });
+        } else {
+            forward(inc, req).await;
         }
     }
 
     Ok(())
 }
 
+async fn forward(inc: BazukaClient, req: NodeRequest) {
+    let (resp_snd, mut resp_rcv) = mpsc::channel::<Result<Response<Body>, NodeError>>(1);
+    let inc_req = NodeRequest {
+        socket_addr: None,
+        body: req.body,
+        resp: resp_snd,
+    };
+    if inc.sender.chan.send(inc_req).is_ok() {
+        if let Some(answer) = resp_rcv.recv().await {
+            let _ = req.resp.send(answer).await;
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum Action {
     Drop,
     Delay(Duration),
     Redirect(u16),
+    // Forwards the request normally, counting the requests matching the rule
+    Count(Arc<AtomicUsize>),
 }
 
 #[derive(Clone)]
@@ -179,6 +202,7 @@ pub fn test_network(
         .into_iter()
         .map(|node_opts| create_test_node(node_opts))
         .unzip();
+    let chans = nodes.iter().map(|n| n.incoming.clone()).collect::<Vec<_>>();
     let incs: HashMap<_, _> = nodes.iter().map(|n| (n.addr, n.incoming.clone())).collect();
     let route_futs = nodes
         .into_iter()
@@ -188,6 +212,6 @@ pub fn test_network(
     (
         futures::future::try_join_all(node_futs),
         futures::future::try_join_all(route_futs),
-        incs.into_values().collect(),
+        chans,
     )
 }
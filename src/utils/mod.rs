@@ -20,8 +20,15 @@ pub fn calc_pow_difficulty(
     last_pow: &ProofOfWork,
     prev_pow: &ProofOfWork,
 ) -> u32 {
-    let time_delta = last_pow.timestamp - prev_pow.timestamp;
-    let avg_block_time = time_delta / (diff_calc_interval - 1) as u32;
+    let num_intervals = std::cmp::max(diff_calc_interval - 1, 1) as u32;
+    // The median rule allows equal (or even decreasing) timestamps, keep the delta
+    // in a sane range. Beyond the upper bound the change is clamped anyway.
+    let max_time_delta = std::cmp::max(block_time as u32 * num_intervals * 2, 1);
+    let time_delta = last_pow
+        .timestamp
+        .saturating_sub(prev_pow.timestamp)
+        .clamp(1, max_time_delta);
+    let avg_block_time = std::cmp::max(time_delta / num_intervals, 1);
     let diff_change = (block_time as f32 / avg_block_time as f32).clamp(0.5f32, 2f32);
     let new_diff = rust_randomx::Difficulty::new(last_pow.target).scale(diff_change);
     new_diff.to_u32()
@@ -52,6 +52,11 @@ impl Wallet {
             state_delta: None,
         }
     }
+    // Replaces a pending transaction of the same nonce with a self-send of nothing,
+    // only accepted when paying a higher fee than the canceled transaction
+    pub fn create_cancel(&self, nonce: u32, fee: Money) -> TransactionAndDelta {
+        self.create_transaction(self.get_address(), 0, fee, nonce)
+    }
     pub fn create_contract(
         &self,
         contract: zk::ZkContract,
@@ -61,7 +66,35 @@ impl Wallet {
     ) -> TransactionAndDelta {
         let mut tx = Transaction {
             src: self.get_address(),
-            data: TransactionData::CreateContract { contract },
+            data: TransactionData::CreateContract {
+                contract: Box::new(contract),
+            },
+            nonce,
+            fee,
+            sig: Signature::Unsigned,
+        };
+        self.sign(&mut tx);
+        TransactionAndDelta {
+            tx,
+            state_delta: Some(initial_state.as_delta()),
+        }
+    }
+
+    // Only the owner will be able to update (Or pause) the contract
+    pub fn create_owned_contract(
+        &self,
+        contract: zk::ZkContract,
+        owner: Address,
+        initial_state: zk::ZkDataPairs,
+        fee: Money,
+        nonce: u32,
+    ) -> TransactionAndDelta {
+        let mut tx = Transaction {
+            src: self.get_address(),
+            data: TransactionData::CreateOwnedContract {
+                contract: Box::new(contract),
+                owner,
+            },
             nonce,
             fee,
             sig: Signature::Unsigned,
@@ -73,6 +106,30 @@ impl Wallet {
         }
     }
 
+    pub fn pause_contract(
+        &self,
+        contract_id: ContractId,
+        paused: bool,
+        fee: Money,
+        nonce: u32,
+    ) -> TransactionAndDelta {
+        let mut tx = Transaction {
+            src: self.get_address(),
+            data: TransactionData::PauseContract {
+                contract_id,
+                paused,
+            },
+            nonce,
+            fee,
+            sig: Signature::Unsigned,
+        };
+        self.sign(&mut tx);
+        TransactionAndDelta {
+            tx,
+            state_delta: None,
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn call_function(
         &self,
@@ -28,6 +28,13 @@ pub trait ZkHasher: Clone {
     fn hash(vals: &[ZkScalar]) -> ZkScalar;
 }
 
+// The aux-data of a function call is the index of the function, binding the proof
+// to it, so that a proof can't be replayed against another function of the same
+// contract. (Function 0 keeps the aux-data of zero)
+pub fn function_call_aux_data(function_id: u32) -> ZkCompressedState {
+    ZkCompressedState::new(ZkScalar::from(function_id as u64), 0)
+}
+
 pub fn check_proof(
     vk: &ZkVerifierKey,
     prev_state: &ZkCompressedState,
@@ -110,9 +117,30 @@ impl TryInto<u64> for ZkScalar {
 pub enum ZkStatePatch {
     Full(ZkState),
     Delta(ZkDeltaPairs),
+    // A full-state too big for a single response, transferred in several parts
+    Chunks(Vec<ZkStateChunk>),
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+impl ZkStatePatch {
+    pub fn size(&self) -> usize {
+        match self {
+            ZkStatePatch::Full(full) => full.size(),
+            ZkStatePatch::Delta(delta) => delta.size() as usize,
+            ZkStatePatch::Chunks(chunks) => chunks.iter().map(|c| c.state.size()).sum(),
+        }
+    }
+}
+
+// A range of the (locator-sorted) data of a full-state. Rollbacks are carried by
+// the first chunk.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ZkStateChunk {
+    pub index: usize,
+    pub total: usize,
+    pub state: ZkState,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
 pub enum ZkStateModel {
     // Allocate 1
     Scalar,
@@ -139,7 +167,10 @@ impl ZkStateModel {
         for l in locator.0.iter() {
             match curr {
                 ZkStateModel::Struct { field_types } => {
-                    curr = field_types[*l as usize].clone();
+                    curr = field_types
+                        .get(*l as usize)
+                        .ok_or(ZkLocatorError::InvalidLocator)?
+                        .clone();
                 }
                 ZkStateModel::List {
                     item_type,
@@ -159,6 +190,9 @@ impl ZkStateModel {
         Ok(curr)
     }
 
+    // The root is the plain tree root, not bound to the model. The circuits (And the
+    // already deployed MPN proofs) prove transitions of plain tree roots, binding the
+    // model into the root needs new circuits first.
     pub fn compress<H: ZkHasher>(
         &self,
         data: &ZkDataPairs,
@@ -295,6 +329,14 @@ pub struct ZkState {
 }
 
 impl ZkState {
+    pub fn size(&self) -> usize {
+        self.data.size()
+            + self
+                .rollbacks
+                .iter()
+                .map(|r| r.size() as usize)
+                .sum::<usize>()
+    }
     pub fn push_delta(&mut self, delta: &ZkDeltaPairs) {
         let mut rollback = ZkDeltaPairs::default();
         for loc in delta.0.keys() {
@@ -314,6 +356,58 @@ impl ZkState {
             }
         }
     }
+    pub fn chunks(&self, chunk_size: usize) -> Vec<ZkStateChunk> {
+        let mut locs = self.data.0.keys().collect::<Vec<_>>();
+        locs.sort_by(|a, b| a.0.cmp(&b.0));
+        let ranges = locs.chunks(chunk_size.max(1)).collect::<Vec<_>>();
+        let total = ranges.len().max(1);
+        (0..total)
+            .map(|index| ZkStateChunk {
+                index,
+                total,
+                state: ZkState {
+                    data: ZkDataPairs(
+                        ranges
+                            .get(index)
+                            .map(|range| {
+                                range
+                                    .iter()
+                                    .map(|loc| ((*loc).clone(), self.data.0[*loc]))
+                                    .collect()
+                            })
+                            .unwrap_or_default(),
+                    ),
+                    rollbacks: if index == 0 {
+                        self.rollbacks.clone()
+                    } else {
+                        Vec::new()
+                    },
+                },
+            })
+            .collect()
+    }
+    // None if any of the chunks is missing or repeated
+    pub fn from_chunks(chunks: &[ZkStateChunk]) -> Option<ZkState> {
+        let total = chunks.first()?.total;
+        let mut sorted = chunks.iter().collect::<Vec<_>>();
+        sorted.sort_by_key(|c| c.index);
+        if sorted.len() != total
+            || sorted
+                .iter()
+                .enumerate()
+                .any(|(i, c)| c.index != i || c.total != total)
+        {
+            return None;
+        }
+        let mut state = ZkState {
+            data: Default::default(),
+            rollbacks: sorted[0].state.rollbacks.clone(),
+        };
+        for chunk in sorted {
+            state.data.0.extend(chunk.state.data.0.clone());
+        }
+        Some(state)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
@@ -338,6 +432,10 @@ impl ZkCompressedState {
     pub fn size(&self) -> u32 {
         self.state_size
     }
+    // Storage footprint of the full state, assuming a scalar per non-empty entry
+    pub fn byte_size(&self) -> u64 {
+        self.state_size as u64 * std::mem::size_of::<ZkScalar>() as u64
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
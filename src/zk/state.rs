@@ -2,7 +2,7 @@ use thiserror::Error;
 
 use super::*;
 use crate::core::ContractId;
-use crate::db::{KvStore, KvStoreError, RamKvStore, StringKey, WriteOp};
+use crate::db::{KvStore, KvStoreError, KvStoreTransaction, RamKvStore, StringKey, WriteOp};
 use std::collections::HashMap;
 use std::str::FromStr;
 
@@ -82,6 +82,16 @@ impl<H: ZkHasher> ZkStateBuilder<H> {
     }
 }
 
+// The size of a state is the number of its non-empty scalars
+fn size_after_change(size: u32, prev: ZkScalar, next: ZkScalar) -> u32 {
+    let zero = ZkScalar::default();
+    match (prev == zero, next == zero) {
+        (true, false) => size + 1,
+        (false, true) => size - 1,
+        _ => size,
+    }
+}
+
 impl<H: ZkHasher> KvStoreStateManager<H> {
     pub fn delete_contract<K: KvStore>(
         db: &mut K,
@@ -181,11 +191,15 @@ impl<H: ZkHasher> KvStoreStateManager<H> {
         } else {
             return Ok(None);
         };
-        let mut state_hash = Self::root(db, id)?.state_hash;
+        let mut state_hash = root.state_hash;
+        let mut state_size = root.state_size;
         for (k, v) in rollback_patch.0 {
-            state_hash = Self::set_data(db, id, k, v.unwrap_or_default())?;
+            let prev_val = Self::get_data(db, id, &k)?;
+            let val = v.unwrap_or_default();
+            state_size = size_after_change(state_size, prev_val, val);
+            state_hash = Self::set_data(db, id, k, val)?;
         }
-        let new_state = ZkCompressedState::new(state_hash, root.state_size);
+        let new_state = ZkCompressedState::new(state_hash, state_size);
         db.update(&[
             WriteOp::Remove(rollback_key),
             WriteOp::Put(format!("{}_compressed", id).into(), new_state.into()),
@@ -267,16 +281,19 @@ impl<H: ZkHasher> KvStoreStateManager<H> {
         }
 
         let mut state_hash = contract_type.compress_default::<H>();
+        let mut state_size = 0;
         for (k, v) in state.data.0.iter() {
+            state_size = size_after_change(state_size, ZkScalar::default(), *v);
             state_hash = Self::set_data(db, id, k.clone(), *v)?;
         }
+        let final_state_size = state_size;
 
         db.update(&[
             WriteOp::Put(
                 format!("{}_compressed", id).into(),
                 ZkCompressedState {
                     state_hash,
-                    state_size: 0,
+                    state_size,
                 }
                 .into(),
             ),
@@ -288,7 +305,10 @@ impl<H: ZkHasher> KvStoreStateManager<H> {
         for (i, rollback) in state.rollbacks.iter().enumerate() {
             let mut state_hash = Self::root(db, id)?.state_hash;
             for (k, v) in &rollback.0 {
-                state_hash = Self::set_data(db, id, k.clone(), v.unwrap_or_default())?;
+                let prev_val = Self::get_data(db, id, k)?;
+                let val = v.unwrap_or_default();
+                state_size = size_after_change(state_size, prev_val, val);
+                state_hash = Self::set_data(db, id, k.clone(), val)?;
             }
             db.update(&[WriteOp::Put(
                 format!("{}_rollback_{}", id, height - 1 - i as u64).into(),
@@ -296,14 +316,14 @@ impl<H: ZkHasher> KvStoreStateManager<H> {
             )])?;
             rollback_results.push(ZkCompressedState {
                 state_hash,
-                state_size: 0,
+                state_size,
             });
         }
 
         Ok((
             ZkCompressedState {
                 state_hash,
-                state_size: 0,
+                state_size: final_state_size,
             },
             rollback_results,
         ))
@@ -322,27 +342,24 @@ impl<H: ZkHasher> KvStoreStateManager<H> {
         for (k, v) in &patch.0 {
             let prev_val = Self::get_data(&fork, id, k)?;
             rollback_patch.0.insert(k.clone(), Some(prev_val)); // Or None if default
+            root.state_size = size_after_change(root.state_size, prev_val, v.unwrap_or_default());
             root.state_hash = Self::set_data(&mut fork, id, k.clone(), v.unwrap_or_default())?;
         }
-        let mut ops = fork.to_ops();
-        ops.push(WriteOp::Put(
+        let mut txn = KvStoreTransaction::new();
+        txn.extend(fork.to_ops());
+        txn.put(
             format!("{}_compressed", id).into(),
             ZkCompressedState::new(root.state_hash, root.state_size).into(),
-        ));
-        ops.push(WriteOp::Put(
+        );
+        txn.put(
             format!("{}_rollback_{}", id, height).into(),
             (&rollback_patch).into(),
-        ));
-        ops.push(WriteOp::Put(
-            format!("{}_height", id).into(),
-            (height + 1).into(),
-        ));
+        );
+        txn.put(format!("{}_height", id).into(), (height + 1).into());
         if height >= MAX_ROLLBACKS {
-            ops.push(WriteOp::Remove(
-                format!("{}_rollback_{}", id, height - MAX_ROLLBACKS).into(),
-            ));
+            txn.remove(format!("{}_rollback_{}", id, height - MAX_ROLLBACKS).into());
         }
-        db.update(&ops)?;
+        txn.commit(db)?;
         Ok(())
     }
 
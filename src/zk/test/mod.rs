@@ -38,6 +38,16 @@ fn test_u64_conversion() {
     assert!(u64max_plus_1.is_err());
 }
 
+#[test]
+fn test_function_call_aux_data() {
+    // Existing single-function contracts are not affected
+    assert_eq!(function_call_aux_data(0), ZkCompressedState::default());
+
+    // A proof for one function verifies different public inputs than another
+    assert_ne!(function_call_aux_data(0), function_call_aux_data(1));
+    assert_ne!(function_call_aux_data(1), function_call_aux_data(2));
+}
+
 #[test]
 fn test_zk_list_membership_proof() {
     let model = ZkStateModel::Struct {